@@ -4,12 +4,49 @@ use crate::ANIMATION_TILESET;
 use crate::gfx::gl_support::BlendMode;
 use crate::ui::*;
 
+use serde::{Serialize, Deserialize};
+
+
 pub trait Animated {
     fn update(&self, _dt: f32, _mob: &mut MapObject) {
 
     }
+
+    /**
+     * @return The savable description of this animation, None if it can't be saved.
+     */
+    fn to_data(&self) -> Option<AnimationData> {
+        None
+    }
 }
 
+
+/**
+ * Animations are trait objects in the map, this is what is written
+ * to savegames instead.
+ */
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub enum AnimationData {
+    Spin {
+        speed: f32,
+    },
+    Removal {
+        timer_start: f32,
+        time_limit: f32,
+    },
+}
+
+
+impl AnimationData {
+    pub fn to_animation(&self) -> Box<dyn Animated> {
+        match self {
+            AnimationData::Spin { speed } => Box::new(SpinAnimation::new(*speed)),
+            AnimationData::Removal { timer_start, time_limit } => Box::new(RemovalAnimation::new(*timer_start, *time_limit)),
+        }
+    }
+}
+
+
 pub struct NoAnimation {
 
 }
@@ -38,6 +75,10 @@ impl Animated for SpinAnimation {
         let frame = (mob.animation_timer * self.speed) as usize;
         mob.visual.current_image_id = mob.visual.base_image_id + (frame % mob.visual.directions);
     }
+
+    fn to_data(&self) -> Option<AnimationData> {
+        Some(AnimationData::Spin { speed: self.speed })
+    }
 }
 
 
@@ -79,4 +120,8 @@ impl Animated for RemovalAnimation {
         }
     }
 
+    fn to_data(&self) -> Option<AnimationData> {
+        Some(AnimationData::Removal { timer_start: self.timer_start, time_limit: self.time_limit })
+    }
+
 }
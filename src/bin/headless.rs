@@ -0,0 +1,26 @@
+use std::path::Path;
+
+use rust_and_dust_collector::headless::run_headless;
+use rust_and_dust_collector::headless::run_replay;
+use rust_and_dust_collector::seed_from_args;
+
+
+/**
+ * Runs the game simulation without window or audio device.
+ *
+ * Usage: headless [seconds] [--seed number]
+ *        headless --replay file
+ */
+fn main() {
+
+    let args: Vec<String> = std::env::args().collect();
+
+    if args.len() > 2 && args[1] == "--replay" {
+        run_replay(Path::new(&args[2]));
+        return;
+    }
+
+    // optional run time in seconds, defaults to one minute
+    let seconds = if args.len() > 1 {args[1].parse::<f32>().unwrap_or(60.0)} else {60.0};
+    run_headless(seconds, seed_from_args(&args));
+}
@@ -0,0 +1,10 @@
+use rust_and_dust_collector::validate::run_validation;
+
+
+/**
+ * Cross-checks all game data files and reports broken references.
+ * Exits with code 1 if there were problems.
+ */
+fn main() {
+    std::process::exit(run_validation());
+}
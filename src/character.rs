@@ -0,0 +1,195 @@
+use serde::{Serialize, Deserialize};
+
+use crate::item::Attribute;
+use crate::damage::MAX_RESISTANCE;
+
+
+// experience for a kill, per level of the killed creature
+pub const EXPERIENCE_PER_CREATURE_LEVEL: u32 = 20;
+
+// each level needs this much more experience than the one before
+pub const EXPERIENCE_STEP: u32 = 100;
+
+pub const POINTS_PER_LEVEL: u32 = 3;
+pub const HIT_POINTS_PER_LEVEL: i32 = 10;
+
+// what one attribute point is worth, in percent
+pub const AGILITY_DAMAGE: i32 = 3;
+pub const ARMOR_DEFENCE: i32 = 2;
+pub const SPEED_BONUS: i32 = 2;
+
+
+/**
+ * The progress of the player: experience, level and the attribute points
+ * bought with the levels. Only the player creature has one.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Character {
+    pub experience: u32,
+    pub level: u32,
+
+    // earned with level ups, but not spent yet
+    pub unspent_points: u32,
+
+    pub agility: u32,
+    pub armor: u32,
+    pub speed: u32,
+}
+
+
+impl Default for Character {
+    fn default() -> Character {
+        Character::new()
+    }
+}
+
+
+impl Character {
+
+    pub fn new() -> Character {
+        Character {
+            experience: 0,
+            level: 1,
+            unspent_points: 0,
+            agility: 0,
+            armor: 0,
+            speed: 0,
+        }
+    }
+
+
+    /**
+     * Raises the level as often as the experience allows.
+     *
+     * @return The number of levels gained
+     */
+    pub fn add_experience(&mut self, amount: u32) -> u32 {
+        self.experience += amount;
+
+        let mut levels = 0;
+
+        while self.experience >= experience_for_level(self.level + 1) {
+            self.level += 1;
+            self.unspent_points += POINTS_PER_LEVEL;
+            levels += 1;
+        }
+
+        levels
+    }
+
+
+    /**
+     * Spends an unspent point on Agility, Armor or Speed.
+     *
+     * @return false if there was no point left, or the attribute can't be raised
+     */
+    pub fn spend_point(&mut self, attribute: Attribute) -> bool {
+        if self.unspent_points == 0 {
+            return false;
+        }
+
+        match attribute {
+            Attribute::Agility => self.agility += 1,
+            Attribute::Armor => self.armor += 1,
+            Attribute::Speed => self.speed += 1,
+            _ => return false,
+        }
+
+        self.unspent_points -= 1;
+        true
+    }
+
+
+    pub fn points(&self, attribute: Attribute) -> u32 {
+        match attribute {
+            Attribute::Agility => self.agility,
+            Attribute::Armor => self.armor,
+            Attribute::Speed => self.speed,
+            _ => 0,
+        }
+    }
+
+
+    /**
+     * Agility makes the player's projectiles hit harder.
+     */
+    pub fn damage_bonus(&self, damage: i32) -> i32 {
+        damage * (100 + AGILITY_DAMAGE * self.agility as i32) / 100
+    }
+
+
+    /**
+     * Armor blocks a share of physical hits, in percent.
+     */
+    pub fn defence(&self) -> i32 {
+        (ARMOR_DEFENCE * self.armor as i32).min(MAX_RESISTANCE)
+    }
+
+
+    /**
+     * Speed makes the player walk faster.
+     */
+    pub fn speed_factor(&self) -> f32 {
+        1.0 + (SPEED_BONUS * self.speed as i32) as f32 / 100.0
+    }
+}
+
+
+/**
+ * The total experience needed to reach a level. Level 1 needs none,
+ * level 2 needs 100, level 3 needs 300 and so on.
+ */
+pub fn experience_for_level(level: u32) -> u32 {
+    let steps = level.saturating_sub(1);
+    EXPERIENCE_STEP * steps * (steps + 1) / 2
+}
+
+
+/**
+ * Killing creatures far below the player's level is worth less, down to
+ * a fifth of the full experience.
+ */
+pub fn experience_for_kill(creature_level: u32, player_level: u32) -> u32 {
+    let full = EXPERIENCE_PER_CREATURE_LEVEL * creature_level.max(1);
+    let difference = player_level.saturating_sub(creature_level).min(4);
+
+    full * (5 - difference) / 5
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn test_levels_and_points() {
+        assert_eq!(experience_for_level(1), 0);
+        assert_eq!(experience_for_level(2), 100);
+        assert_eq!(experience_for_level(3), 300);
+
+        let mut character = Character::new();
+
+        assert_eq!(character.add_experience(99), 0);
+        assert_eq!(character.add_experience(250), 2);
+        assert_eq!(character.level, 3);
+        assert_eq!(character.unspent_points, 2 * POINTS_PER_LEVEL);
+
+        assert!(character.spend_point(Attribute::Agility));
+        assert!(character.spend_point(Attribute::Armor));
+        assert!(!character.spend_point(Attribute::ResFire));
+        assert_eq!(character.unspent_points, 2 * POINTS_PER_LEVEL - 2);
+
+        assert_eq!(character.damage_bonus(100), 100 + AGILITY_DAMAGE);
+        assert_eq!(character.defence(), ARMOR_DEFENCE);
+
+        while character.spend_point(Attribute::Speed) {}
+        assert_eq!(character.points(Attribute::Speed), 2 * POINTS_PER_LEVEL - 2);
+        assert!(character.speed_factor() > 1.0);
+
+        // low creatures are worth less, but never nothing
+        assert_eq!(experience_for_kill(2, 1), 2 * EXPERIENCE_PER_CREATURE_LEVEL);
+        assert!(experience_for_kill(1, 3) < experience_for_kill(1, 1));
+        assert!(experience_for_kill(1, 30) > 0);
+    }
+}
@@ -0,0 +1,105 @@
+use vecmath::Vector2;
+
+use crate::damage::Element;
+
+
+// seconds a text stays visible
+pub const COMBAT_TEXT_TIME: f32 = 1.2;
+
+// texts rise this many screen pixels per second
+pub const COMBAT_TEXT_RISE: f32 = 40.0;
+
+
+/**
+ * A short text over the place of a hit, like the damage it did.
+ * It rises and fades, and is removed when its time is over.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct CombatText {
+    pub text: String,
+    pub color: [f32; 4],
+
+    // map position of the hit, and how high above the ground the text starts
+    pub position: Vector2<f32>,
+    pub z_off: f32,
+
+    pub age: f32,
+}
+
+
+impl CombatText {
+
+    /**
+     * The damage a hit did, in the color of its element.
+     *
+     * @param shocked The target was shocked and took more damage than usual
+     */
+    pub fn damage(position: Vector2<f32>, z_off: f32, amount: i32, element: Element, shocked: bool) -> CombatText {
+        let text = if shocked {format!("Shocked {}", amount)} else {amount.to_string()};
+        CombatText::new(position, z_off, text, element_color(element))
+    }
+
+
+    /**
+     * Resistances or armor of the target blocked all damage.
+     */
+    pub fn resisted(position: Vector2<f32>, z_off: f32) -> CombatText {
+        CombatText::new(position, z_off, "Resisted".to_string(), [0.7, 0.7, 0.7, 1.0])
+    }
+
+
+    /**
+     * The player reached a new level.
+     */
+    pub fn level_up(position: Vector2<f32>, level: u32) -> CombatText {
+        CombatText::new(position, 60.0, format!("Level {}", level), [1.0, 0.85, 0.3, 1.0])
+    }
+
+
+    fn new(position: Vector2<f32>, z_off: f32, text: String, color: [f32; 4]) -> CombatText {
+        CombatText {
+            text,
+            color,
+            position,
+            z_off,
+            age: 0.0,
+        }
+    }
+
+
+    /**
+     * @return false once the text has faded out
+     */
+    pub fn update(&mut self, dt: f32) -> bool {
+        self.age += dt;
+        self.age < COMBAT_TEXT_TIME
+    }
+
+
+    /**
+     * How far the text has risen, in screen pixels.
+     */
+    pub fn rise(&self) -> f32 {
+        self.age * COMBAT_TEXT_RISE
+    }
+
+
+    /**
+     * The color to draw the text with now, it fades out in the second half of its time.
+     */
+    pub fn faded_color(&self) -> [f32; 4] {
+        let half = COMBAT_TEXT_TIME * 0.5;
+        let alpha = (1.0 - (self.age - half).max(0.0) / half).clamp(0.0, 1.0);
+        [self.color[0], self.color[1], self.color[2], self.color[3] * alpha]
+    }
+}
+
+
+pub fn element_color(element: Element) -> [f32; 4] {
+    match element {
+        Element::Physical => [1.0, 1.0, 1.0, 1.0],
+        Element::Fire => [1.0, 0.55, 0.2, 1.0],
+        Element::Cold => [0.5, 0.8, 1.0, 1.0],
+        Element::Lightning => [1.0, 1.0, 0.4, 1.0],
+    }
+}
@@ -1,7 +1,37 @@
 use std::collections::HashMap;
 
-use crate::read_lines;
+use serde::{Serialize, Deserialize};
+
+use crate::loader::CsvFile;
+use crate::loader::LoaderError;
+use crate::loader::CsvRow;
+use crate::tile_catalog::TileCatalog;
+use crate::tile_catalog::read_tile_catalog;
 use crate::gfx::gl_support::BlendMode;
+use crate::status_effect::StatusEffects;
+use crate::character::Character;
+use crate::creature_ai::AiConfig;
+use crate::damage::Element;
+use crate::damage::Resistances;
+use crate::damage::parse_element;
+
+
+/**
+ * Used for creatures which don't define their own collision radius.
+ */
+pub const DEFAULT_COLLISION_RADIUS: f32 = 16.0;
+
+
+/**
+ * Creatures are drawn at this scale on the map.
+ */
+pub const CREATURE_SCALE: f32 = 0.5;
+
+
+/**
+ * No hit box may be larger, projectiles don't look farther for targets.
+ */
+pub const MAX_HIT_RADIUS: f32 = 128.0;
 
 
 pub struct CreatureFactory 
@@ -17,8 +47,21 @@ pub struct CreaturePrototype
     pub speed: f32,
     pub min_hp: i32,
     pub max_hp: i32,
+
+    // stronger creatures are worth more experience
+    pub level: u32,
     pub projectile_spawn_distance: f32,
 
+    // how close the creature can get to walls
+    pub collision_radius: f32,
+
+    pub hit_box: HitBox,
+    pub ai: AiConfig,
+    pub resistances: Resistances,
+    pub attacks: Vec<CreatureAttack>,
+
+    // the image is multiplied with this, status effects tint it further
+    pub color: [f32; 4],
     pub blend_mode: BlendMode,
     pub movement_function: fn(f32) -> f32,
     pub animation_type: CreatureAnimation,
@@ -26,21 +69,73 @@ pub struct CreaturePrototype
 
 
 #[allow(dead_code)]
+#[derive(Serialize, Deserialize)]
 pub struct Creature 
 {
+    pub key: String,              // for prototype lookup
     pub base_tile_id: usize,
     pub frames: usize,
     pub base_speed: f32,
     pub hit_points: i32,
+
+    // older saves don't have this, restore() takes it from the prototype
+    #[serde(default)]
+    pub max_hit_points: i32,
+
+    #[serde(default)]
+    pub effects: StatusEffects,
+
+    // experience and level, only the player has this
+    #[serde(default)]
+    pub character: Option<Character>,
+
     pub projectile_spawn_distance: f32,
 
+    #[serde(default = "default_collision_radius")]
+    pub collision_radius: f32,
+
+    // tuning data, restored from the prototype after loading
+    #[serde(skip)]
+    pub level: u32,
+
+    #[serde(skip)]
+    pub hit_box: HitBox,
+
+    #[serde(skip)]
+    pub ai: AiConfig,
+
+    #[serde(skip)]
+    pub resistances: Resistances,
+
+    #[serde(skip)]
+    pub attacks: Vec<CreatureAttack>,
+
+    #[serde(skip)]
+    pub color: [f32; 4],
+
     pub blend_mode: BlendMode,
+
+    // functions can't be saved, this is restored from the prototype after loading
+    #[serde(skip, default = "default_movement")]
     pub movement_function: fn(f32) -> f32,
     pub animation_type: CreatureAnimation,
 }
 
 
-#[derive(Copy, Clone)]
+impl Creature {
+
+    /**
+     * Walking speed in pixels per second, the player walks faster with Speed points.
+     */
+    pub fn move_speed(&self) -> f32
+    {
+        let speed_factor = self.character.as_ref().map(|character| character.speed_factor()).unwrap_or(1.0);
+        self.base_speed * speed_factor
+    }
+}
+
+
+#[derive(Copy, Clone, Serialize, Deserialize)]
 pub enum CreatureAnimation
 {
     NONE,
@@ -48,28 +143,79 @@ pub enum CreatureAnimation
 }
 
 
+/**
+ * Where projectiles can hit a creature: a circle around its foot point,
+ * up to the height above the ground. Height 0 means there is no limit.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HitBox
+{
+    pub radius: f32,
+    pub height: f32,
+}
+
+
+impl Default for HitBox {
+    fn default() -> HitBox {
+        HitBox {
+            radius: DEFAULT_COLLISION_RADIUS * 1.5,
+            height: 0.0,
+        }
+    }
+}
+
+
+/**
+ * One way a creature can attack. The projectile comes from projectiles.csv,
+ * the damage of the attack replaces the damage of the projectile.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreatureAttack
+{
+    pub projectile: String,
+
+    // seconds until this attack can be used again
+    pub cooldown: f32,
+    pub range: f32,
+    pub damage: i32,
+    pub element: Element,
+}
+
+
 impl CreatureFactory {
 
-    pub fn new() -> CreatureFactory 
+    pub fn new() -> Result<CreatureFactory, LoaderError> 
     {
-        let prototypes = read_creature_prototypes();
+        let prototypes = read_creature_prototypes()?;
 
-        CreatureFactory {
+        Ok(CreatureFactory {
             prototypes,
-        }
+        })
     }
 
 
     pub fn create(&self, key: &str) -> Creature 
     {
         let proto = self.prototypes.get(&key.to_string()).unwrap();
+        let hit_points = (proto.max_hp + proto.min_hp) / 2;
 
         Creature {
+            key: key.to_string(),
             base_tile_id: proto.base_tile_id,
             frames: proto.frames,
             base_speed: proto.speed,
-            hit_points: (proto.max_hp + proto.min_hp) / 2,
+            hit_points,
+            max_hit_points: hit_points,
+            effects: StatusEffects::new(),
+            character: None,
+            level: proto.level,
             projectile_spawn_distance: proto.projectile_spawn_distance,
+            collision_radius: proto.collision_radius,
+            hit_box: proto.hit_box,
+            ai: proto.ai,
+            resistances: proto.resistances,
+            attacks: proto.attacks.clone(),
+            color: proto.color,
             blend_mode: proto.blend_mode,
             movement_function: proto.movement_function,
             animation_type: proto.animation_type,
@@ -86,39 +232,159 @@ impl CreatureFactory {
     {
         self.prototypes.get(&name.to_string()).is_some()
     }
+
+
+    pub fn prototypes(&self) -> impl Iterator<Item = (&String, &CreaturePrototype)>
+    {
+        self.prototypes.iter()
+    }
+
+
+    /**
+     * Loaded creatures lack the parts which can't be saved. Take them
+     * from the prototype again.
+     */
+    pub fn restore(&self, creature: &mut Creature)
+    {
+        let proto_opt = self.prototypes.get(&creature.key);
+
+        if let Some(proto) = proto_opt {
+            creature.movement_function = proto.movement_function;
+            creature.level = proto.level;
+            creature.hit_box = proto.hit_box;
+            creature.ai = proto.ai;
+            creature.resistances = proto.resistances;
+            creature.attacks = proto.attacks.clone();
+            creature.color = proto.color;
+
+            // saved before there was a maximum, start with full health
+            if creature.max_hit_points <= 0 {
+                creature.max_hit_points = (proto.max_hp + proto.min_hp) / 2;
+                creature.hit_points = creature.max_hit_points;
+            }
+        }
+    }
 }
 
 
-fn read_creature_prototypes() -> HashMap <String, CreaturePrototype> 
+fn read_creature_prototypes() -> Result<HashMap <String, CreaturePrototype>, LoaderError> 
 {
-    let lines = read_lines("resources/creatures/creatures.csv");
+    let file = CsvFile::read("resources/creatures/creatures.csv")?;
     let mut prototypes = HashMap::new();
 
-    for i in 1..lines.len() {
-        let mut parts = lines[i].split(",");
-
-        let name = parts.next().unwrap().to_string();
+    // only needed for the hit boxes, a broken catalog is reported by the data validation
+    let catalog = read_tile_catalog("resources/gfx/creatures", "creatures.tica").ok();
+
+    for mut row in file.rows() {
+        let name = row.next_str()?.to_string();
+        let base_tile_id = row.next_usize()?;
+        let frames = row.next_usize()?;
+        let speed = row.next_f32()?;
+        let min_hp = row.next_i32()?;
+        let max_hp = row.next_i32()?;
+        let projectile_spawn_distance = row.next_f32()?;
+        let collision_radius = row.next_f32()?;
+
+        let mut hit_box = HitBox {
+            radius: row.next_f32()?,
+            height: row.next_f32()?,
+        };
+
+        if hit_box.radius <= 0.0 {
+            hit_box.radius = derived_hit_radius(catalog.as_ref(), base_tile_id, collision_radius);
+        }
 
         prototypes.insert(name, 
             CreaturePrototype {
-                base_tile_id: parts.next().unwrap().parse::<usize>().unwrap(),
-                frames: parts.next().unwrap().parse::<usize>().unwrap(),
-                speed: parts.next().unwrap().parse::<f32>().unwrap(),
-                min_hp: parts.next().unwrap().parse::<i32>().unwrap(),
-                max_hp: parts.next().unwrap().parse::<i32>().unwrap(),
-                projectile_spawn_distance: parts.next().unwrap().parse::<f32>().unwrap(),
+                base_tile_id,
+                frames,
+                speed,
+                min_hp,
+                max_hp,
+                projectile_spawn_distance,
+                collision_radius,
+                hit_box,
+                ai: AiConfig {
+                    aggro_range: row.next_f32()?,
+                    leash_distance: row.next_f32()?,
+                    attack_range: row.next_f32()?,
+                    flee_percent: row.next_i32()?,
+                },
+                resistances: Resistances {
+                    fire: row.next_i32()?,
+                    cold: row.next_i32()?,
+                    lightning: row.next_i32()?,
+                },
+                color: row.next_rgba()?,
+                level: row.next_u32()?,
+                attacks: parse_attacks(&mut row)?,
                 blend_mode: BlendMode::Blend,
                 movement_function: movement_bounce,
                 animation_type: CreatureAnimation::NONE,
             });
     }
 
-    prototypes
+    Ok(prototypes)
+}
+
+
+/**
+ * Half the width of the first frame, as drawn on the map. Creatures
+ * without an image get a hit box a bit larger than their collision radius.
+ */
+fn derived_hit_radius(catalog: Option<&TileCatalog>, base_tile_id: usize, collision_radius: f32) -> f32
+{
+    catalog
+        .and_then(|catalog| catalog.tiles.iter().find(|tile| tile.id == base_tile_id && tile.has_image()))
+        .map(|tile| tile.size[0] * 0.5 * CREATURE_SCALE)
+        .unwrap_or(collision_radius * 1.5)
+}
+
+
+/**
+ * The attacks are the last columns of a creature, five for each attack.
+ * Creatures which can't attack leave them out.
+ */
+fn parse_attacks(row: &mut CsvRow) -> Result<Vec<CreatureAttack>, LoaderError>
+{
+    let mut result = Vec::new();
+
+    while row.has_more() {
+        let projectile = row.next_str()?.trim();
+
+        if projectile.is_empty() {
+            // end of data
+            break;
+        }
+
+        result.push(CreatureAttack {
+            projectile: projectile.to_string(),
+            cooldown: row.next_f32()?,
+            range: row.next_f32()?,
+            damage: row.next_i32()?,
+            element: row.next_with(parse_element)?,
+        });
+    }
+
+    Ok(result)
+}
+
+
+// savegames from before creatures had a collision radius
+fn default_collision_radius() -> f32
+{
+    DEFAULT_COLLISION_RADIUS
 }
 
 
 // movement functions
 
+fn default_movement() -> fn(f32) -> f32
+{
+    movement_bounce
+}
+
+
 pub fn movement_glide(_p: f32) -> f32
 {
     0.0
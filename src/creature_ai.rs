@@ -0,0 +1,209 @@
+use serde::{Serialize, Deserialize};
+
+
+// seconds a creature stares at the player before it reacts
+pub const ALERT_TIME: f32 = 0.6;
+
+// once chasing, creatures follow the player this much farther than they notice the player
+pub const LOSE_FACTOR: f32 = 1.5;
+
+// returning creatures are home again this close to the group center
+pub const HOME_RADIUS: f32 = 60.0;
+
+
+/**
+ * How a kind of creature fights. Read from creatures.csv, so
+ * different monsters can behave differently.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AiConfig {
+    // the player is noticed inside this range, if visible
+    pub aggro_range: f32,
+
+    // creatures won't go farther than this from the center of their group
+    pub leash_distance: f32,
+
+    // creatures shoot from this distance, they must come closer otherwise
+    pub attack_range: f32,
+
+    // creatures run away with less health than this, in percent. 0 means never
+    pub flee_percent: i32,
+}
+
+
+impl Default for AiConfig {
+    fn default() -> AiConfig {
+        AiConfig {
+            aggro_range: 400.0,
+            leash_distance: 700.0,
+            attack_range: 350.0,
+            flee_percent: 0,
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AiState {
+    // stands still until the player comes
+    #[default]
+    Idle,
+    // wanders around the group center
+    Patrol,
+    // has noticed the player and is about to react
+    Alert,
+    Chase,
+    Attack,
+    Flee,
+    // goes back to the group center and ignores the player on the way
+    Return,
+}
+
+
+/**
+ * What a creature knows about its surroundings when it decides what to do.
+ */
+pub struct Senses {
+    pub player_distance: f32,
+    pub player_visible: bool,
+    pub player_alive: bool,
+
+    // distance from the group center
+    pub home_distance: f32,
+    pub health_percent: i32,
+
+    // another member of the group has seen the player
+    pub group_alerted: bool,
+
+    // the creature can move at all
+    pub mobile: bool,
+
+    // the action timer ran out
+    pub ready: bool,
+}
+
+
+/**
+ * The state machine of the creature AI.
+ *
+ * @return The state the creature should be in now, which can be the current one.
+ */
+pub fn next_state(state: AiState, config: &AiConfig, senses: &Senses) -> AiState {
+
+    let wander = if senses.mobile {AiState::Patrol} else {AiState::Idle};
+    let giving_up = if senses.mobile {AiState::Return} else {AiState::Idle};
+
+    if !senses.player_alive {
+        return match state {
+            AiState::Idle | AiState::Patrol | AiState::Return => state,
+            _ => giving_up,
+        };
+    }
+
+    let sees_player = senses.player_visible && senses.player_distance <= config.aggro_range;
+    let lost_player = senses.player_distance > config.aggro_range * LOSE_FACTOR || senses.home_distance > config.leash_distance;
+    let in_range = senses.player_visible && senses.player_distance <= config.attack_range;
+    let scared = senses.mobile && config.flee_percent > 0 && senses.health_percent < config.flee_percent;
+
+    match state {
+        AiState::Idle | AiState::Patrol => {
+            if sees_player || (senses.group_alerted && senses.player_distance <= config.aggro_range * LOSE_FACTOR) {
+                AiState::Alert
+            }
+            else {
+                state
+            }
+        },
+        AiState::Alert | AiState::Chase | AiState::Attack => {
+            if state == AiState::Alert && !senses.ready {
+                AiState::Alert
+            }
+            else if lost_player {
+                giving_up
+            }
+            else if scared {
+                AiState::Flee
+            }
+            else if in_range {
+                AiState::Attack
+            }
+            else if senses.mobile {
+                AiState::Chase
+            }
+            else {
+                AiState::Alert
+            }
+        },
+        AiState::Flee => {
+            if lost_player || senses.player_distance > config.aggro_range {
+                AiState::Return
+            }
+            else {
+                AiState::Flee
+            }
+        },
+        AiState::Return => {
+            if !senses.mobile || senses.home_distance < HOME_RADIUS {
+                wander
+            }
+            else {
+                AiState::Return
+            }
+        },
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    fn senses(player_distance: f32, home_distance: f32) -> Senses {
+        Senses {
+            player_distance,
+            player_visible: true,
+            player_alive: true,
+            home_distance,
+            health_percent: 100,
+            group_alerted: false,
+            mobile: true,
+            ready: true,
+        }
+    }
+
+
+    #[test]
+    fn test_state_machine() {
+        let config = AiConfig { aggro_range: 400.0, leash_distance: 600.0, attack_range: 300.0, flee_percent: 30 };
+
+        // noticed only when close and visible
+        assert_eq!(next_state(AiState::Patrol, &config, &senses(500.0, 0.0)), AiState::Patrol);
+        assert_eq!(next_state(AiState::Patrol, &config, &Senses { player_visible: false, ..senses(350.0, 0.0) }), AiState::Patrol);
+        assert_eq!(next_state(AiState::Patrol, &config, &senses(350.0, 0.0)), AiState::Alert);
+        assert_eq!(next_state(AiState::Idle, &config, &Senses { group_alerted: true, ..senses(500.0, 0.0) }), AiState::Alert);
+
+        assert_eq!(next_state(AiState::Alert, &config, &Senses { ready: false, ..senses(350.0, 0.0) }), AiState::Alert);
+        assert_eq!(next_state(AiState::Alert, &config, &senses(350.0, 0.0)), AiState::Chase);
+        assert_eq!(next_state(AiState::Chase, &config, &senses(250.0, 100.0)), AiState::Attack);
+        assert_eq!(next_state(AiState::Attack, &config, &Senses { player_visible: false, ..senses(250.0, 100.0) }), AiState::Chase);
+
+        // immobile creatures wait until the player comes into range
+        assert_eq!(next_state(AiState::Alert, &config, &Senses { mobile: false, ..senses(350.0, 0.0) }), AiState::Alert);
+
+        // too far from home or from the player
+        assert_eq!(next_state(AiState::Chase, &config, &senses(350.0, 650.0)), AiState::Return);
+        assert_eq!(next_state(AiState::Chase, &config, &senses(650.0, 100.0)), AiState::Return);
+        assert_eq!(next_state(AiState::Return, &config, &senses(100.0, 100.0)), AiState::Return);
+        assert_eq!(next_state(AiState::Return, &config, &senses(100.0, 50.0)), AiState::Patrol);
+
+        // hurt creatures run until they are safe
+        assert_eq!(next_state(AiState::Attack, &config, &Senses { health_percent: 20, ..senses(250.0, 100.0) }), AiState::Flee);
+        assert_eq!(next_state(AiState::Flee, &config, &Senses { health_percent: 20, ..senses(350.0, 200.0) }), AiState::Flee);
+        assert_eq!(next_state(AiState::Flee, &config, &Senses { health_percent: 20, ..senses(450.0, 200.0) }), AiState::Return);
+
+        // nobody to fight
+        assert_eq!(next_state(AiState::Attack, &config, &Senses { player_alive: false, ..senses(250.0, 100.0) }), AiState::Return);
+        assert_eq!(next_state(AiState::Patrol, &config, &Senses { player_alive: false, ..senses(250.0, 100.0) }), AiState::Patrol);
+    }
+}
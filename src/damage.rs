@@ -0,0 +1,155 @@
+use serde::{Serialize, Deserialize};
+
+use crate::inventory::Inventory;
+use crate::inventory::Slot;
+use crate::item::Attribute;
+
+
+/**
+ * No resistance can block more than this many percent of a hit.
+ */
+pub const MAX_RESISTANCE: i32 = 75;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Element {
+    Physical,
+    Fire,
+    Cold,
+    Lightning,
+}
+
+
+/**
+ * What a projectile does to the target it hits.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Damage {
+    pub amount: i32,
+    pub element: Element,
+
+    // chance in percent that an elemental hit also ignites, chills or shocks
+    #[serde(default)]
+    pub effect_chance: i32,
+}
+
+
+/**
+ * Elemental resistances in percent. Physical damage is not resisted.
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Resistances {
+    pub fire: i32,
+    pub cold: i32,
+    pub lightning: i32,
+}
+
+
+impl Damage {
+
+    /**
+     * Adds the damage mods of the equipped items. Physical projectiles
+     * profit from physical damage, all others from spell damage and
+     * the chance mods of their element.
+     */
+    pub fn with_equipment(&self, inventory: &Inventory) -> Damage {
+        let attribute = if self.element == Element::Physical {Attribute::PhysicalDamage} else {Attribute::SpellDamage};
+
+        let effect_chance = match self.element {
+            Element::Physical => 0,
+            Element::Fire => equipment_total(inventory, Attribute::IgniteChance),
+            Element::Cold => equipment_total(inventory, Attribute::ChillChance),
+            Element::Lightning => equipment_total(inventory, Attribute::ShockChance),
+        };
+
+        Damage {
+            amount: self.amount + equipment_total(inventory, attribute),
+            element: self.element,
+            effect_chance: self.effect_chance + effect_chance,
+        }
+    }
+
+
+    /**
+     * @return The damage which is left after the resistances, never less than zero.
+     */
+    pub fn after_resistances(&self, resistances: &Resistances) -> i32 {
+        let resistance = match self.element {
+            Element::Physical => 0,
+            Element::Fire => resistances.fire,
+            Element::Cold => resistances.cold,
+            Element::Lightning => resistances.lightning,
+        };
+
+        let resistance = resistance.clamp(0, MAX_RESISTANCE);
+
+        (self.amount * (100 - resistance) / 100).max(0)
+    }
+}
+
+
+impl Resistances {
+
+    pub fn from_equipment(inventory: &Inventory) -> Resistances {
+        Resistances {
+            fire: equipment_total(inventory, Attribute::ResFire),
+            cold: equipment_total(inventory, Attribute::ResCold),
+            lightning: equipment_total(inventory, Attribute::ResLight),
+        }
+    }
+}
+
+
+/**
+ * The sum of all mods of this attribute on the worn items. Items in the
+ * bag, the stash or on the cursor don't count.
+ */
+pub fn equipment_total(inventory: &Inventory, attribute: Attribute) -> i32 {
+    let mut sum = 0.0;
+
+    for entry in &inventory.entries {
+        if entry.slot == Slot::OnCursor || entry.slot == Slot::Bag || entry.slot == Slot::Stash {
+            continue;
+        }
+
+        if let Some(item) = inventory.bag.get(&entry.item_id) {
+            sum += item.get_attribute_total_mod(attribute.clone());
+        }
+    }
+
+    sum as i32
+}
+
+
+pub fn parse_element(input: &str) -> Result<Element, String> {
+    match input {
+        "physical" => Ok(Element::Physical),
+        "fire" => Ok(Element::Fire),
+        "cold" => Ok(Element::Cold),
+        "lightning" => Ok(Element::Lightning),
+        _ => Err("unknown element, use physical, fire, cold or lightning".to_string()),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn test_resistances() {
+        let fireball = Damage { amount: 20, element: Element::Fire, effect_chance: 0 };
+        let shot = Damage { amount: 20, element: Element::Physical, effect_chance: 0 };
+
+        let resistances = Resistances { fire: 50, cold: 0, lightning: 0 };
+
+        assert_eq!(fireball.after_resistances(&Resistances::default()), 20);
+        assert_eq!(fireball.after_resistances(&resistances), 10);
+        assert_eq!(shot.after_resistances(&resistances), 20);
+
+        // capped, and negative resistances don't add damage
+        assert_eq!(fireball.after_resistances(&Resistances { fire: 200, cold: 0, lightning: 0 }), 5);
+        assert_eq!(fireball.after_resistances(&Resistances { fire: -50, cold: 0, lightning: 0 }), 20);
+    }
+}
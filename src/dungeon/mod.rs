@@ -1,13 +1,10 @@
-use std::collections::HashMap;
-use std::collections::HashSet;
+use std::collections::BTreeMap;
 use std::cmp;
 
 use vecmath::Vector2;
 use rand::prelude::*;
 use geo::LineString;
 use geo::Polygon;
-use geo::CoordsIter;
-use geo::BooleanOps;
 
 use crate::MAP_GROUND_LAYER;
 use crate::MAP_OBJECT_LAYER;
@@ -16,6 +13,13 @@ use crate::map::TransitionDestination;
 use crate::WHITE;
 
 
+/**
+ * Where the dungeon entrance stairs lead to: the town, in front of the dungeon gate.
+ */
+pub const TOWN_MAP_ID: i32 = 0;
+pub const TOWN_ENTRANCE: Vector2<f32> = [720.0, 1802.0];
+
+
 pub struct Room {
     pub x1: i32,
     pub y1: i32,
@@ -28,27 +32,29 @@ pub struct Dungeon {
     pub start_position: [f32; 2],
 
     pub rooms: Vec<Room>,
-    pub corridors: Vec<HashMap<i32, [i32; 2]>>,
+    pub corridors: Vec<BTreeMap<i32, [i32; 2]>>,
 }
 
 
-pub fn generate_dungeon(map: &mut Map) -> Dungeon {
+/**
+ * All randomness comes from the given generator, the same generator state
+ * always gives the same dungeon.
+ */
+pub fn generate_dungeon<R: Rng + ?Sized>(map: &mut Map, rng: &mut R) -> Dungeon {
 
     map.map_image_name = "".to_string();
     map.backdrop_image_name = "".to_string();
 
-    let mut rng = rand::rng();
+    let dungeon = rooms_and_corridors(map, rng);
 
-    let dungeon = rooms_and_corridors(map, &mut rng);
-
-    furnish_dungeon(&dungeon, map, &mut rng);
+    furnish_dungeon(&dungeon, map, rng);
     dungeon
 }
 
 
 fn rooms_and_corridors<R: Rng + ?Sized>(map: &mut Map, rng: &mut R) -> Dungeon {
 
-    let mut corridors: Vec<HashMap<i32, [i32; 2]>> = Vec::new();
+    let mut corridors: Vec<BTreeMap<i32, [i32; 2]>> = Vec::new();
     let mut rooms: Vec<Room> = Vec::new();
 
     let mut entrances: [i32; 16 * 8] = [0; 16 * 8];
@@ -91,7 +97,8 @@ fn rooms_and_corridors<R: Rng + ?Sized>(map: &mut Map, rng: &mut R) -> Dungeon {
     // to avoid double tiles and easier wall placement in corridors we first collect all
     // floor coordinates and then actually build the corridor
 
-    let mut floors: HashMap<i32, [i32; 2]>; 
+    // sorted, so the tiles are placed in the same order on every run
+    let mut floors: BTreeMap<i32, [i32; 2]>; 
 
     for ry in 0 .. 4 {
         for rx in 0 .. 4 {
@@ -100,7 +107,7 @@ fn rooms_and_corridors<R: Rng + ?Sized>(map: &mut Map, rng: &mut R) -> Dungeon {
             
             // "down right" corridors
 
-            floors = HashMap::new();
+            floors = BTreeMap::new();
 
             if ry < 3 {
                 // straight starting stubs
@@ -122,13 +129,14 @@ fn rooms_and_corridors<R: Rng + ?Sized>(map: &mut Map, rng: &mut R) -> Dungeon {
 
                 // now the windy connection
                 let wriggle_prob = rng.random_range(0.1 .. 1.0);
-                build_winded_corridor(map, &mut floors, rng, 
-                    entrances[room + 4], //  = x;
-                    entrances[room + 5] + 2, //  = b;
+                build_winded_corridor(&mut floors, rng, 
+                    Segment {
+                        sx: entrances[room + 4], //  = x;
+                        sy: entrances[room + 5] + 2, //  = b;
         
-                    entrances[room + 8 * 4], //  = x;
-                    entrances[room + 8 * 4 + 1] - 2, //  = t;
-
+                        dx: entrances[room + 8 * 4], //  = x;
+                        dy: entrances[room + 8 * 4 + 1] - 2, //  = t;
+                    },
                     wriggle_prob);
             }
            
@@ -137,7 +145,7 @@ fn rooms_and_corridors<R: Rng + ?Sized>(map: &mut Map, rng: &mut R) -> Dungeon {
 
             // "up right" corridors
 
-            floors = HashMap::new();
+            floors = BTreeMap::new();
 
             if rx < 3 {
                 // straight starting stubs
@@ -159,12 +167,13 @@ fn rooms_and_corridors<R: Rng + ?Sized>(map: &mut Map, rng: &mut R) -> Dungeon {
                 );
     
                 let wriggle_prob = rng.random_range(0.1 .. 1.0);
-                build_winded_corridor(map, &mut floors, rng, 
-                    entrances[room + 2] + 2,
-                    entrances[room + 3],
-                    entrances[room + 8 + 6] - 2,
-                    entrances[room + 8 + 7],
-
+                build_winded_corridor(&mut floors, rng, 
+                    Segment {
+                        sx: entrances[room + 2] + 2,
+                        sy: entrances[room + 3],
+                        dx: entrances[room + 8 + 6] - 2,
+                        dy: entrances[room + 8 + 7],
+                    },
                     wriggle_prob);
             }
 
@@ -174,18 +183,9 @@ fn rooms_and_corridors<R: Rng + ?Sized>(map: &mut Map, rng: &mut R) -> Dungeon {
         }
     }
 
-    // This is expensive, would be even with only one call to merge_polygons,
-    // but we need the walkable lists often for basically all movement related checks
-    // so we have to minimize the number of entries even at this high cost (cause it happens only
-    // oncve per dungeon, but "walkable" lookup happens many times per path calculation)
-    loop {
-        let old_count = map.walkable.len();
-        map.walkable = merge_polygons(&map.walkable);
-
-        if map.walkable.len() >= old_count {
-            break;
-        }
-    }
+    // the rooms and corridors are many small areas, the walk grid
+    // makes the lookups fast no matter how many there are
+    map.update_walk_grid();
 
 
     Dungeon {
@@ -202,7 +202,7 @@ fn furnish_dungeon<R: Rng + ?Sized>(dungeon: &Dungeon, map: &mut Map, rng: &mut
     place_wall_tile(map, dungeon.rooms[0].x2, dungeon.rooms[0].y1, 
                     0, 248, WHITE);
 
-    let destination = TransitionDestination::Map {to_map: 0, to_location: [720.0, 1802.0]};
+    let destination = TransitionDestination::Map {to_map: TOWN_MAP_ID, to_location: TOWN_ENTRANCE};
 
     map.transitions.clear();
     map.add_transition(map_pos(dungeon.rooms[0].x2, dungeon.rooms[0].y1, 0), 100.0, destination);
@@ -306,15 +306,27 @@ fn store_walkable_area(sx: i32, sy: i32, dx: i32, dy: i32,
 }
 
 
-fn build_winded_corridor<R: Rng + ?Sized>(map: &mut Map, floors: &mut HashMap<i32, [i32; 2]>, rng: &mut R, 
-                                          sx: i32, sy: i32, dx: i32, dy: i32,
-                                          wriggle_prob: f64) {
+/**
+ * A corridor from the cell sx, sy to the cell dx, dy.
+ */
+struct Segment {
+    sx: i32,
+    sy: i32,
+    dx: i32,
+    dy: i32,
+}
+
+
+fn build_winded_corridor<R: Rng + ?Sized>(floors: &mut BTreeMap<i32, [i32; 2]>, rng: &mut R, 
+                                          segment: Segment, wriggle_prob: f64) {
+    let Segment {sx, sy, dx, dy} = segment;
+
     // is this straight?
 
     if sx == dx || sy == dy {
         // straight corridor
 
-        subdivide_corridor(map, floors, rng, sx, sy, dx, dy, wriggle_prob);
+        subdivide_corridor(floors, rng, segment, wriggle_prob);
     }
     else {
         // L-shaped corridor, split it into two straight parts
@@ -322,20 +334,21 @@ fn build_winded_corridor<R: Rng + ?Sized>(map: &mut Map, floors: &mut HashMap<i3
         // two options to chose
 
         if rng.random() {
-            subdivide_corridor(map, floors, rng, sx, sy, sx, dy, wriggle_prob);
-            subdivide_corridor(map, floors, rng, sx, dy, dx, dy, wriggle_prob);
+            subdivide_corridor(floors, rng, Segment {sx, sy, dx: sx, dy}, wriggle_prob);
+            subdivide_corridor(floors, rng, Segment {sx, sy: dy, dx, dy}, wriggle_prob);
         }
         else {
-            subdivide_corridor(map, floors, rng, sx, sy, dx, sy, wriggle_prob);
-            subdivide_corridor(map, floors, rng, dx, sy, dx, dy, wriggle_prob);    
+            subdivide_corridor(floors, rng, Segment {sx, sy, dx, dy: sy}, wriggle_prob);
+            subdivide_corridor(floors, rng, Segment {sx: dx, sy, dx, dy}, wriggle_prob);    
         }
     }
 }
 
 
-fn subdivide_corridor<R: Rng + ?Sized>(map: &mut Map, floors: &mut HashMap<i32, [i32; 2]>, rng: &mut R,
-                                       sx: i32, sy: i32, dx: i32, dy: i32,
-                                       wriggle_prob: f64) {
+fn subdivide_corridor<R: Rng + ?Sized>(floors: &mut BTreeMap<i32, [i32; 2]>, rng: &mut R,
+                                       segment: Segment, wriggle_prob: f64) {
+    let Segment {sx, sy, dx, dy} = segment;
+
     let vx = (dx - sx).signum();
     let vy = (dy - sy).signum();
 
@@ -362,19 +375,19 @@ fn subdivide_corridor<R: Rng + ?Sized>(map: &mut Map, floors: &mut HashMap<i32,
 
         // U turn
 
-        subdivide_corridor(map, floors, rng, 
-                           sx + min * vx, sy + min * vy, 
-                           sx + min * vx + d * vy, sy + min * vy + d * -vx,
+        subdivide_corridor(floors, rng, 
+                           Segment {sx: sx + min * vx, sy: sy + min * vy, 
+                                    dx: sx + min * vx + d * vy, dy: sy + min * vy + d * -vx},
                            wriggle_prob);
 
-        subdivide_corridor(map, floors, rng, 
-                           sx + min * vx + d * vy, sy + min * vy + d * -vx, 
-                           sx + max * vx + d * vy, sy + max * vy + d * -vx,
+        subdivide_corridor(floors, rng, 
+                           Segment {sx: sx + min * vx + d * vy, sy: sy + min * vy + d * -vx, 
+                                    dx: sx + max * vx + d * vy, dy: sy + max * vy + d * -vx},
                            wriggle_prob);
 
-        subdivide_corridor(map, floors, rng, 
-                           sx + max * vx + d * vy, sy + max * vy + d * -vx, 
-                           sx + max * vx, sy + max * vy,
+        subdivide_corridor(floors, rng, 
+                           Segment {sx: sx + max * vx + d * vy, sy: sy + max * vy + d * -vx, 
+                                    dx: sx + max * vx, dy: sy + max * vy},
                            wriggle_prob);
 
         // end piece
@@ -388,7 +401,7 @@ fn subdivide_corridor<R: Rng + ?Sized>(map: &mut Map, floors: &mut HashMap<i32,
  *
  * @param end The end piece of the corridor will be omitted of end is zero. Pass one to place the end piece too
  */
-fn build_straight_corridor(floors: &mut HashMap<i32, [i32; 2]>,
+fn build_straight_corridor(floors: &mut BTreeMap<i32, [i32; 2]>,
                            sx: i32, sy: i32, dx: i32, dy: i32, end: i32) {
     let vx = (dx - sx).signum();
     let vy = (dy - sy).signum();
@@ -411,13 +424,13 @@ fn floor_key(x: i32, y: i32) -> i32 {
 }
 
 
-fn add_floor_coordinate(floors: &mut HashMap<i32, [i32; 2]>, x: i32, y: i32) {
+fn add_floor_coordinate(floors: &mut BTreeMap<i32, [i32; 2]>, x: i32, y: i32) {
     let key = floor_key(x, y);
     floors.insert(key, [x, y]);
 }
 
 
-fn build_tunnel_from_coordinates(map: &mut Map, floors: &HashMap<i32, [i32; 2]>)
+fn build_tunnel_from_coordinates(map: &mut Map, floors: &BTreeMap<i32, [i32; 2]>)
 {
     // let wall_color = [0.63, 0.64, 0.65, 1.0];
     // let floor_color = [0.38, 0.36, 0.33, 1.0];
@@ -481,7 +494,7 @@ fn build_tunnel_from_coordinates(map: &mut Map, floors: &HashMap<i32, [i32; 2]>)
 }
 
 
-fn build_corridor_from_coordinates(map: &mut Map, floors: &HashMap<i32, [i32; 2]>)
+fn build_corridor_from_coordinates(map: &mut Map, floors: &BTreeMap<i32, [i32; 2]>)
 {
     let floor_color = [0.68, 0.66, 0.63, 1.0];
     let wall_color = [0.53, 0.54, 0.55, 1.0];
@@ -571,7 +584,7 @@ fn place_coins<R: Rng + ?Sized>(map: &mut Map,
                                 x: i32, y: i32, id: &str, count: u32,
                                 rng: &mut R) -> u64 
 {
-    let mut item = map.item_factory.create(id, rng);
+    let mut item = map.item_factory.create(id, rng).expect("coins are required items");
     item.stack_size = count;
 
     map.place_item(item, map_pos(x, y, 0))
@@ -597,67 +610,3 @@ pub fn map_pos(x: i32, y: i32, z_off: i32) -> [f32; 2]
 
     [fx, fy]
 }
-
-
-/**
- * Careful, this is an expensive operation with O(n²) complexity
- */
-fn merge_polygons(polygons: &Vec<Polygon<f32>>) -> Vec<Polygon<f32>>
-{
-    let mut result = Vec::new();
-    let mut already_merged = HashSet::new();
-
-    for left in 0 .. polygons.len() {
-        let mut merged = false;
-
-        for right in left + 1 .. polygons.len() {
-
-            if !merged && !already_merged.contains(&right) {
-                let mut matching_points = Vec::new();
-
-                for c_left in polygons[left].exterior_coords_iter() {
-                    for c_right in polygons[right].exterior_coords_iter() {
-                    
-                        let xd = c_left.x - c_right.x;
-                        let yd = c_left.y - c_right.y;
-                        let d2 = xd * xd + yd * yd;
-    
-                        if d2 < 1.0 && !matching_points.contains(&c_left) {
-                            matching_points.push(c_left);
-                        }
-                    }
-                }
-    
-                if matching_points.len() > 1 {
-                    // we can merge these two squares
-    
-                    // println!("Merge option: poly {} and {} have {} common coordinates", left, right, matching_points.len());
-/*                
-                    for c in matching_points {
-                        println!("  c: {:?}", c);
-                    }
-*/                
-                    let union = polygons[left].union(&polygons[right]);
-                    // println!("->  union: {:?} {:?}", union, union.0);
-    
-                    result.push(union.0[0].clone());
-    
-                    already_merged.insert(right);
-                    // println!("square count = {}", result.len());
-
-                    merged = true;
-                    break;
-                }
-            }            
-        }
-
-        if !merged && !already_merged.contains(&left) {
-            result.push(polygons[left].clone());
-            // println!("square count = {}", result.len());
-        }
-    }
-
-    println!("Merged {} square areas into {}", polygons.len(), result.len());
-
-    result
-}
\ No newline at end of file
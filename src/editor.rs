@@ -1,11 +1,10 @@
 use vecmath::Vector2;
 use vecmath::vec2_sub;
 
-use glium::winit::keyboard::Key;
-use glium::winit::keyboard::NamedKey;
 use glium::Frame;
 
 use crate::ui::*;
+use crate::key_bindings::Action;
 use crate::map::{MAP_GROUND_LAYER, MAP_OBJECT_LAYER, MAP_CLOUD_LAYER};
 use crate::Map;
 use crate::GameWorld;
@@ -17,6 +16,28 @@ use crate::screen_to_world_pos;
 use crate::move_player;
 
 
+// the keys listed in the editor help, in this order
+const EDITOR_HELP_ACTIONS: [Action; 17] = [
+    Action::EditorHelp,
+    Action::EditorTileSelector,
+    Action::EditorGroundLayer,
+    Action::EditorObjectLayer,
+    Action::EditorCloudLayer,
+    Action::EditorMoveRight,
+    Action::EditorMoveLeft,
+    Action::EditorMoveUp,
+    Action::EditorMoveDown,
+    Action::EditorColor,
+    Action::EditorBlendAdd,
+    Action::EditorBlendMix,
+    Action::EditorDelete,
+    Action::EditorParticles,
+    Action::EditorLoad,
+    Action::EditorSave,
+    Action::GameMode,
+];
+
+
 pub struct MapEditor {
     pub selected_tile_id: usize,
     pub show_editor_keys: bool,
@@ -88,56 +109,56 @@ impl UiController for MapEditor {
                         }
                     }
                     
-                    if event.args.button == Button::Keyboard(Key::Named(NamedKey::F1)) { 
+                    if world.key_bindings.is(Action::EditorHelp, &event.args.button) {
                         self.show_editor_keys = !self.show_editor_keys;
                     }        
 
-                    if event.args.button == Button::Keyboard(Key::Character("1".into())) {
+                    if world.key_bindings.is(Action::EditorGroundLayer, &event.args.button) {
                         world.map.selected_layer = MAP_GROUND_LAYER;
                         self.selected_tile_id = 0;
                     }        
 
-                    if event.args.button == Button::Keyboard(Key::Character("2".into())) {                        
+                    if world.key_bindings.is(Action::EditorObjectLayer, &event.args.button) {
                         world.map.selected_layer = MAP_OBJECT_LAYER;
                         self.selected_tile_id = 0;
                     }        
 
-                    if event.args.button == Button::Keyboard(Key::Character("3".into())) {                        
+                    if world.key_bindings.is(Action::EditorCloudLayer, &event.args.button) {
                         world.map.selected_layer = MAP_CLOUD_LAYER;
                         self.selected_tile_id = 0;
                     }        
 
                     let step = if ui.context.keyboard_state.shift_pressed {8.0} else {1.0};
 
-                    if event.args.button == Button::Keyboard(Key::Named(NamedKey::ArrowRight)) {                        
+                    if world.key_bindings.is(Action::EditorMoveRight, &event.args.button) {
                         world.map.move_selected_object(step, 0.0);
                     }        
 
-                    if event.args.button == Button::Keyboard(Key::Named(NamedKey::ArrowLeft)) {                        
+                    if world.key_bindings.is(Action::EditorMoveLeft, &event.args.button) {
                         world.map.move_selected_object(-step, 0.0);
                     }        
 
-                    if event.args.button == Button::Keyboard(Key::Named(NamedKey::ArrowUp)) {  
+                    if world.key_bindings.is(Action::EditorMoveUp, &event.args.button) {
                         world.map.move_selected_object(0.0, -step);
                     }        
 
-                    if event.args.button == Button::Keyboard(Key::Named(NamedKey::ArrowDown)) {
+                    if world.key_bindings.is(Action::EditorMoveDown, &event.args.button) {
                         world.map.move_selected_object(0.0, step);
                     }        
 
 
-                    if event.args.button == Button::Keyboard(Key::Named(NamedKey::Space)) {
+                    if world.key_bindings.is(Action::EditorTileSelector, &event.args.button) {
                         let set = &world.layer_tileset[world.map.selected_layer];
                         let cont = self.make_tile_selector(&ui, set);
                         ui.root.head.add_child(cont);
                     }        
 
-                    if event.args.button == Button::Keyboard(Key::Character("a".into())) {
+                    if world.key_bindings.is(Action::EditorBlendAdd, &event.args.button) {
                         let map = &mut world.map;
                         map.apply_to_selected_mob(|mob| {mob.visual.blend = BlendMode::Add;});
                     }
 
-                    if event.args.button == Button::Keyboard(Key::Character("c".into())) {
+                    if world.key_bindings.is(Action::EditorColor, &event.args.button) {
                         let map = &mut world.map;
                         let object = map.layers[map.selected_layer].get_mut(&map.selected_item);
                         match object {
@@ -149,7 +170,7 @@ impl UiController for MapEditor {
                         }
                     }        
 
-                    if event.args.button == Button::Keyboard(Key::Named(NamedKey::Delete)) {
+                    if world.key_bindings.is(Action::EditorDelete, &event.args.button) {
                         let map = &mut world.map;
                         let mob = map.layers[map.selected_layer].get(&map.selected_item);
 
@@ -163,23 +184,29 @@ impl UiController for MapEditor {
                         }
                     }        
 
-                    if event.args.button == Button::Keyboard(Key::Character("l".into())) {
-                        world.map.load("start.map");
+                    if world.key_bindings.is(Action::EditorLoad, &event.args.button) {
+                        match world.map.load("start.map", &mut world.rng) {
+                            Ok(()) => {},
+                            Err(message) => println!("Could not load the map: {}", message),
+                        }
                     }
 
-                    if event.args.button == Button::Keyboard(Key::Character("m".into())) {
+                    if world.key_bindings.is(Action::EditorBlendMix, &event.args.button) {
                         let map = &mut world.map;
                         map.apply_to_selected_mob(|mob| {mob.visual.blend = BlendMode::Blend;});
                     }
 
-                    if event.args.button == Button::Keyboard(Key::Character("p".into())) {
+                    if world.key_bindings.is(Action::EditorParticles, &event.args.button) {
                         let pos = screen_to_world_pos(&ui, &world.map.get_player_position(), 
                                                       &ui.context.mouse_state.position);
                         place_particle_generator(world, pos);
                     }
 
-                    if event.args.button == Button::Keyboard(Key::Character("s".into())) {
-                        world.map.save("town.map").unwrap();
+                    if world.key_bindings.is(Action::EditorSave, &event.args.button) {
+                        match world.map.save("town.map") {
+                            Ok(()) => {},
+                            Err(message) => println!("Could not save the map: {}", message),
+                        }
                     }        
                 },
                 Some(comp) => {
@@ -284,8 +311,7 @@ impl UiController for MapEditor {
                 Some(id) => {
                     if map.selected_item == id {
 
-                        let mob = &mut map.layers[map.selected_layer].get_mut(&id).unwrap();
-                        mob.position = pos;
+                        map.layers[map.selected_layer].set_position(id, pos);
                     }
                 }
             }
@@ -331,8 +357,12 @@ impl UiController for MapEditor {
 
         let font = &ui.context.font_normal;
         
-        font.draw(&ui.display, target, &ui.program, 10, 20, "Press F1 to see editor hotkeys", &WHITE);
-        font.draw(&ui.display, target, &ui.program, 10, 40, "Press g to enter game mode", &WHITE);
+        let bindings = &world.key_bindings;
+        let help_msg = format!("Press {} to see editor hotkeys", bindings.key_name(Action::EditorHelp));
+        let game_msg = format!("Press {} to enter game mode", bindings.key_name(Action::GameMode));
+
+        font.draw(&ui.display, target, &ui.program, 10, 20, &help_msg, &WHITE);
+        font.draw(&ui.display, target, &ui.program, 10, 40, &game_msg, &WHITE);
 
         let layer_msg = 
             "Selected layer: ".to_string() + &(layer_id + 1).to_string() + 
@@ -347,25 +377,10 @@ impl UiController for MapEditor {
             let left = 100;
             let mut top = 100;
 
-
-            font.draw(&ui.display, target, &ui.program, left, top, "F1: Show/hide this list", &color);
-            top += line_space;
-            font.draw(&ui.display, target, &ui.program, left, top, "Space: Open tile selector", &color);
-            top += line_space;
-            font.draw(&ui.display, target, &ui.program, left, top, "1,2,3,.. : Select map layer", &color);
-            top += line_space;
-            font.draw(&ui.display, target, &ui.program, left, top, "c: Open color selector for selected item", &color);
-            top += line_space;
-            font.draw(&ui.display, target, &ui.program, left, top, "a: Set blend mode on selected item to 'Addition'", &color);
-            top += line_space;
-            font.draw(&ui.display, target, &ui.program, left, top, "m: Set blend mode on selected item to 'Mix' (default)", &color);
-            top += line_space;
-            font.draw(&ui.display, target, &ui.program, left, top, "Delete: Removes the selected item from the map", &color);
-            top += line_space;
-            font.draw(&ui.display, target, &ui.program, left, top, "l: Load a saved map", &color);
-            top += line_space;
-            font.draw(&ui.display, target, &ui.program, left, top, "s: Save the map", &color);
-            // top += line_space;
+            for action in EDITOR_HELP_ACTIONS {
+                font.draw(&ui.display, target, &ui.program, left, top, &bindings.help_line(action), &color);
+                top += line_space;
+            }
         }
     }
 
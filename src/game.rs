@@ -1,48 +1,66 @@
 use std::rc::Rc;
+use std::path::Path;
 use vecmath::{Vector2, vec2_sub, vec2_add, vec2_scale, vec2_normalized};
 
 use glium::Frame;
 use glium::Texture2d;
-use glium::winit::keyboard::Key;
-use glium::winit::keyboard::NamedKey;
 
 use rand::Rng;
+use rand::rngs::StdRng;
 
 use crate::ui::*;
+use crate::key_bindings::Action;
 use crate::GameWorld;
 use crate::Dungeon;
 use crate::move_player;
-use crate::screen_to_world_pos;
+use crate::window_to_world_pos;
 use crate::views::player_items_view::PlayerItemsView;
+use crate::views::character_view::CharacterView;
 use crate::views::shop_view::ShopView;
 use crate::TileSet;
 use crate::gfx::gl_support::load_texture;
 use crate::Map;
+use crate::Inventory;
+use crate::Slot;
 use crate::map::MoveEndAction;
 use crate::map::MapObject;
 use crate::map::MapObjectFactory;
 use crate::map::MobType;
+use crate::map::PROJECTILE_HEIGHT;
 use crate::map::TransitionDestination;
 use crate::MAP_OBJECT_LAYER;
 use crate::PROJECTILE_TILESET;
 use crate::SoundPlayer;
 use crate::sound::Sound;
 use crate::generate_dungeon;
+use crate::dungeon::TOWN_MAP_ID;
+use crate::dungeon::TOWN_ENTRANCE;
 use crate::map_pos;
+use crate::damage::Damage;
+use crate::character::experience_for_kill;
+use crate::character::HIT_POINTS_PER_LEVEL;
+use crate::combat_text::CombatText;
 
 
 const MAGIC_ITEM_CHANCE: f32 = 0.4;
 const MAGIC_FIND_FACTOR: f32 = 0.8;
 
+const QUICKSAVE_FILE: &str = "saves/quicksave.ron";
+
 
 pub struct Game 
 {
     piv: PlayerItemsView,
+    character_view: CharacterView,
     shop_view: ShopView,
     item_tiles: TileSet,
 
     show_player_inventory: bool,
+    show_character: bool,
     show_shop_inventory: bool,
+
+    // a savegame was loaded, map textures must be reloaded in the next update
+    reload_map: bool,
 }
 
 
@@ -62,14 +80,39 @@ impl UiController for Game
 
         if event.args.state == ButtonState::Release {
 
+            // the death screen is shown until the player clicks, only a saved game can be loaded meanwhile
+            if world.map.is_player_dead() {
+                if world.key_bindings.is(Action::QuickLoad, &event.args.button) {
+                    self.quick_load(world);
+                }
+                else if event.args.button == Button::Mouse(MouseButton::Left) {
+                    respawn_player(&mut world.map, &mut world.player_inventory, &mut world.rng);
+                    self.close_views();
+                    self.reload_map = true;
+                }
+
+                return true;
+            }
+
             // general key presses
-            if event.args.button == Button::Keyboard(Key::Character("i".into())) {
+            if world.key_bindings.is(Action::ToggleInventory, &event.args.button) {
                 self.show_player_inventory = !self.show_player_inventory;
             }
-            else if event.args.button == Button::Keyboard(Key::Named(NamedKey::Escape)) {
-                // close the views ... todo: if none are open show a game menu
-                self.show_player_inventory = false;
-                self.show_shop_inventory = false;
+            else if world.key_bindings.is(Action::ToggleCharacter, &event.args.button) {
+                self.show_character = !self.show_character;
+            }
+            else if world.key_bindings.is(Action::CloseViews, &event.args.button) {
+                // todo: if none are open show a game menu
+                self.close_views();
+            }
+            else if world.key_bindings.is(Action::QuickSave, &event.args.button) {
+                match world.save_game(Path::new(QUICKSAVE_FILE)) {
+                    Ok(()) => println!("Game saved to {}", QUICKSAVE_FILE),
+                    Err(message) => println!("Could not save the game: {}", message),
+                }
+            }
+            else if world.key_bindings.is(Action::QuickLoad, &event.args.button) {
+                self.quick_load(world);
             }
 
             // mouse button presses
@@ -89,6 +132,15 @@ impl UiController for Game
                         }
                     }
         
+                    // dragged items are dropped to the floor over the sheet
+                    if !consumed && self.show_character && self.piv.dragged_item.is_none() {
+                        let closed;
+                        (consumed, closed) = self.character_view.handle_button_event(event, world);
+                        if closed {
+                            self.show_character = false;
+                        }
+                    }
+
                     if !consumed && self.show_player_inventory {
                         let closed;
                         (consumed, closed) = self.piv.handle_button_event(event, &ui.context.mouse_state, world);
@@ -101,40 +153,21 @@ impl UiController for Game
 
                     // then check the game itself
 
-                    let pos = screen_to_world_pos(&ui, &world.map.get_player_position(), &ui.context.mouse_state.position);
-                    
-                    if event.args.button == Button::Mouse(MouseButton::Left) {
-                        // ui.root.head.clear();
-
-                        let target_opt = Map::find_nearest_object(&world.map.layers[MAP_OBJECT_LAYER], &pos, 100.0, world.map.player_id);
-                        match target_opt {
-                            None => {},
-                            Some(target_uid) => {
-                                let target = world.map.layers[MAP_OBJECT_LAYER].get(&target_uid).unwrap();
-
-                                if target.creature.is_some() {
-                                    let target_pos = target.position;
-                                    fire_player_projectile(&mut world.map, target_pos, &mut world.speaker);
-                                    return true;
-                                }
-                            }
-                        }
+                    let click = click_world(&mut world.map, &world.player_inventory, &mut world.speaker, &event.args.button,
+                                            &ui.context.mouse_state.position, &ui.context.window_size);
 
-                        {
+                    match click {
+                        WorldClick::Attack => {
+                            return true;
+                        },
+                        WorldClick::Move => {
                             // close shop and inventory views if the player moves from shop
                             if self.show_shop_inventory {
                                 self.show_shop_inventory = false;
                                 self.show_player_inventory = false;
                             }
-
-                            // now move
-                            let screen_direction = vec2_sub(ui.context.mouse_state.position, ui.window_center());
-                            move_player(&mut world.map, screen_direction);                
-                        }
-                    }
-
-                    if event.args.button == Button::Mouse(MouseButton::Right) {
-                        fire_player_projectile(&mut world.map, pos, &mut world.speaker);
+                        },
+                        WorldClick::Nothing => {}
                     }
                 },
                 Some(comp) => {
@@ -187,6 +220,10 @@ impl UiController for Game
             self.piv.draw(ui, target, &world.player_inventory, &self.item_tiles);
         }
 
+        if self.show_character {
+            self.character_view.draw(ui, target, world);
+        }
+
         if self.show_shop_inventory {
             let shop = &world.map.shops[self.shop_view.get_shop_index()];
             self.shop_view.draw(ui, target, shop, &world.player_inventory, &self.item_tiles);
@@ -194,22 +231,28 @@ impl UiController for Game
     }
 
 
-    fn draw_overlay(&mut self, target: &mut Frame, ui: &mut UI, _world: &mut Self::Appdata) 
+    fn draw_overlay(&mut self, target: &mut Frame, ui: &mut UI, world: &mut Self::Appdata) 
     {
         ui.context.font_normal.draw(&ui.display, target, &ui.program, 10, 20, "Game testing mode", &WHITE);
+
+        if world.map.is_player_dead() {
+            draw_death_screen(ui, target, death_penalty(&world.map, &world.player_inventory));
+        }
     }
 
 
     fn update(&mut self, world: &mut Self::Appdata, dt: f32) -> bool 
     {
+        // a loaded game or a respawn still needs its simulation step, like in the headless world
+        let reload = self.reload_map;
+        self.reload_map = false;
+
         let map = &mut world.map;
         let inv = &mut world.player_inventory;
         let rng = &mut world.rng;
         let speaker = &mut world.speaker;
         
-        let (killed_mob_list, transition_opt) = map.update(dt, inv, rng, speaker);
-
-        drop_loot(map, killed_mob_list, rng, speaker);
+        let transition_opt = update_world(map, inv, rng, speaker, dt);
 
         if transition_opt.is_some() {
             let index = transition_opt.unwrap();
@@ -217,32 +260,7 @@ impl UiController for Game
             
             match transition.destination {
                 TransitionDestination::Map { to_map, to_location } => {
-
-                    if to_map == 501 {
-                        // preserve player
-                        let mut player = world.map.layers[MAP_OBJECT_LAYER].remove(&world.map.player_id).unwrap();
-        
-                        world.map.clear();
-                        let dungeon = generate_dungeon(&mut world.map);
-                
-                        // stop player movement
-                        player.move_time_total = 0.0;
-                        player.move_time_left = 0.0;
-                        player.visual.z_off = 0.0;
-                        world.map.layers[MAP_OBJECT_LAYER].insert(world.map.player_id, player);
-                
-                        world.map.set_player_position(dungeon.start_position);
-        
-                        let creature_positions = find_suitable_creature_positions(&dungeon);
-                        world.map.populate("dungeon.csv", rng, creature_positions);
-                    }
-                    else {
-                        world.map.load("town.map");
-                        // self.populate("town.csv", rng);
-            
-                        world.map.set_player_position(to_location);
-                    }
-
+                    change_map(&mut world.map, rng, to_map, to_location);
                     return true;        
                 },
                 TransitionDestination::Shop { index } => {
@@ -257,7 +275,7 @@ impl UiController for Game
             }
         }
 
-        return false;
+        reload
     }
 }
 
@@ -276,27 +294,156 @@ impl Game {
         ui.root.head.add_child(backpack);
 
         let piv = PlayerItemsView::new((ui.context.window_size[0] as i32) / 2, 10, inventory_bg);
+        let character_view = CharacterView::left_of((ui.context.window_size[0] as i32) / 2 - 10, 10);
     
         let shop_view = ShopView::new(inventory_fg);
         
         Game 
         {
             piv,
+            character_view,
             shop_view,
             show_player_inventory: false,
+            show_character: false,
             show_shop_inventory: false,
+            reload_map: false,
             item_tiles: item_tiles.shallow_copy(),
         }
     }
+
+
+    fn close_views(&mut self)
+    {
+        self.show_player_inventory = false;
+        self.show_character = false;
+        self.show_shop_inventory = false;
+    }
+
+
+    fn quick_load(&mut self, world: &mut GameWorld)
+    {
+        match world.load_game(Path::new(QUICKSAVE_FILE)) {
+            Ok(()) => {
+                println!("Game loaded from {}", QUICKSAVE_FILE);
+                self.close_views();
+                self.reload_map = true;
+            },
+            Err(message) => println!("Could not load the game: {}", message),
+        }
+    }
+}
+
+
+fn draw_death_screen(ui: &UI, target: &mut Frame, loss: u32)
+{
+    let size = ui.context.window_size;
+    let w = 500;
+    let h = 130;
+    let x = (size[0] as i32 - w) / 2;
+    let y = (size[1] as i32 - h) / 2;
+
+    ui.draw_box(target, x, y, w, h, &[0.6, 0.6, 0.6, 1.0]);
+    ui.fill_box(target, x + 1, y + 1, w - 2, h - 2, &[0.08, 0.02, 0.02, 0.9]);
+
+    let lines = [
+        (&ui.context.font_large, "You died".to_string()),
+        (&ui.context.font_normal, format!("The way back to town will cost you {} copper.", loss)),
+        (&ui.context.font_normal, "Click to return to town.".to_string()),
+    ];
+
+    let mut line_y = y + 20;
+
+    for (font, text) in lines {
+        let line_x = x + (w - font.calc_string_width(&text) as i32) / 2;
+        font.draw(&ui.display, target, &ui.program, line_x, line_y, &text, &WHITE);
+        line_y += font.line_height + 10;
+    }
+}
+
+
+/**
+ * What a mouse click on the game world did.
+ */
+#[derive(Debug, PartialEq)]
+pub enum WorldClick {
+    Nothing,
+    Attack,
+    Move,
+}
+
+
+/**
+ * The part of a mouse click which acts on the game world, after the user
+ * interface had its chance. It needs no display, so replays can use it too.
+ *
+ * @param mouse_position Screen coordinates of the click
+ */
+pub fn click_world(map: &mut Map, inventory: &Inventory, speaker: &mut SoundPlayer, button: &Button,
+                   mouse_position: &Vector2<f32>, window_size: &[u32; 2]) -> WorldClick
+{
+    // the dead can't act
+    if map.is_player_dead() {
+        return WorldClick::Nothing;
+    }
+
+    let pos = window_to_world_pos(window_size, &map.get_player_position(), mouse_position);
+
+    if *button == Button::Mouse(MouseButton::Left) {
+
+        let target_opt = Map::find_nearest_object(&map.layers[MAP_OBJECT_LAYER], &pos, 100.0, map.player_id);
+
+        if let Some(target_uid) = target_opt {
+            let target = map.layers[MAP_OBJECT_LAYER].get(&target_uid).unwrap();
+
+            // without a weapon the player walks there instead
+            if target.creature.is_some() {
+                let target_pos = target.position;
+                if fire_player_projectile(map, inventory, target_pos, speaker).is_some() {
+                    return WorldClick::Attack;
+                }
+            }
+        }
+
+        let window_center = [(window_size[0] / 2) as f32, (window_size[1] / 2) as f32];
+        let screen_direction = vec2_sub(*mouse_position, window_center);
+        move_player(map, screen_direction);
+
+        return WorldClick::Move;
+    }
+
+    if *button == Button::Mouse(MouseButton::Right) && 
+       fire_player_projectile(map, inventory, pos, speaker).is_some() {
+        return WorldClick::Attack;
+    }
+
+    WorldClick::Nothing
 }
 
 
-fn fire_player_projectile(map: &mut Map, fire_at: Vector2<f32>, speaker: &mut SoundPlayer) -> u64
+/**
+ * The player attacks with the activation of the weapon in the right hand.
+ * The projectiles are stronger with damage mods on the equipped items.
+ *
+ * @return The uid of the projectile, None if the weapon can't attack
+ */
+pub fn fire_player_projectile(map: &mut Map, inventory: &Inventory, fire_at: Vector2<f32>, speaker: &mut SoundPlayer) -> Option<u64>
 {
+    let kind = inventory.equipped_item(Slot::RHand)
+        .and_then(|item| item.activation.projectile_key())?;
+
     let player = map.layers[MAP_OBJECT_LAYER].get(&map.player_id).unwrap();
     let pc = player.creature.as_ref().unwrap();
+    let character = pc.character.clone().unwrap_or_default();
+
+    let uid = fire_projectile(map, kind, fire_at, pc.projectile_spawn_distance, speaker);
+
+    let projectile = map.layers[MAP_OBJECT_LAYER].get_mut(&uid).unwrap();
+    projectile.damage = projectile.damage.map(|damage| {
+        let damage = damage.with_equipment(inventory);
+        Damage { amount: character.damage_bonus(damage.amount), .. damage }
+    });
 
-    fire_projectile(map, "Fireball", fire_at, pc.projectile_spawn_distance, speaker)
+    Some(uid)
 }
 
 
@@ -311,6 +458,7 @@ pub fn fire_projectile(map: &mut Map, kind: &str, fire_at: Vector2<f32>,
     let mut projectile = launch_projectile(player.position, fire_at, start_distance, 
                                            MobType::PlayerProjectile, factory);
     map.projectile_builder.configure_projectile(kind, &mut projectile.visual, &mut projectile.velocity, speaker);
+    map.projectile_builder.arm_projectile(kind, &mut projectile);
 
     let uid = projectile.uid;
     map.layers[MAP_OBJECT_LAYER].insert(uid, projectile);
@@ -329,7 +477,7 @@ pub fn launch_projectile(shooter_position: Vector2<f32>, fire_at: Vector2<f32>,
     let dir = vec2_normalized(np);
     let start_pos = vec2_add(shooter_position, vec2_scale(dir, start_distance));
 
-    let mut projectile = factory.create_mob(1, PROJECTILE_TILESET, start_pos, 12.0, 0.5);
+    let mut projectile = factory.create_mob(1, PROJECTILE_TILESET, start_pos, PROJECTILE_HEIGHT, 0.5);
     projectile.velocity = dir;
     
     projectile.move_time_total = 2.0;
@@ -341,7 +489,140 @@ pub fn launch_projectile(shooter_position: Vector2<f32>, fire_at: Vector2<f32>,
 } 
 
 
-fn drop_loot<R: Rng + ?Sized>(map: &mut Map, killed_mob_list: Vec<MapObject>, 
+/**
+ * Advances the game world by dt seconds. This is the part of Game::update
+ * which does not need a user interface, so it can also run headless.
+ *
+ * @return The index of the map transition the player stepped on, if any.
+ */
+pub fn update_world(map: &mut Map, inventory: &mut Inventory, rng: &mut StdRng, 
+                    speaker: &mut SoundPlayer, dt: f32) -> Option<usize>
+{
+    let (killed_mob_list, transition_opt) = map.update(dt, inventory, rng, speaker);
+
+    gain_experience(map, &killed_mob_list);
+    drop_loot(map, killed_mob_list, rng, speaker);
+
+    transition_opt
+}
+
+
+/**
+ * Follows a map transition. Map id 501 is a new random dungeon level,
+ * everything else leads back to town.
+ */
+pub fn change_map(map: &mut Map, rng: &mut StdRng, to_map: i32, to_location: Vector2<f32>)
+{
+    if to_map == 501 {
+        enter_dungeon(map, rng);
+    }
+    else {
+        if let Err(message) = map.load("town.map", rng) {
+            println!("Could not load the town: {}", message);
+        }
+        // self.populate("town.csv", rng);
+
+        map.set_player_position(to_location);
+    }
+}
+
+
+/**
+ * The money a dead player loses, in copper coins.
+ */
+pub fn death_penalty(map: &Map, inventory: &Inventory) -> u32
+{
+    inventory.total_money() * map.rules.death_money_loss / 100
+}
+
+
+/**
+ * Brings a dead player back to life in town. The death penalty is taken
+ * from the inventory.
+ *
+ * @return The money lost, in copper coins
+ */
+pub fn respawn_player(map: &mut Map, inventory: &mut Inventory, rng: &mut StdRng) -> u32
+{
+    let loss = death_penalty(map, inventory);
+
+    if loss > 0 {
+        inventory.withdraw_money(loss, &mut map.item_factory);
+    }
+
+    let player = map.layers[MAP_OBJECT_LAYER].get_mut(&map.player_id).unwrap();
+    let creature = player.creature.as_mut().unwrap();
+    creature.hit_points = creature.max_hit_points;
+    creature.effects.clear();
+    player.visual.color = creature.color;
+
+    change_map(map, rng, TOWN_MAP_ID, TOWN_ENTRANCE);
+
+    println!("The player was respawned in town and lost {} copper", loss);
+
+    loss
+}
+
+
+/**
+ * Replaces the map contents with a freshly generated and populated 
+ * dungeon level. The player is kept and placed at the dungeon entrance.
+ */
+pub fn enter_dungeon(map: &mut Map, rng: &mut StdRng) -> Dungeon
+{
+    // preserve player
+    let mut player = map.layers[MAP_OBJECT_LAYER].remove(&map.player_id).unwrap();
+
+    map.clear();
+    let dungeon = generate_dungeon(map, rng);
+
+    // stop player movement
+    player.stop_moving();
+    map.layers[MAP_OBJECT_LAYER].insert(map.player_id, player);
+
+    map.set_player_position(dungeon.start_position);
+
+    let creature_positions = find_suitable_creature_positions(&dungeon);
+    map.populate("dungeon.csv", rng, creature_positions);
+
+    dungeon
+}
+
+
+/**
+ * The player learns from the creatures killed in this update. Each level
+ * up raises the maximum hit points and heals the player. The dead learn nothing.
+ */
+pub fn gain_experience(map: &mut Map, killed_mob_list: &[MapObject])
+{
+    let player = map.layers[MAP_OBJECT_LAYER].get_mut(&map.player_id).unwrap();
+    let position = player.position;
+    let creature = player.creature.as_mut().unwrap();
+
+    let character = match &mut creature.character {
+        Some(character) if creature.hit_points > 0 => character,
+        _ => return,
+    };
+
+    let mut levels = 0;
+
+    for mob in killed_mob_list {
+        let level = mob.creature.as_ref().map(|creature| creature.level).unwrap_or(1);
+        levels += character.add_experience(experience_for_kill(level, character.level));
+    }
+
+    if levels > 0 {
+        let level = character.level;
+
+        creature.max_hit_points += levels as i32 * HIT_POINTS_PER_LEVEL;
+        creature.hit_points = creature.max_hit_points;
+
+        map.combat_texts.push(CombatText::level_up(position, level));
+    }
+}
+
+
+pub fn drop_loot<R: Rng + ?Sized>(map: &mut Map, killed_mob_list: Vec<MapObject>, 
                               rng: &mut R, speaker: &mut SoundPlayer) 
 {
     // todo: monster or area levels
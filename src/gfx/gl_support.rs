@@ -25,6 +25,8 @@ use geo::Coord;
 use geo::Polygon;
 use geo::CoordsIter;
 
+use serde::{Serialize, Deserialize};
+
 use crate::ui::*;
 
 #[derive(Copy, Clone)]
@@ -35,7 +37,7 @@ pub struct Vertex {
 implement_vertex!(Vertex, position, tex_coords);
 
 
-#[derive(Debug, PartialEq, Copy, Clone)]
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
 pub enum BlendMode {
     Blend,
     Add,
@@ -0,0 +1,328 @@
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+use std::path::Path;
+
+use crate::Map;
+use crate::ItemFactory;
+use crate::Inventory;
+use crate::SoundPlayer;
+use crate::sound::RecordingBackend;
+use crate::sound::SoundLog;
+use crate::sound::SoundClock;
+use crate::sound::Sound;
+use crate::map::MAP_OBJECT_LAYER;
+use crate::map::TransitionDestination;
+use crate::game::update_world;
+use crate::game::change_map;
+use crate::game::enter_dungeon;
+use crate::game::click_world;
+use crate::game::respawn_player;
+use crate::ui::ButtonState;
+use crate::ui::Button;
+use crate::ui::MouseButton;
+use crate::replay::InputEvent;
+use crate::replay::Recording;
+use crate::replay::Replay;
+use crate::add_starting_items;
+use crate::voxel_image_generator::generated_creature_prototype;
+use crate::loader::LoaderError;
+use crate::loader::exit_on_error;
+use crate::SIMULATION_DT;
+
+
+// same step length as the windowed game, so the same seed gives the same run
+pub const HEADLESS_DT: f32 = SIMULATION_DT;
+
+
+/**
+ * The game world without any display or audio device. It holds the same
+ * simulation data as GameWorld, but no textures or tile sets, so it can
+ * run in CI, balance scripts and soak tests.
+ */
+pub struct HeadlessWorld
+{
+    pub map: Map,
+    pub player_inventory: Inventory,
+    pub speaker: SoundPlayer,
+    pub rng: StdRng,
+
+    // there is no audio device, sounds are only logged
+    pub sound_log: SoundLog,
+    sound_clock: SoundClock,
+
+    // simulated time in seconds
+    pub time: f32,
+
+    // simulation steps done so far
+    pub tick: u64,
+
+    // there is no window, replayed mouse input still needs a screen
+    pub mouse_position: [f32; 2],
+    pub window_size: [u32; 2],
+}
+
+
+impl HeadlessWorld
+{
+    pub fn new(seed: u64) -> Result<HeadlessWorld, LoaderError>
+    {
+        let item_factory = ItemFactory::new()?;
+        let mut player_inventory = Inventory::new();
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        let mut map = Map::new("Headless Map", "", "", item_factory)?;
+
+        // generated creatures have no images here, the tile ids are never used
+        for name in ["generated_creature_1", "generated_creature_2"] {
+            map.creature_factory.add(name, generated_creature_prototype(name, 0, 16));
+        }
+
+        enter_dungeon(&mut map, &mut rng);
+
+        add_starting_items(&mut map.item_factory, &mut player_inventory, &mut rng)?;
+
+        let recorder = RecordingBackend::new();
+        let sound_log = recorder.log();
+        let sound_clock = recorder.clock();
+
+        Ok(HeadlessWorld {
+            map,
+            player_inventory,
+            speaker: SoundPlayer::with_backend(Box::new(recorder)),
+            rng,
+            sound_log,
+            sound_clock,
+            time: 0.0,
+            tick: 0,
+            mouse_position: [0.0, 0.0],
+            window_size: [1200, 770],
+        })
+    }
+
+
+    /**
+     * Advances the simulation by dt seconds. Map transitions are followed,
+     * shops are ignored since there is nobody to trade with.
+     */
+    pub fn update(&mut self, dt: f32)
+    {
+        let transition_opt = update_world(&mut self.map, &mut self.player_inventory,
+                                          &mut self.rng, &mut self.speaker, dt);
+
+        if let Some(index) = transition_opt {
+            let transition = &self.map.transitions[index];
+
+            match transition.destination {
+                TransitionDestination::Map { to_map, to_location } => {
+                    change_map(&mut self.map, &mut self.rng, to_map, to_location);
+                },
+                TransitionDestination::Shop { index: _ } => {
+                }
+            }
+        }
+
+        self.time += dt;
+        self.tick += 1;
+        self.sound_clock.set(self.tick);
+    }
+
+
+    /**
+     * Runs the simulation for the given number of seconds in fixed steps.
+     */
+    pub fn run(&mut self, seconds: f32, dt: f32)
+    {
+        let steps = (seconds / dt).ceil() as u64;
+
+        for _i in 0 .. steps {
+            self.update(dt);
+        }
+    }
+
+
+    /**
+     * Applies recorded input the way the game would without any open view.
+     * Mouse clicks move and attack, or respawn the player after death.
+     *
+     * There is no user interface here, so keys are ignored: they only open
+     * and close views, or save and load the game. A session in which the
+     * player used the inventory, shop or character sheet, or loaded a game,
+     * can only be replayed by the windowed game.
+     */
+    pub fn apply_input(&mut self, event: &InputEvent)
+    {
+        match event {
+            InputEvent::MouseMove(event) => {
+                self.mouse_position = [event.mx, event.my];
+            },
+            InputEvent::Button(event) => {
+                if event.args.state == ButtonState::Release {
+                    if self.map.is_player_dead() {
+                        // like the death screen, a click brings the player back to town
+                        if event.args.button == Button::Mouse(MouseButton::Left) {
+                            respawn_player(&mut self.map, &mut self.player_inventory, &mut self.rng);
+                        }
+                    }
+                    else {
+                        click_world(&mut self.map, &self.player_inventory, &mut self.speaker, &event.args.button,
+                                    &self.mouse_position, &self.window_size);
+                    }
+                }
+            },
+            InputEvent::Scroll(_event) => {
+            }
+        }
+    }
+
+
+    /**
+     * Plays a recorded session until its end tick. The world must
+     * have been created with the seed of the recording.
+     */
+    pub fn replay(&mut self, recording: Recording)
+    {
+        self.window_size = recording.window_size;
+
+        let mut replay = Replay::new(recording);
+
+        while !replay.is_finished(self.tick) {
+            for event in replay.inputs_for_tick(self.tick) {
+                self.apply_input(&event);
+            }
+
+            self.update(HEADLESS_DT);
+        }
+    }
+
+
+    pub fn count_creatures(&self) -> usize
+    {
+        let mut count = 0;
+
+        for mob in self.map.layers[MAP_OBJECT_LAYER].values() {
+            if mob.creature.is_some() && mob.uid != self.map.player_id {
+                count += 1;
+            }
+        }
+
+        count
+    }
+
+
+    pub fn print_summary(&self)
+    {
+        let mut items = 0;
+        let hits = self.sound_log.borrow().iter().filter(|record| record.is(Sound::FireballHit)).count();
+
+        for mob in self.map.layers[MAP_OBJECT_LAYER].values() {
+            if mob.item.is_some() {
+                items += 1;
+            }
+        }
+
+        println!("Simulated {:.2}s: {} creatures and {} items on the map, player at {:?}, {} items in the inventory, {} projectile hits",
+                 self.time, self.count_creatures(), items,
+                 self.map.get_player_position(), self.player_inventory.bag.len(),
+                 hits);
+    }
+}
+
+
+/**
+ * Entry point for the --headless command line switch.
+ */
+pub fn run_headless(seconds: f32, seed: u64)
+{
+    let mut world = exit_on_error(HeadlessWorld::new(seed));
+
+    world.run(seconds, HEADLESS_DT);
+    world.print_summary();
+}
+
+
+/**
+ * Plays a recorded session without window and prints the resulting state.
+ */
+pub fn run_replay(path: &Path)
+{
+    let recording = match Recording::read(path) {
+        Ok(recording) => recording,
+        Err(message) => {
+            println!("Error: {}", message);
+            std::process::exit(1);
+        }
+    };
+
+    let mut world = exit_on_error(HeadlessWorld::new(recording.seed));
+
+    world.replay(recording);
+    world.print_summary();
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::fire_projectile;
+
+
+    #[test]
+    fn test_headless_run() {
+        let mut world = HeadlessWorld::new(12345678901).unwrap();
+        let creatures = world.count_creatures();
+
+        assert!(creatures > 0);
+
+        world.run(5.0, HEADLESS_DT);
+
+        assert!(world.time >= 5.0);
+        assert!(world.count_creatures() <= creatures);
+    }
+
+
+    #[test]
+    fn test_fireball_hit_sound() {
+        let mut world = HeadlessWorld::new(12345678901).unwrap();
+        world.sound_log.borrow_mut().clear();
+
+        // stand right next to a creature and shoot at it
+        let mut target = [0.0, 0.0];
+        for mob in world.map.layers[MAP_OBJECT_LAYER].values() {
+            if mob.creature.is_some() && mob.uid != world.map.player_id {
+                target = mob.position;
+                break;
+            }
+        }
+
+        world.map.set_player_position([target[0] - 60.0, target[1]]);
+        fire_projectile(&mut world.map, "Fireball", target, 24.0, &mut world.speaker);
+        world.run(0.5, HEADLESS_DT);
+
+        let log = world.sound_log.borrow();
+        assert!(log[0].is(Sound::FireballLaunch));
+        assert!(log.iter().any(|record| record.is(Sound::FireballHit)));
+    }
+
+
+    #[test]
+    fn test_same_seed_same_game() {
+        let mut first = HeadlessWorld::new(4711).unwrap();
+        let mut second = HeadlessWorld::new(4711).unwrap();
+
+        first.run(3.0, HEADLESS_DT);
+        second.run(3.0, HEADLESS_DT);
+
+        let positions = |world: &HeadlessWorld| -> Vec<(u64, [f32; 2])> {
+            world.map.layers[MAP_OBJECT_LAYER].values().map(|mob| (mob.uid, mob.position)).collect()
+        };
+
+        assert_eq!(positions(&first), positions(&second));
+        assert_eq!(first.map.walkable, second.map.walkable);
+        assert_eq!(*first.sound_log.borrow(), *second.sound_log.borrow());
+
+        // and a different seed gives a different dungeon
+        let other = HeadlessWorld::new(4712).unwrap();
+        assert_ne!(other.map.walkable, first.map.walkable);
+    }
+}
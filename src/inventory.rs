@@ -1,10 +1,12 @@
 use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+
 use crate::item::Item;
 use crate::item::ItemKind;
 use crate::ItemFactory;
 use crate::ui::*;
 
-#[derive(PartialEq, Eq, Debug, Hash, Clone, Copy)]
+#[derive(PartialEq, Eq, Debug, Hash, Clone, Copy, Serialize, Deserialize)]
 pub enum Slot {
     OnCursor = 0,
     Bag = 1,
@@ -18,7 +20,7 @@ pub enum Slot {
     RRing = 9,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Entry {
     pub item_id: u64,
     pub slot: Slot,
@@ -26,7 +28,7 @@ pub struct Entry {
     pub location_y: i32,    
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Inventory {
 
     // Keeps all the items (owns all the items)
@@ -139,6 +141,17 @@ impl Inventory {
     }
     
 
+    /**
+     * @return The item which is worn in this slot, if there is one
+     */
+    pub fn equipped_item(&self, slot: Slot) -> Option<&Item>
+    {
+        self.entries.iter()
+            .find(|entry| entry.slot == slot)
+            .and_then(|entry| self.bag.get(&entry.item_id))
+    }
+
+
     /**
      * @return The total amount of curency in this inventoy, measured in copper coins
      */ 
@@ -289,7 +302,7 @@ impl Inventory {
         }        
 
         // and add the 100 copper coins
-        let mut copper_coins = item_factory.create_base("copper_coin");
+        let mut copper_coins = item_factory.create_base("copper_coin").expect("coins are required items");
         copper_coins.stack_size = 100;
         self.put_item(copper_coins, Slot::Bag);
     }
@@ -1,14 +1,21 @@
-use core::str::Split;
 use std::fmt::Formatter;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use rand::Rng;
+use serde::{Serialize, Deserialize};
 
 use crate::inventory::Slot;
-use crate::read_lines;
-use crate::parse_rgba;
+use crate::loader::CsvFile;
+use crate::loader::CsvRow;
+use crate::loader::LoaderError;
 
 
-#[derive(Clone, Debug, PartialEq)]
+const ITEMS_FILE: &str = "resources/items/items.csv";
+
+// the game itself creates these, so items.csv must have them
+const REQUIRED_ITEMS: [&str; 2] = ["copper_coin", "silver_coin"];
+
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Activation
 {
     None,
@@ -27,10 +34,23 @@ impl Activation
             Activation::LightningStrike => "Activation: Lightning Strike",
         }
     }
+
+
+    /**
+     * @return The key of the projectile in projectiles.csv which this activation fires
+     */
+    pub fn projectile_key(&self) -> Option<&str> {
+        match self {
+            Activation::None => None,
+            Activation::Fireball => Some("Fireball"),
+            Activation::FrostBolt => Some("Frost bolt"),
+            Activation::LightningStrike => Some("Lightning strike"),
+        }
+    }
 }
 
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum DropEffect
 {
     None,
@@ -40,7 +60,7 @@ pub enum DropEffect
 }
 
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum ItemKind
 {
     Misc,
@@ -102,7 +122,7 @@ pub struct ItemPrototype
 }
 
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Item 
 {   
     // the ID must be unique in a game
@@ -204,75 +224,73 @@ pub struct ItemFactory
 {
     next_id: u64,
 
-    proto_items: HashMap<String, ItemPrototype>,
-    proto_mods: HashMap<String, ModPrototype>,
+    // sorted, so random picks don't depend on hash order
+    proto_items: BTreeMap<String, ItemPrototype>,
+    proto_mods: BTreeMap<String, ModPrototype>,
 }
 
 
 impl ItemFactory 
 {
-    pub fn new() -> ItemFactory 
+    pub fn new() -> Result<ItemFactory, LoaderError> 
     {
-        let proto_mods = read_proto_mods();
-        let proto_items = read_proto_items(&proto_mods);
+        let proto_mods = read_proto_mods()?;
+        let proto_items = read_proto_items(&proto_mods)?;
 
-        ItemFactory {
+        for key in REQUIRED_ITEMS {
+            if !proto_items.contains_key(key) {
+                return Err(unknown_item(key));
+            }
+        }
+
+        Ok(ItemFactory {
             next_id: 0,
             proto_items,
             proto_mods,
-        }
+        })
     }
 
 
-    pub fn create_base(&mut self, key: &str) -> Item 
+    pub fn prototypes(&self) -> impl Iterator<Item = &ItemPrototype>
     {
-        let proto_opt = self.proto_items.get(key);
-        
-        if proto_opt.is_none() {
-            panic!("Unknown item '{}'", key);
-        }
-        else {
-            let id = self.next_id;
-            self.next_id += 1;
-            let proto = proto_opt.unwrap();
-
-            Item {
-                id, 
-                key: proto.key.to_string(),
-                singular: proto.singular.to_string(),
-                plural: proto.plural.to_string(),
-                show_type: proto.show_type,
-                mods: Vec::new(),
-
-                inventory_tile_id: proto.inventory_tile_id,
-                inventory_w: proto.inventory_w,
-                inventory_h: proto.inventory_h,
-                inventory_scale: proto.inventory_scale,
-                map_scale: proto.map_scale,
-                color: proto.color,
-                ilvl: proto.ilvl,
-                kind: proto.kind.clone(),
-            
-                map_tile_id: proto.map_tile_id,
-                stack_size: 1,
-                max_stack_size: proto.max_stack_size,
-                base_price: proto.base_price,
-
-                activation: proto.activation.clone(),
-                drop_effect: proto.drop_effect.clone(),
-                description: proto.description.to_string(),
-            }
-        }
+        self.proto_items.values()
+    }
+
+
+    pub fn next_id(&self) -> u64
+    {
+        self.next_id
     }
 
 
-    pub fn create<R: Rng + ?Sized>(&mut self, key: &str, rng: &mut R) -> Item 
+    /**
+     * Must be called after loading a savegame, so new items
+     * don't reuse the ids of loaded ones.
+     */
+    pub fn set_next_id(&mut self, next_id: u64)
     {
-        let mut item = self.create_base(key);
-        let proto = self.proto_items.get(key).unwrap();
+        self.next_id = next_id;
+    }
+
+
+    pub fn create_base(&mut self, key: &str) -> Result<Item, LoaderError>
+    {
+        let proto = self.proto_items.get(key).ok_or_else(|| unknown_item(key))?;
+        let item = new_item(self.next_id, proto);
+        self.next_id += 1;
+
+        Ok(item)
+    }
+
+
+    pub fn create<R: Rng + ?Sized>(&mut self, key: &str, rng: &mut R) -> Result<Item, LoaderError>
+    {
+        let proto = self.proto_items.get(key).ok_or_else(|| unknown_item(key))?;
+        let mut item = new_item(self.next_id, proto);
         item.mods = process_proto_mods(&proto.mods, rng); // proto.mods.clone()
+        self.next_id += 1;
 
-        item
+        Ok(item)
     }
 
 
@@ -284,14 +302,16 @@ impl ItemFactory
             // ilvl 0 means "do not generate randomly"
             // these items can still be generated by their key
             if proto.ilvl > 0 && proto.ilvl >= max_level {
-                matches.push(proto.key.clone());
+                matches.push(proto);
             }
         }
 
         // pick a random one
-        let index = rng.random_range(0 .. matches.len());
+        let proto = matches[rng.random_range(0 .. matches.len())];
 
-        let item = self.create(&matches[index], rng);
+        let mut item = new_item(self.next_id, proto);
+        item.mods = process_proto_mods(&proto.mods, rng);
+        self.next_id += 1;
 
         item
     }
@@ -378,14 +398,53 @@ impl ItemFactory
 }
 
 
-fn read_proto_items(proto_mods: &HashMap<String, ModPrototype>) -> HashMap<String, ItemPrototype> 
+fn new_item(id: u64, proto: &ItemPrototype) -> Item
+{
+    Item {
+        id, 
+        key: proto.key.to_string(),
+        singular: proto.singular.to_string(),
+        plural: proto.plural.to_string(),
+        show_type: proto.show_type,
+        mods: Vec::new(),
+
+        inventory_tile_id: proto.inventory_tile_id,
+        inventory_w: proto.inventory_w,
+        inventory_h: proto.inventory_h,
+        inventory_scale: proto.inventory_scale,
+        map_scale: proto.map_scale,
+        color: proto.color,
+        ilvl: proto.ilvl,
+        kind: proto.kind.clone(),
+    
+        map_tile_id: proto.map_tile_id,
+        stack_size: 1,
+        max_stack_size: proto.max_stack_size,
+        base_price: proto.base_price,
+
+        activation: proto.activation.clone(),
+        drop_effect: proto.drop_effect.clone(),
+        description: proto.description.to_string(),
+    }
+}
+
+
+fn unknown_item(key: &str) -> LoaderError
+{
+    LoaderError {
+        text: Some(key.to_string()),
+        .. LoaderError::new(ITEMS_FILE, "unknown item")
+    }
+}
+
+
+fn read_proto_items(proto_mods: &BTreeMap<String, ModPrototype>) -> Result<BTreeMap<String, ItemPrototype>, LoaderError> 
 {
-    let lines = read_lines("resources/items/items.csv");
-    let mut proto_items: HashMap<String, ItemPrototype> = HashMap::new();
+    let file = CsvFile::read(ITEMS_FILE)?;
+    let mut proto_items: BTreeMap<String, ItemPrototype> = BTreeMap::new();
 
-    for i in 1..lines.len() {
-        let mut parts = lines[i].split(",");
-        let key = parts.next().unwrap().to_string();
+    for mut row in file.rows() {
+        let key = row.next_str()?.to_string();
         
         // ignore empty lines, they are just to separate sections
         if key.len() > 0 {
@@ -393,59 +452,58 @@ fn read_proto_items(proto_mods: &HashMap<String, ModPrototype>) -> HashMap<Strin
                 key.to_string(),
                 ItemPrototype {
                     key,
-                    singular: parts.next().unwrap().to_string(),
-                    plural:  parts.next().unwrap().to_string(),
-                    show_type: "yes" == parts.next().unwrap(), 
-                    inventory_tile_id: parts.next().unwrap().parse::<usize>().unwrap(),
-                    map_tile_id: parts.next().unwrap().parse::<usize>().unwrap(),
-                    inventory_w: parts.next().unwrap().parse::<i32>().unwrap(),
-                    inventory_h: parts.next().unwrap().parse::<i32>().unwrap(),
-                    inventory_scale: parts.next().unwrap().parse::<f32>().unwrap(),
-                    map_scale: parts.next().unwrap().parse::<f32>().unwrap(),
-                    color: parse_rgba(parts.next().unwrap()),
-                    ilvl: parts.next().unwrap().parse::<u32>().unwrap(),
-                    kind: parse_item_type(parts.next().unwrap()),
-                    max_stack_size: parts.next().unwrap().parse::<u32>().unwrap(),
-                    base_price: parts.next().unwrap().parse::<u32>().unwrap(),
-
-                    drop_effect: parse_drop_effect(parts.next().unwrap()),
-                    mods: parse_mods(&mut parts, proto_mods),
+                    singular: row.next_str()?.to_string(),
+                    plural:  row.next_str()?.to_string(),
+                    show_type: "yes" == row.next_str()?, 
+                    inventory_tile_id: row.next_usize()?,
+                    map_tile_id: row.next_usize()?,
+                    inventory_w: row.next_i32()?,
+                    inventory_h: row.next_i32()?,
+                    inventory_scale: row.next_f32()?,
+                    map_scale: row.next_f32()?,
+                    color: row.next_rgba()?,
+                    ilvl: row.next_u32()?,
+                    kind: row.next_with(parse_item_type)?,
+                    max_stack_size: row.next_u32()?,
+                    base_price: row.next_u32()?,
+
+                    drop_effect: row.next_with(parse_drop_effect)?,
+                    mods: parse_mods(&mut row, proto_mods)?,
                     activation: Activation::None,
-                    description: parts.next().unwrap().to_string(),
+                    description: if row.has_more() {row.next_str()?.to_string()} else {String::new()},
                 }
             );
         }
     }
 
-    proto_items
+    Ok(proto_items)
 }
 
 
-fn read_proto_mods() -> HashMap<String, ModPrototype> 
+fn read_proto_mods() -> Result<BTreeMap<String, ModPrototype>, LoaderError> 
 {
-    let lines = read_lines("resources/items/modifiers.csv");
-    let mut proto_items: HashMap<String, ModPrototype> = HashMap::new();
+    let file = CsvFile::read("resources/items/modifiers.csv")?;
+    let mut proto_items: BTreeMap<String, ModPrototype> = BTreeMap::new();
 
-    for i in 1..lines.len() {
-        let mut parts = lines[i].split(",");
-        let key = parts.next().unwrap().to_string();
+    for mut row in file.rows() {
+        let key = row.next_str()?.to_string();
         
         // ignore empty lines, they are just to separate sections
         if key.len() > 0 {
             proto_items.insert(
                 key.to_string(),
                 ModPrototype {
-                    attribute: parse_attribute(parts.next().unwrap()),
-                    min_value: parts.next().unwrap().parse::<i32>().unwrap(),
-                    max_value: parts.next().unwrap().parse::<i32>().unwrap(),
-                    unit: parse_unit(parts.next().unwrap()),
-                    ilvl: parts.next().unwrap().parse::<u32>().unwrap(),
+                    attribute: row.next_with(parse_attribute)?,
+                    min_value: row.next_i32()?,
+                    max_value: row.next_i32()?,
+                    unit: row.next_with(parse_unit)?,
+                    ilvl: row.next_u32()?,
                 }
             );
         }
     }
 
-    proto_items
+    Ok(proto_items)
 }
 
 
@@ -470,135 +528,101 @@ fn calc_slot(v: i32) -> Slot
 }
 
 
-fn parse_mods(parts: &mut Split<&str>, proto_mods: &HashMap<String, ModPrototype>) -> Vec<ModPrototype>
+fn parse_mods(row: &mut CsvRow, proto_mods: &BTreeMap<String, ModPrototype>) -> Result<Vec<ModPrototype>, LoaderError>
 {
     let mut result = Vec::new();
 
-    loop {
-        let key = parts.next();
-        if key.is_some() {
-            let key = key.unwrap();
+    while row.has_more() {
+        let key = row.next_str()?;
 
-            match key {
-                "info" => {
-                    // info must be the last key and it's not a mod, 
-                    // so we stop parsing mods here
-                    break;
-                }
-                "" => {
-                    // end of data
-                    break;
-                }
-                _ => {
-                    let mod_opt = proto_mods.get(key);
-                    if mod_opt.is_some() {
-                        result.push(mod_opt.unwrap().clone());
-                    }
-                    else {
-                        panic!("parse_mods: unknown modifier key found: '{}'", key);
-                    }
-                } 
+        match key {
+            "info" => {
+                // info must be the last key and it's not a mod, 
+                // so we stop parsing mods here
+                break;
             }
-        }
-        else {
-            // out of data
-            break;
+            "" => {
+                // end of data
+                break;
+            }
+            _ => {
+                let mod_opt = proto_mods.get(key);
+                match mod_opt {
+                    Some(proto_mod) => result.push(proto_mod.clone()),
+                    None => return Err(row.error("unknown modifier key", key)),
+                }
+            } 
         }
     }
 
-    result
+    Ok(result)
 }
 
 
-fn parse_attribute(input: &str) -> Attribute
+fn parse_attribute(input: &str) -> Result<Attribute, String>
 {
     match input {
-        "res_fire" => Attribute::ResFire,
-        "res_light" => Attribute::ResLight,
-        "res_cold" => Attribute::ResCold,
-        "spell_dam" => Attribute::SpellDamage,
-        "phys_dam" => Attribute::PhysicalDamage,
-        _ => panic!("parse_attribute: unknown attribute {}", input),
+        "res_fire" => Ok(Attribute::ResFire),
+        "res_light" => Ok(Attribute::ResLight),
+        "res_cold" => Ok(Attribute::ResCold),
+        "spell_dam" => Ok(Attribute::SpellDamage),
+        "phys_dam" => Ok(Attribute::PhysicalDamage),
+        "ignite_chance" => Ok(Attribute::IgniteChance),
+        "chill_chance" => Ok(Attribute::ChillChance),
+        "shock_chance" => Ok(Attribute::ShockChance),
+        _ => Err("unknown attribute".to_string()),
     }
 }
 
 
-fn parse_unit(input: &str) -> Unit
+fn parse_unit(input: &str) -> Result<Unit, String>
 {
     match input {
-        "%" => Unit::Percent,
-        _ => Unit::Integer,
-    }
-}
-
-
-fn parse_range(input: &str) -> (i32, i32) 
-{
-    if input.contains("-") {
-        let mut parts = input.split("-");
-        let min_value = parts.next().unwrap().parse::<i32>().unwrap();
-        let max_value = parts.next().unwrap().parse::<i32>().unwrap();
-        (min_value, max_value)
-    }
-    else {
-        let value = input.parse::<i32>().unwrap();
-        (value, value)
+        "%" => Ok(Unit::Percent),
+        "" => Ok(Unit::Integer),
+        _ => Err("unknown unit".to_string()),
     }
 }
 
 
-fn parse_drop_effect(input: &str) -> DropEffect
+fn parse_drop_effect(input: &str) -> Result<DropEffect, String>
 {
     if "enchant_fireball" == input {
-        DropEffect::EnchantFireball
+        Ok(DropEffect::EnchantFireball)
     }
     else if "enchant_frost_bolt" == input {
-        DropEffect::EnchantFrostBolt
+        Ok(DropEffect::EnchantFrostBolt)
     }
     else if "enchant_lightning_strike" == input {
-        DropEffect::EnchantLightningStrike
+        Ok(DropEffect::EnchantLightningStrike)
     }
     else if "" == input {
-        DropEffect::None
+        Ok(DropEffect::None)
     }
     else {
-        panic!("Unknown drop effect '{}'", input);
+        Err("unknown drop effect".to_string())
     }
 }
 
 
-fn parse_item_type(input: &str) -> ItemKind
+fn parse_item_type(input: &str) -> Result<ItemKind, String>
 {
-    if "wand" == input {
-        return ItemKind::Wand;
-    }
-    else if "ring" == input {
-        return ItemKind::Ring;
-    }
-    else if "bow" == input {
-        return ItemKind::Bow;
-    }
-    else if "amulet" == input {
-        return ItemKind::Amulet;
-    }
-    else if "scroll" == input {
-        return ItemKind::Scroll;
-    }
-    else if "currency" == input {
-        return ItemKind::Currency;
-    }
-    else if "plugin" == input {
-        return ItemKind::Plugin;
-    }
-    else {
-        println!("parse_item_type: Unknown item type '{}'", input);
-        return ItemKind::Misc;
+    match input {
+        "wand" => Ok(ItemKind::Wand),
+        "ring" => Ok(ItemKind::Ring),
+        "bow" => Ok(ItemKind::Bow),
+        "amulet" => Ok(ItemKind::Amulet),
+        "scroll" => Ok(ItemKind::Scroll),
+        "currency" => Ok(ItemKind::Currency),
+        "plugin" => Ok(ItemKind::Plugin),
+        "misc" => Ok(ItemKind::Misc),
+        _ => Err("unknown item type".to_string()),
     }
 }
 
 
 #[allow(dead_code)]
-#[derive(PartialEq, Eq, Debug, Clone)]
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
 pub enum Attribute {
 
     Agility,
@@ -609,6 +633,9 @@ pub enum Attribute {
     ResFire,
     ResLight,
     ResCold,
+    IgniteChance,
+    ChillChance,
+    ShockChance,
 }
 
 
@@ -625,6 +652,9 @@ impl std::fmt::Display for Attribute
             Attribute::ResFire => "Fire Resistance",
             Attribute::ResLight => "Lightning Resistance",
             Attribute::ResCold => "Cold Resistance",
+            Attribute::IgniteChance => "Chance to Ignite",
+            Attribute::ChillChance => "Chance to Chill",
+            Attribute::ShockChance => "Chance to Shock",
         };
 
         write!(f, "{}", name)
@@ -632,7 +662,7 @@ impl std::fmt::Display for Attribute
 }
 
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
 pub enum Unit {
     Percent,
     Integer,
@@ -652,7 +682,7 @@ impl std::fmt::Display for Unit {
 }
 
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum ModKind {
     Implicit,      // part of the item itself
     Echanted,      // These can be added/removed/modified 
@@ -669,7 +699,7 @@ pub struct ModPrototype {
 }
 
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Mod {
     pub attribute: Attribute,
     pub min_value: i32,
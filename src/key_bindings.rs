@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use glium::winit::keyboard::Key;
+use glium::winit::keyboard::NamedKey;
+
+use crate::ui::Button;
+use crate::read_lines;
+use crate::loader::LoaderError;
+
+
+/**
+ * The user settings for the keyboard. If the file is missing, the
+ * default bindings are used.
+ */
+pub const KEY_BINDINGS_FILE: &str = "key_bindings.cfg";
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    EditorMode,
+    GameMode,
+
+    ToggleInventory,
+    ToggleCharacter,
+    CloseViews,
+    QuickSave,
+    QuickLoad,
+
+    EditorHelp,
+    EditorGroundLayer,
+    EditorObjectLayer,
+    EditorCloudLayer,
+    EditorMoveRight,
+    EditorMoveLeft,
+    EditorMoveUp,
+    EditorMoveDown,
+    EditorTileSelector,
+    EditorColor,
+    EditorBlendAdd,
+    EditorBlendMix,
+    EditorDelete,
+    EditorParticles,
+    EditorLoad,
+    EditorSave,
+}
+
+
+// name in the settings file, default key, description for help texts
+const ACTIONS: [(Action, &str, &str, &str); 23] = [
+    (Action::EditorMode, "editor_mode", "e", "Switch to the map editor"),
+    (Action::GameMode, "game_mode", "g", "Switch to game mode"),
+
+    (Action::ToggleInventory, "toggle_inventory", "i", "Show/hide the inventory"),
+    (Action::ToggleCharacter, "toggle_character", "c", "Show/hide the character sheet"),
+    (Action::CloseViews, "close_views", "Escape", "Close inventory, character sheet and shop"),
+    (Action::QuickSave, "quick_save", "F5", "Save the game"),
+    (Action::QuickLoad, "quick_load", "F9", "Load the saved game"),
+
+    (Action::EditorHelp, "editor_help", "F1", "Show/hide this list"),
+    (Action::EditorGroundLayer, "editor_ground_layer", "1", "Select the ground layer"),
+    (Action::EditorObjectLayer, "editor_object_layer", "2", "Select the object layer"),
+    (Action::EditorCloudLayer, "editor_cloud_layer", "3", "Select the cloud layer"),
+    (Action::EditorMoveRight, "editor_move_right", "ArrowRight", "Move the selected item right, faster with shift"),
+    (Action::EditorMoveLeft, "editor_move_left", "ArrowLeft", "Move the selected item left, faster with shift"),
+    (Action::EditorMoveUp, "editor_move_up", "ArrowUp", "Move the selected item up, faster with shift"),
+    (Action::EditorMoveDown, "editor_move_down", "ArrowDown", "Move the selected item down, faster with shift"),
+    (Action::EditorTileSelector, "editor_tile_selector", "Space", "Open tile selector"),
+    (Action::EditorColor, "editor_color", "c", "Open color selector for selected item"),
+    (Action::EditorBlendAdd, "editor_blend_add", "a", "Set blend mode on selected item to 'Addition'"),
+    (Action::EditorBlendMix, "editor_blend_mix", "m", "Set blend mode on selected item to 'Mix' (default)"),
+    (Action::EditorDelete, "editor_delete", "Delete", "Removes the selected item from the map"),
+    (Action::EditorParticles, "editor_particles", "p", "Place a particle generator"),
+    (Action::EditorLoad, "editor_load", "l", "Load a saved map"),
+    (Action::EditorSave, "editor_save", "s", "Save the map"),
+];
+
+
+// the named keys which can be used in the settings file
+const NAMED_KEYS: [(&str, NamedKey); 27] = [
+    ("Escape", NamedKey::Escape),
+    ("Enter", NamedKey::Enter),
+    ("Tab", NamedKey::Tab),
+    ("Space", NamedKey::Space),
+    ("Backspace", NamedKey::Backspace),
+    ("Delete", NamedKey::Delete),
+    ("Insert", NamedKey::Insert),
+    ("Home", NamedKey::Home),
+    ("End", NamedKey::End),
+    ("PageUp", NamedKey::PageUp),
+    ("PageDown", NamedKey::PageDown),
+    ("ArrowUp", NamedKey::ArrowUp),
+    ("ArrowDown", NamedKey::ArrowDown),
+    ("ArrowLeft", NamedKey::ArrowLeft),
+    ("ArrowRight", NamedKey::ArrowRight),
+    ("F1", NamedKey::F1),
+    ("F2", NamedKey::F2),
+    ("F3", NamedKey::F3),
+    ("F4", NamedKey::F4),
+    ("F5", NamedKey::F5),
+    ("F6", NamedKey::F6),
+    ("F7", NamedKey::F7),
+    ("F8", NamedKey::F8),
+    ("F9", NamedKey::F9),
+    ("F10", NamedKey::F10),
+    ("F11", NamedKey::F11),
+    ("F12", NamedKey::F12),
+];
+
+
+/**
+ * Which key triggers which action.
+ */
+pub struct KeyBindings {
+    keys: HashMap<Action, Key>,
+}
+
+
+impl KeyBindings {
+
+    pub fn defaults() -> KeyBindings {
+
+        let mut keys = HashMap::new();
+
+        for (action, _name, key, _description) in ACTIONS {
+            keys.insert(action, parse_key(key).unwrap());
+        }
+
+        KeyBindings {
+            keys,
+        }
+    }
+
+
+    /**
+     * Reads "action = key" lines, '#' starts a comment. Actions which are
+     * not in the file keep their default key.
+     */
+    pub fn load(path_name: &str) -> Result<KeyBindings, LoaderError> {
+
+        let mut bindings = KeyBindings::defaults();
+
+        if !Path::new(path_name).exists() {
+            return Ok(bindings);
+        }
+
+        let lines = read_lines(path_name)?;
+
+        for (index, line) in lines.iter().enumerate() {
+            let line_no = index + 1;
+            let content = line.split('#').next().unwrap_or("").trim();
+
+            if content.is_empty() {
+                continue;
+            }
+
+            let (name, key_name) = content.split_once('=')
+                .ok_or(LoaderError::at_line(path_name, line_no, "expected 'action = key'"))?;
+
+            let name = name.trim();
+            let key_name = key_name.trim();
+
+            let action = ACTIONS.iter().find(|entry| entry.1 == name)
+                .map(|entry| entry.0)
+                .ok_or(LoaderError {
+                    text: Some(name.to_string()),
+                    .. LoaderError::at_line(path_name, line_no, "unknown action")
+                })?;
+
+            let key = parse_key(key_name)
+                .ok_or(LoaderError {
+                    text: Some(key_name.to_string()),
+                    .. LoaderError::at_line(path_name, line_no, "unknown key, use a single character or a key name like F1 or Escape")
+                })?;
+
+            bindings.keys.insert(action, key);
+        }
+
+        Ok(bindings)
+    }
+
+
+    /**
+     * @return true if the button is the key bound to this action
+     */
+    pub fn is(&self, action: Action, button: &Button) -> bool {
+        match button {
+            Button::Keyboard(key) => self.keys.get(&action) == Some(key),
+            Button::Mouse(_) => false,
+        }
+    }
+
+
+    /**
+     * The bound key in the same notation as in the settings file.
+     */
+    pub fn key_name(&self, action: Action) -> String {
+        match self.keys.get(&action) {
+            Some(Key::Character(text)) => text.to_string(),
+            Some(Key::Named(named)) => {
+                NAMED_KEYS.iter().find(|entry| entry.1 == *named)
+                    .map(|entry| entry.0.to_string())
+                    .unwrap_or(format!("{:?}", named))
+            },
+            _ => "?".to_string(),
+        }
+    }
+
+
+    /**
+     * A line for help texts like "F1: Show/hide this list".
+     */
+    pub fn help_line(&self, action: Action) -> String {
+        let description = ACTIONS.iter().find(|entry| entry.0 == action).map(|entry| entry.3).unwrap_or("");
+        format!("{}: {}", self.key_name(action), description)
+    }
+}
+
+
+fn parse_key(name: &str) -> Option<Key> {
+
+    if let Some(entry) = NAMED_KEYS.iter().find(|entry| entry.0 == name) {
+        return Some(Key::Named(entry.1));
+    }
+
+    if name.chars().count() == 1 {
+        return Some(Key::Character(name.into()));
+    }
+
+    None
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn test_key_bindings() {
+        let defaults = KeyBindings::defaults();
+
+        assert!(defaults.is(Action::ToggleInventory, &Button::Keyboard(Key::Character("i".into()))));
+        assert!(defaults.is(Action::EditorHelp, &Button::Keyboard(Key::Named(NamedKey::F1))));
+        assert!(!defaults.is(Action::EditorHelp, &Button::Keyboard(Key::Named(NamedKey::F2))));
+        assert_eq!(defaults.help_line(Action::EditorSave), "s: Save the map");
+
+        // every action has a default, and the shipped settings are the defaults
+        let shipped = KeyBindings::load(KEY_BINDINGS_FILE).unwrap();
+
+        for (action, _name, _key, _description) in ACTIONS {
+            assert_ne!(defaults.key_name(action), "?");
+            assert_eq!(shipped.key_name(action), defaults.key_name(action));
+        }
+    }
+
+
+    #[test]
+    fn test_load_key_bindings() {
+        let path = std::env::temp_dir().join("rust_and_dust_test_keys.cfg");
+        let path_name = path.display().to_string();
+
+        std::fs::write(&path, "# test\ntoggle_inventory = b\n\nquick_save = F2 # comment\n").unwrap();
+        let bindings = KeyBindings::load(&path_name).unwrap();
+
+        assert_eq!(bindings.key_name(Action::ToggleInventory), "b");
+        assert_eq!(bindings.key_name(Action::QuickSave), "F2");
+        assert_eq!(bindings.key_name(Action::QuickLoad), "F9");
+
+        std::fs::write(&path, "toggle_inventory = b\nfly = x\n").unwrap();
+        let error = KeyBindings::load(&path_name).err().unwrap();
+
+        assert_eq!(error.line, Some(2));
+        assert_eq!(error.text, Some("fly".to_string()));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
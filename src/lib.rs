@@ -0,0 +1,295 @@
+// The game model and everything else which doesn't need the application
+// window. The game and the tools are binaries on top of this library.
+
+use vecmath::{vec2_add, vec2_sub, vec2_len, vec2_scale, Vector2};
+
+use std::fs::read_to_string;
+use std::path::Path;
+use std::collections::HashMap;
+
+pub mod item;
+pub mod creature;
+pub mod creature_ai;
+pub mod character;
+pub mod inventory;
+pub mod projectile;
+pub mod damage;
+pub mod status_effect;
+pub mod combat_text;
+pub mod dungeon;
+pub mod map;
+pub mod map_layer;
+pub mod walk_grid;
+pub mod pathfinding;
+pub mod shop;
+pub mod editor;
+pub mod game;
+pub mod ui;
+pub mod views;
+pub mod sound;
+pub mod particle_driver;
+pub mod animation;
+pub mod mob_group;
+pub mod gfx;
+pub mod voxel_image_generator;
+pub mod headless;
+pub mod map_data;
+pub mod savegame;
+pub mod loader;
+pub mod tile_catalog;
+pub mod rules;
+pub mod validate;
+pub mod replay;
+pub mod key_bindings;
+
+use dungeon::*;
+use map::{Map, MapObject, MAP_GROUND_LAYER, MAP_OBJECT_LAYER, follow_path};
+use pathfinding::find_path;
+use ui::*;
+use item::ItemFactory;
+use item::Activation;
+use inventory::{Inventory, Slot};
+use sound::SoundPlayer;
+use crate::gfx::gl_support::*;
+use crate::savegame::{write_savegame, read_savegame};
+use crate::loader::LoaderError;
+use crate::key_bindings::KeyBindings;
+
+
+pub const MAP_RESOURCE_PATH: &str = "resources/gfx/map/";
+pub const CREATURE_TILESET: usize = 3;
+pub const PROJECTILE_TILESET: usize = 5;
+pub const ANIMATION_TILESET: usize = 7;
+
+// the game world always advances in steps of this length, 100 per second
+pub const SIMULATION_DT: f32 = 0.01;
+
+// used if no seed is given on the command line
+pub const DEFAULT_SEED: u64 = 12345678901;
+
+// Game structures
+
+pub struct GameWorld {
+    pub map: Map,
+    pub layer_tileset: [TileSet; 8],
+
+    pub player_inventory: Inventory,
+
+    pub speaker: SoundPlayer,
+    pub key_bindings: KeyBindings,
+
+    pub rng: rand::rngs::StdRng,
+
+    // object positions before the last simulation step, for render interpolation
+    pub previous_positions: HashMap<u64, Vector2<f32>>,
+}
+
+
+impl GameWorld {
+
+    pub fn save_game(&self, path: &Path) -> Result<(), String> {
+        write_savegame(path, &self.map, &self.player_inventory)
+    }
+
+
+    /**
+     * Replaces map and inventory with the savegame content. The caller
+     * must reload the map textures afterwards.
+     */
+    pub fn load_game(&mut self, path: &Path) -> Result<(), String> {
+        let save = read_savegame(path)?;
+
+        self.map.apply_data(save.map)?;
+        self.map.item_factory.set_next_id(save.next_item_id);
+        self.player_inventory = save.player_inventory;
+
+        Ok(())
+    }
+
+
+    /**
+     * Must be called before each simulation step. Only the object layer
+     * is remembered, ground and clouds don't move.
+     */
+    pub fn remember_positions(&mut self) {
+        self.previous_positions.clear();
+
+        for (uid, mob) in &self.map.layers[MAP_OBJECT_LAYER] {
+            self.previous_positions.insert(*uid, mob.position);
+        }
+    }
+
+
+    /**
+     * Where to draw an object between two simulation steps.
+     *
+     * @param alpha How far the time has advanced towards the next step, 0.0 to 1.0
+     */
+    pub fn display_position(&self, mob: &MapObject, alpha: f32) -> Vector2<f32> {
+
+        match self.previous_positions.get(&mob.uid) {
+            Some(previous) => {
+                let delta = vec2_sub(mob.position, *previous);
+
+                // jumps, e.g. into a new map, must not be smeared over the screen
+                if vec2_len(delta) > 50.0 {
+                    mob.position
+                }
+                else {
+                    vec2_add(*previous, vec2_scale(delta, alpha))
+                }
+            },
+            None => mob.position
+        }
+    }
+}
+
+
+/**
+ * Finds the value of the --seed switch.
+ *
+ * @return The given seed or DEFAULT_SEED
+ */
+pub fn seed_from_args(args: &[String]) -> u64
+{
+    let position = args.iter().position(|arg| arg == "--seed");
+
+    match position.and_then(|index| args.get(index + 1)) {
+        Some(value) => {
+            match value.parse::<u64>() {
+                Ok(seed) => seed,
+                Err(_) => {
+                    println!("Invalid seed '{}', using the default seed {}", value, DEFAULT_SEED);
+                    DEFAULT_SEED
+                }
+            }
+        },
+        None => DEFAULT_SEED
+    }
+}
+
+
+/**
+ * Some inventory contents for testing. The windowed and the headless game
+ * must start alike, or recorded sessions won't replay the same way.
+ */
+pub fn add_starting_items(item_factory: &mut ItemFactory, inventory: &mut Inventory, rng: &mut rand::rngs::StdRng) -> Result<(), LoaderError>
+{
+    let wand = item_factory.create("wooden_wand", rng)?;
+    inventory.put_item(wand, Slot::Bag);
+
+    let mut wand = item_factory.create("engraved_wand", rng)?;
+    wand.activation = Activation::Fireball;
+    inventory.put_item(wand, Slot::RHand);
+
+    let mut coins = item_factory.create("copper_coin", rng)?;
+    coins.stack_size = 1000;
+    inventory.put_item(coins, Slot::Bag);
+
+    let scroll = item_factory.create("fire_ball_scroll", rng)?;
+    inventory.put_item(scroll, Slot::Bag);
+
+    let scroll = item_factory.create("frost_bolt_scroll", rng)?;
+    inventory.put_item(scroll, Slot::Bag);
+
+    Ok(())
+}
+
+
+pub fn move_player(map: &mut Map, screen_direction: Vector2<f32>) 
+{
+    // world coordinates have y components double as large
+    // as screen coordinates
+    let direction = [screen_direction[0], screen_direction[1] * 2.0];
+    let dest = vec2_add(map.get_player_position(), direction);
+
+    walk_player_to(map, dest);
+}
+
+
+/**
+ * Sends the player on the way to the destination, around walls if needed.
+ *
+ * @return false if there is no way to the destination
+ */
+pub fn walk_player_to(map: &mut Map, dest: Vector2<f32>) -> bool
+{
+    let player = map.layers[MAP_OBJECT_LAYER].get_mut(&map.player_id).unwrap();
+
+    let creature = player.creature.as_ref().unwrap();
+
+    match find_path(&map.walk_grid, player.position, dest, creature.collision_radius) {
+        Some(path) => {
+            println!("  walking to {:?} over {} waypoints", dest, path.len());
+
+            let speed = creature.move_speed();
+            follow_path(player, path, speed);
+            true
+        },
+        None => false,
+    }
+}
+
+
+pub fn read_lines(pathname: &str) -> Result<Vec<String>, LoaderError> {
+    let path = Path::new(pathname);    
+    let rs = read_to_string(path).map_err(|e| LoaderError::new(pathname, &e.to_string()))?;
+    let mut lines = Vec::new();
+    
+    for line in rs.lines() {
+        lines.push(line.to_string());
+    }
+
+    Ok(lines)
+}
+
+
+pub fn parse_rgba(color_str: &str) -> Result<[f32; 4], String> {
+    let mut color_iter = color_str.split(" ");
+
+    let mut color: [f32; 4] = [0.0; 4];
+    for channel in &mut color {
+        let value = color_iter.next().and_then(|part| part.parse::<f32>().ok());
+
+        match value {
+            Some(value) => *channel = value,
+            None => return Err("expected four numbers separated by spaces".to_string()),
+        }
+    }
+
+    Ok(color)
+}
+
+
+pub fn screen_to_world_pos(ui: &UI, player_pos: &Vector2<f32>, screen_pos: &Vector2<f32>) -> Vector2<f32>
+{
+    window_to_world_pos(&ui.context.window_size, player_pos, screen_pos)
+}
+
+
+/**
+ * Same as screen_to_world_pos, for callers which have no UI.
+ */
+pub fn window_to_world_pos(window_size: &[u32; 2], player_pos: &Vector2<f32>, screen_pos: &Vector2<f32>) -> Vector2<f32>
+{
+    let rel_mouse_x = screen_pos[0] - (window_size[0] / 2) as f32;
+    let rel_mouse_y = (screen_pos[1] - (window_size[1] / 2) as f32) * 2.0;
+
+    // transform to world coordinates
+    // it is relatrive to player position
+    [rel_mouse_x + player_pos[0], rel_mouse_y + player_pos[1]]
+}
+
+
+pub fn calc_tile_position(position: &Vector2<f32>, foot: Vector2<f32>, scale: f32, player_position: &Vector2<f32>, window_center: &Vector2<f32>) -> [f32; 2] {
+        
+    let mut pos_x = position[0] - player_position[0];
+    let mut pos_y = (position[1] - player_position[1]) * 0.5;  
+    
+    pos_x += window_center[0];
+    pos_y += window_center[1];
+    pos_x += -foot[0] * scale;
+    pos_y += -foot[1] * scale;
+    
+    [pos_x, pos_y]
+}
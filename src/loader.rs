@@ -0,0 +1,291 @@
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::str::FromStr;
+
+use crate::read_lines;
+use crate::parse_rgba;
+
+
+/**
+ * Describes what went wrong while reading a resource file, in
+ * terms a modder can use to find and fix the problem.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoaderError {
+    pub path: String,
+
+    // line numbers start with 1, like in a text editor
+    pub line: Option<usize>,
+    pub column: Option<String>,
+
+    // the offending input, if there is one
+    pub text: Option<String>,
+    pub message: String,
+}
+
+
+impl LoaderError {
+
+    pub fn new(path: &str, message: &str) -> LoaderError {
+        LoaderError {
+            path: path.to_string(),
+            line: None,
+            column: None,
+            text: None,
+            message: message.to_string(),
+        }
+    }
+
+
+    pub fn at_line(path: &str, line: usize, message: &str) -> LoaderError {
+        LoaderError {
+            line: Some(line),
+            .. LoaderError::new(path, message)
+        }
+    }
+}
+
+
+impl Display for LoaderError {
+
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+
+        write!(f, "{}", self.path)?;
+
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+        }
+
+        if let Some(column) = &self.column {
+            write!(f, " column '{}'", column)?;
+        }
+
+        write!(f, ": {}", self.message)?;
+
+        if let Some(text) = &self.text {
+            write!(f, ", got '{}'", text)?;
+        }
+
+        Ok(())
+    }
+}
+
+
+/**
+ * A comma separated file with a header line. The header names the
+ * columns, those names are used in error messages.
+ */
+pub struct CsvFile {
+    pub path: String,
+    pub header: Vec<String>,
+    pub lines: Vec<String>,
+}
+
+
+impl CsvFile {
+
+    pub fn read(path: &str) -> Result<CsvFile, LoaderError> {
+
+        let mut lines = read_lines(path)?;
+
+        if lines.is_empty() {
+            return Err(LoaderError::new(path, "file is empty, expected a header line"));
+        }
+
+        let header = lines.remove(0).split(",").map(|name| name.trim().to_string()).collect();
+
+        Ok(CsvFile {
+            path: path.to_string(),
+            header,
+            lines,
+        })
+    }
+
+
+    /**
+     * A file description for formats which have no header line,
+     * the column names are given by the caller.
+     */
+    pub fn with_columns(path: &str, columns: &[&str]) -> CsvFile {
+        CsvFile {
+            path: path.to_string(),
+            header: columns.iter().map(|name| name.to_string()).collect(),
+            lines: Vec::new(),
+        }
+    }
+
+
+    /**
+     * All data rows, the header is skipped.
+     */
+    pub fn rows(&self) -> impl Iterator<Item = CsvRow<'_>> {
+        // header is line 1
+        self.lines.iter().enumerate().map(|(i, line)| self.row(i + 2, line))
+    }
+
+
+    pub fn row<'a>(&'a self, line: usize, text: &'a str) -> CsvRow<'a> {
+        CsvRow {
+            file: self,
+            line,
+            fields: text.split(",").collect(),
+            next: 0,
+        }
+    }
+}
+
+
+/**
+ * One line of a CsvFile. Columns are consumed from left to right.
+ */
+pub struct CsvRow<'a> {
+    file: &'a CsvFile,
+    pub line: usize,
+    fields: Vec<&'a str>,
+    next: usize,
+}
+
+
+impl <'a> CsvRow<'a> {
+
+    fn column_name(&self, index: usize) -> String {
+        match self.file.header.get(index) {
+            Some(name) if !name.is_empty() => name.to_string(),
+            _ => format!("#{}", index + 1),
+        }
+    }
+
+
+    /**
+     * An error about the column which was read last.
+     */
+    pub fn error(&self, message: &str, text: &str) -> LoaderError {
+        let index = if self.next > 0 {self.next - 1} else {0};
+
+        LoaderError {
+            path: self.file.path.to_string(),
+            line: Some(self.line),
+            column: Some(self.column_name(index)),
+            text: Some(text.to_string()),
+            message: message.to_string(),
+        }
+    }
+
+
+    pub fn has_more(&self) -> bool {
+        self.next < self.fields.len()
+    }
+
+
+    pub fn next_str(&mut self) -> Result<&'a str, LoaderError> {
+
+        match self.fields.get(self.next) {
+            Some(text) => {
+                self.next += 1;
+                Ok(text)
+            },
+            None => {
+                Err(LoaderError {
+                    path: self.file.path.to_string(),
+                    line: Some(self.line),
+                    column: Some(self.column_name(self.next)),
+                    text: None,
+                    message: "value is missing, the line is too short".to_string(),
+                })
+            }
+        }
+    }
+
+
+    /**
+     * @param expected What kind of value the column should have, for the error message
+     */
+    pub fn next_parse<T: FromStr>(&mut self, expected: &str) -> Result<T, LoaderError> {
+        let text = self.next_str()?;
+
+        text.trim().parse::<T>().map_err(|_| self.error(&format!("expected {}", expected), text))
+    }
+
+
+    pub fn next_usize(&mut self) -> Result<usize, LoaderError> {
+        self.next_parse::<usize>("integer")
+    }
+
+
+    pub fn next_i32(&mut self) -> Result<i32, LoaderError> {
+        self.next_parse::<i32>("integer")
+    }
+
+
+    pub fn next_u32(&mut self) -> Result<u32, LoaderError> {
+        self.next_parse::<u32>("integer")
+    }
+
+
+    pub fn next_f32(&mut self) -> Result<f32, LoaderError> {
+        self.next_parse::<f32>("number")
+    }
+
+
+    pub fn next_rgba(&mut self) -> Result<[f32; 4], LoaderError> {
+        let text = self.next_str()?;
+
+        parse_rgba(text).map_err(|message| self.error(&message, text))
+    }
+
+
+    /**
+     * Reads a column with one of a fixed set of keys.
+     */
+    pub fn next_with<T, F>(&mut self, parse: F) -> Result<T, LoaderError>
+        where F: FnOnce(&str) -> Result<T, String> {
+        let text = self.next_str()?;
+
+        parse(text).map_err(|message| self.error(&message, text))
+    }
+}
+
+
+/**
+ * Reports a loading problem and ends the program. Missing or broken game
+ * data can't be worked around, but it should not look like a crash.
+ */
+pub fn exit_on_error<T>(result: Result<T, LoaderError>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(error) => {
+            println!("Error: {}", error);
+            std::process::exit(1);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn test_csv_error_message() {
+        let file = CsvFile {
+            path: "items.csv".to_string(),
+            header: vec!["Key".to_string(), "Ilvl".to_string()],
+            lines: vec!["wand,1".to_string(), "ring,x".to_string()],
+        };
+
+        let mut rows = file.rows();
+
+        let mut row = rows.next().unwrap();
+        assert_eq!(row.next_str().unwrap(), "wand");
+        assert_eq!(row.next_u32().unwrap(), 1);
+
+        let mut row = rows.next().unwrap();
+        row.next_str().unwrap();
+        let error = row.next_u32().unwrap_err();
+        assert_eq!(error.to_string(), "items.csv:3 column 'Ilvl': expected integer, got 'x'");
+
+        let error = row.next_u32().unwrap_err();
+        assert_eq!(error.to_string(), "items.csv:3 column '#3': value is missing, the line is too short");
+    }
+}
@@ -16,12 +16,11 @@ use glium::winit::application::ApplicationHandler;
 use glium::winit::event_loop::ActiveEventLoop;
 use glium::winit::event::WindowEvent;
 use glium::winit::window::{Window, WindowId};
-use glium::winit::keyboard::Key;
 use glium::winit::event::MouseScrollDelta;
-use glium::VertexBuffer;
 use glium::Surface;
 
-use vecmath::{vec2_add, vec2_len, vec2_scale, Vector2};
+use vecmath::{Vector2, vec2_len};
+
 use geo::Polygon;
 use geo::CoordsIter;
 use geo::LineString;
@@ -32,65 +31,26 @@ use std::time::SystemTime;
 use std::time::Duration;
 use std::thread::sleep;
 
-use std::fs::read_to_string;
-use std::path::Path;
 use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::Path;
 
-mod item;
-mod creature;
-mod inventory;
-mod projectile;
-mod dungeon;
-mod map;
-mod shop;
-mod editor;
-mod game;
-mod ui;
-mod views;
-mod sound;
-mod particle_driver;
-mod animation;
-mod mob_group;
-mod gfx;
-mod voxel_image_generator;
-
-use dungeon::*;
-use map::{Map, MAP_GROUND_LAYER, MAP_OBJECT_LAYER, MAP_CLOUD_LAYER, MoveEndAction};
-use ui::*;
-use editor::MapEditor;
-use crate::game::Game;
-use crate::game::find_suitable_creature_positions;
-use item::ItemFactory;
-use item::Activation;
-use inventory::{Inventory, Slot};
-use sound::SoundPlayer;
-use crate::gfx::gl_support::*;
-
-use crate::voxel_image_generator::VoxelImageGenerator;
-use crate::voxel_image_generator::generate_creature;
-
-
-const MAP_RESOURCE_PATH: &str = "resources/gfx/map/";
-const CREATURE_TILESET: usize = 3;
-const PROJECTILE_TILESET: usize = 5;
-const ANIMATION_TILESET: usize = 7;
-
-// Game structures
-
-pub struct GameWorld {
-    map: Map,
-    layer_tileset: [TileSet; 8],
-
-    player_inventory: Inventory,
-
-    speaker: SoundPlayer,
-
-    rng: rand::rngs::StdRng,
-
-    black_texture: Texture2d,
-    map_texture: Option<Texture2d>,
-    map_backdrop: Option<Texture2d>,
-}
+use rust_and_dust_collector::*;
+use rust_and_dust_collector::map::{Map, MapObject, MAP_GROUND_LAYER, MAP_OBJECT_LAYER, MAP_CLOUD_LAYER, MoveEndAction, MobType};
+use rust_and_dust_collector::ui::*;
+use rust_and_dust_collector::editor::MapEditor;
+use rust_and_dust_collector::game::Game;
+use rust_and_dust_collector::game::enter_dungeon;
+use rust_and_dust_collector::loader::{LoaderError, exit_on_error};
+use rust_and_dust_collector::item::ItemFactory;
+use rust_and_dust_collector::inventory::Inventory;
+use rust_and_dust_collector::sound::SoundPlayer;
+use rust_and_dust_collector::gfx::gl_support::*;
+use rust_and_dust_collector::replay::{InputEvent, Recording, Replay};
+use rust_and_dust_collector::key_bindings::{KeyBindings, Action, KEY_BINDINGS_FILE};
+
+use rust_and_dust_collector::voxel_image_generator::VoxelImageGenerator;
+use rust_and_dust_collector::voxel_image_generator::generate_creature;
 
 
 pub struct GameControllers {
@@ -112,41 +72,75 @@ impl GameControllers {
 }
 
 
+/**
+ * The images of the current map. They belong to the window, the
+ * game world only knows their names.
+ */
+struct MapTextures {
+    black: Texture2d,
+    map: Option<Texture2d>,
+    backdrop: Option<Texture2d>,
+}
+
+
 pub struct App {
     ui: UI,
 
     world: GameWorld,
+    map_textures: MapTextures,
     controllers: GameControllers,
 
     voxel_display_test: VoxelImageGenerator,
 
     update_time: SystemTime,
+
+    // simulation time which is due but not yet simulated
+    accumulator: f32,
+
+    // how far the display is between the last and the next simulation step
+    alpha: f32,
+
+    // simulation steps done so far, recorded input refers to these
+    tick: u64,
+
+    recording: Option<Recording>,
+    recording_file: String,
+    replay: Option<Replay>,
+
     need_focus: bool,
 }
 
 
+// longer frames are cut, so a stalled window can't trigger an avalanche of updates
+const MAX_FRAME_TIME: f32 = 0.25;
+
+// size of the health bars over damaged creatures, in screen pixels
+const HEALTH_BAR_WIDTH: f32 = 40.0;
+const HEALTH_BAR_HEIGHT: f32 = 4.0;
+
+
 impl App {
     
-    fn new(window: Window, display: Display<WindowSurface>, program: Program, window_size: [u32; 2]) -> App {
+    fn new(window: Window, display: Display<WindowSurface>, program: Program, window_size: [u32; 2], seed: u64) -> Result<App, LoaderError> {
 
         // let map_image_file = "map_wasteland.png";
         let map_image_file = "map_soft_grass.png";
         let map_backdrop_file = "backdrop_red_blue.png";
 
-        let black_texture = load_texture(&display, &(MAP_RESOURCE_PATH.to_string() + "map_black.png"));
-        // let map_texture = Some(load_texture(&display, &(MAP_RESOURCE_PATH.to_string() + map_image_file)));
-        // let map_backdrop = Some(load_texture(&display, &(MAP_RESOURCE_PATH.to_string() + map_backdrop_file)));
-        let map_texture = None;
-        let map_backdrop = None;
-
-        let ground_tiles = TileSet::load(&display, "resources/gfx/grounds", "map_objects.tica");
-        let decoration_tiles = TileSet::load(&display, "resources/gfx/objects", "map_objects.tica");
-        let item_tiles = TileSet::load(&display, "resources/gfx/items", "items.tica");
-        let cloud_tiles = TileSet::load(&display, "resources/gfx/clouds", "map_objects.tica");
-        let creature_tiles = TileSet::load(&display, "resources/gfx/creatures", "creatures.tica");
-        let player_tiles = TileSet::load(&display, "resources/gfx/players", "players.tica");
-        let projectile_tiles = TileSet::load(&display, "resources/gfx/projectiles", "projectiles.tica");
-        let animation_tiles = TileSet::load(&display, "resources/gfx/animations", "animations.tica");
+        let mut map_textures = MapTextures {
+            black: load_texture(&display, &(MAP_RESOURCE_PATH.to_string() + "map_black.png")),
+            map: None,
+            backdrop: None,
+        };
+
+        let ground_tiles = TileSet::load(&display, "resources/gfx/grounds", "map_objects.tica")?;
+        let decoration_tiles = TileSet::load(&display, "resources/gfx/objects", "map_objects.tica")?;
+        let item_tiles = TileSet::load(&display, "resources/gfx/items", "items.tica")?;
+        let cloud_tiles = TileSet::load(&display, "resources/gfx/clouds", "map_objects.tica")?;
+        let creature_tiles = TileSet::load(&display, "resources/gfx/creatures", "creatures.tica")?;
+        let player_tiles = TileSet::load(&display, "resources/gfx/players", "players.tica")?;
+        let projectile_tiles = TileSet::load(&display, "resources/gfx/projectiles", "projectiles.tica")?;
+        let animation_tiles = TileSet::load(&display, "resources/gfx/animations", "animations.tica")?;
 
         let mut layer_tileset = [
             ground_tiles,
@@ -159,17 +153,13 @@ impl App {
             animation_tiles,
         ];        
 
-        let item_factory = ItemFactory::new();
+        let item_factory = ItemFactory::new()?;
         let player_inventory = Inventory::new();
-        let mut rng = rand::rngs::StdRng::seed_from_u64(12345678901);
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
 
-        let mut map = Map::new("Demo Map", map_image_file, map_backdrop_file, item_factory);
+        let mut map = Map::new("Demo Map", map_image_file, map_backdrop_file, item_factory)?;
         // map.load("town.map");
 
-        // Testing dungeon generation
-        let dungeon = generate_dungeon(&mut map);
-        map.set_player_position(dungeon.start_position);
-
         let mut ui = UI::new(window, display, program, window_size);
         let voxel_display_test = VoxelImageGenerator::new(&ui.display); 
 
@@ -183,24 +173,21 @@ impl App {
                                         "generated_creature_2");
         map.creature_factory.add("generated_creature_2", creature2);
 
-        let creature_positions = find_suitable_creature_positions(&dungeon);
-
-        map.populate("dungeon.csv", &mut rng, creature_positions);
+        // Testing dungeon generation
+        enter_dungeon(&mut map, &mut rng);
 
         let mut world = GameWorld {
             map,
             layer_tileset,
             player_inventory,
             speaker: SoundPlayer::new(),
+            key_bindings: KeyBindings::load(KEY_BINDINGS_FILE)?,
 
             rng,
-
-            black_texture,
-            map_texture,
-            map_backdrop,
+            previous_positions: HashMap::new(),
         };
 
-        Self::load_map_textures(&mut world, &ui.display);
+        Self::load_map_textures(&mut map_textures, &world.map, &ui.display);
         
         let editor = MapEditor::new();
 
@@ -209,29 +196,13 @@ impl App {
         // let shop_bg = load_texture(&ui.display, "resources/gfx/ui/inventory_bg.png");
         let game = Game::new(inventory_bg, inventory_fg, &mut ui, &world.layer_tileset[6]);
 
-        // Some inventory contents for testing
-
-        let wand = world.map.item_factory.create("wooden_wand", &mut world.rng);
-        world.player_inventory.put_item(wand, Slot::Bag);
-
-        let mut wand = world.map.item_factory.create("engraved_wand", &mut world.rng);
-        wand.activation = Activation::Fireball;
-        world.player_inventory.put_item(wand, Slot::RHand);
-
-        let mut coins = world.map.item_factory.create("copper_coin", &mut world.rng);
-        coins.stack_size = 1000;
-        world.player_inventory.put_item(coins, Slot::Bag);
-
-        let scroll = world.map.item_factory.create("fire_ball_scroll", &mut world.rng);
-        world.player_inventory.put_item(scroll, Slot::Bag);
-
-        let scroll = world.map.item_factory.create("frost_bolt_scroll", &mut world.rng);
-        world.player_inventory.put_item(scroll, Slot::Bag);
+        add_starting_items(&mut world.map.item_factory, &mut world.player_inventory, &mut world.rng)?;
 
 
-        App {        
+        Ok(App {        
             ui,
             world,
+            map_textures,
 
             controllers: GameControllers {
                 editor,
@@ -242,14 +213,21 @@ impl App {
             voxel_display_test,
 
             update_time: SystemTime::now(),
+            accumulator: 0.0,
+            alpha: 0.0,
+            tick: 0,
+            recording: None,
+            recording_file: String::new(),
+            replay: None,
             need_focus: true,
-        }
+        })
     }
 
 
     /**
-     * Updates the game world using the time passed since the last update
-     * to determine how much things have moved etc.
+     * Advances the game world in fixed steps of SIMULATION_DT, as many as
+     * the time passed since the last update allows. The remainder is kept
+     * for the next update, so the simulation doesn't depend on the frame rate.
      *
      * @return true if the display must be updated too, false otherwise.
      */
@@ -260,41 +238,111 @@ impl App {
 
         if difference.is_ok() {
             let secs = difference.unwrap().as_secs_f32();
+            self.update_time = now;
+            self.accumulator += secs.min(MAX_FRAME_TIME);
+        }
 
-            // try to limit to 100 updates per second
-            if secs < 0.01 {
-                // too early, sleep a bit
-                sleep(Duration::from_millis(5));
-                return false;
-            }
+        if self.accumulator < SIMULATION_DT {
+            // too early, sleep a bit
+            sleep(Duration::from_millis(2));
+            return false;
+        }
 
-            self.update_time = now;
+        let mut reload = false;
 
-            // println!("seconds: {}", secs);
+        while self.accumulator >= SIMULATION_DT {
+            self.replay_inputs();
 
             let world = &mut self.world;
-            let reload = self.controllers.current().update(world, secs);
+            world.remember_positions();
+            reload |= self.controllers.current().update(world, SIMULATION_DT);
 
-            if reload {
-                Self::load_map_textures(world, &self.ui.display);
-            }
+            self.accumulator -= SIMULATION_DT;
+            self.tick += 1;
+        }
+
+        self.alpha = self.accumulator / SIMULATION_DT;
+
+        if reload {
+            Self::load_map_textures(&mut self.map_textures, &self.world.map, &self.ui.display);
         }
 
         true
     }
 
 
-    fn load_map_textures(world: &mut GameWorld, display: &Display<WindowSurface>,) {
+    /**
+     * Feeds the recorded input for the current tick to the controllers.
+     * Once the replay is over, the player takes control.
+     */
+    fn replay_inputs(&mut self) {
+
+        if let Some(replay) = &mut self.replay {
+            let inputs = replay.inputs_for_tick(self.tick);
+            let finished = replay.is_finished(self.tick);
+
+            for event in inputs {
+                self.dispatch_input(&event);
+            }
 
-        if world.map.map_image_name.len() == 0 && world.map.backdrop_image_name.len() == 0 {
-            world.map_texture = None;
-            world.map_backdrop = None;
+            if finished {
+                println!("Replay finished at tick {}", self.tick);
+                self.replay = None;
+            }
+        }
+    }
+
+
+    /**
+     * All input from the window passes here, so it can be recorded.
+     */
+    fn handle_input(&mut self, event: InputEvent) {
+
+        // a replay must not be disturbed
+        if self.replay.is_some() {
+            return;
+        }
+
+        self.dispatch_input(&event);
+
+        if let Some(recording) = &mut self.recording {
+            recording.record(self.tick, event);
+        }
+    }
+
+
+    fn dispatch_input(&mut self, event: &InputEvent) {
+        match event {
+            InputEvent::Button(event) => self.handle_button_event(event),
+            InputEvent::MouseMove(event) => self.handle_mouse_move_event(event),
+            InputEvent::Scroll(event) => self.handle_scroll_event(event),
+        }
+    }
+
+
+    fn save_recording(&mut self) {
+        if let Some(recording) = &mut self.recording {
+            recording.finish(self.tick);
+
+            match recording.write(Path::new(&self.recording_file)) {
+                Ok(()) => println!("Input recorded to {}", self.recording_file),
+                Err(message) => println!("Could not save the recording: {}", message),
+            }
+        }
+    }
+
+
+    fn load_map_textures(textures: &mut MapTextures, map: &Map, display: &Display<WindowSurface>,) {
+
+        if map.map_image_name.len() == 0 && map.backdrop_image_name.len() == 0 {
+            textures.map = None;
+            textures.backdrop = None;
         }
         else {
-            let map_texture = load_texture(display, &(MAP_RESOURCE_PATH.to_string() + &world.map.map_image_name));
-            let map_backdrop = load_texture(display, &(MAP_RESOURCE_PATH.to_string() + &world.map.backdrop_image_name));
-            world.map_texture = Some(map_texture);
-            world.map_backdrop = Some(map_backdrop);
+            let map_texture = load_texture(display, &(MAP_RESOURCE_PATH.to_string() + &map.map_image_name));
+            let map_backdrop = load_texture(display, &(MAP_RESOURCE_PATH.to_string() + &map.backdrop_image_name));
+            textures.map = Some(map_texture);
+            textures.backdrop = Some(map_backdrop);
         }
     }
 
@@ -306,11 +354,12 @@ impl App {
         // let buffer = build_dynamic_quad_buffer(&self.ui.display);
         let buffer = &self.ui.context.vertex_buffer;
         let world = &self.world;
+        let alpha = self.alpha;
 
         let width = self.ui.context.window_size[0] as f32;
         let height = self.ui.context.window_size[1] as f32;
 
-        let player_position = &world.map.get_player_position();
+        let player_position = &Self::player_display_position(world, alpha);
         let player_x = player_position[0] as f32;
         let player_y = player_position[1] as f32;
 
@@ -326,9 +375,9 @@ impl App {
         target.clear_color(0.0, 1.0, 1.0, 1.0);
         target.clear_depth(1.0);
 
-        match &self.world.map_backdrop {
+        match &self.map_textures.backdrop {
             None => {
-                draw_texture(&self.ui.display, &mut target, &self.ui.program, BlendMode::Blend, &self.world.black_texture, 
+                draw_texture(&self.ui.display, &mut target, &self.ui.program, BlendMode::Blend, &self.map_textures.black, 
                     0.0, 0.0, 1000.0, 1000.0, &WHITE);
             },
             Some(map_backdrop) => {
@@ -338,7 +387,7 @@ impl App {
             }            
         }
 
-        match &self.world.map_texture {
+        match &self.map_textures.map {
             None => {
             },
             Some(map_texture) => {
@@ -348,20 +397,17 @@ impl App {
             }
         }
 
-        let tex_white = &self.ui.context.tex_white;
-
-        
         // draw ground decorations (flat)
-        Self::render_layer(&self.ui.display, &mut target, &self.ui.program, &buffer, world, tex_white, MAP_GROUND_LAYER);
+        self.render_layer(&mut target, MAP_GROUND_LAYER);
 
         // draw decorations (upright things)
-        Self::render_layer(&self.ui.display, &mut target, &self.ui.program, &buffer, world, tex_white, MAP_OBJECT_LAYER);
+        self.render_layer(&mut target, MAP_OBJECT_LAYER);
 
         // draw clouds
-        Self::render_layer(&self.ui.display, &mut target, &self.ui.program, &buffer, world, tex_white, MAP_CLOUD_LAYER);
+        self.render_layer(&mut target, MAP_CLOUD_LAYER);
         
         // Debug: show walkable areas
-        // self.show_walkable_areas(&self.ui.display, &mut target, &self.ui.program, tex_white, &world.map);
+        // self.show_walkable_areas(&self.ui.display, &mut target, &self.ui.program, &self.ui.context.tex_white, &world.map);
 
 
         {
@@ -391,7 +437,6 @@ impl App {
         let difference = now.duration_since(t0);
 
         if difference.is_ok() {
-            self.update_time = now;
             let secs = difference.unwrap().as_secs_f32();
             let s = format!("Time: {:.4}s", secs);
             let font = &self.ui.context.font_normal;
@@ -403,28 +448,30 @@ impl App {
     }
 
 
-    fn render_layer(display: &Display<WindowSurface>, target: &mut Frame, program: &Program,
-                    buffer: &VertexBuffer<Vertex>,
-                    world: &GameWorld, tex_white: &Texture2d, layer_id: usize) 
+    fn render_layer(&self, target: &mut Frame, layer_id: usize) 
     {
+        let display = &self.ui.display;
+        let program = &self.ui.program;
+        let buffer = &self.ui.context.vertex_buffer;
+        let tex_white = &self.ui.context.tex_white;
+        let world = &self.world;
+        let alpha = self.alpha;
+
         let (display_width, display_height) = display.get_framebuffer_dimensions();
         let window_center = [display_width as f32 * 0.5, display_height as f32 * 0.5];
 
-        let pos_frac = &world.map.get_player_position();
+        let pos_frac = &Self::player_display_position(world, alpha);
         let player_position = &[pos_frac[0].floor(), pos_frac[1].floor()];
-        let mut objects = Vec::with_capacity(world.map.layers.len());
-
-        for (_key, mob) in &world.map.layers[layer_id] {
+        let layer = &world.map.layers[layer_id];
 
-            let xd = mob.position[0] - pos_frac[0];
-            let yd = mob.position[1] - pos_frac[1];
-            let distance = xd * xd + yd * yd; 
-            let max = window_center[0] * window_center[0] + window_center[1] * window_center[1];
+        // big objects can reach into the window from far outside
+        let reach = vec2_len(window_center) * 3.0_f32.sqrt();
+        let min = [pos_frac[0] - reach, pos_frac[1] - reach];
+        let max = [pos_frac[0] + reach, pos_frac[1] + reach];
 
-            if distance < max * 3.0 {
-                objects.push(mob);
-            }
-        }
+        let mut objects: Vec<&MapObject> = layer.in_rect(min, max).iter()
+            .map(|uid| layer.get(uid).unwrap())
+            .collect();
 
         
         objects.sort_unstable_by(|a, b| -> Ordering {
@@ -443,6 +490,7 @@ impl App {
 
         for mob in objects {
             let tileset_id = mob.visual.tileset_id;
+            let position = world.display_position(mob, alpha);
 
             // println!("Accessing mob {} with tile {} from tileset {}", mob.uid, mob.visual.current_image_id, tileset_id);
 
@@ -458,7 +506,7 @@ impl App {
                 return;
             }
 
-            let tpos = calc_tile_position(&mob.position, tile.foot, mob.visual.scale, player_position, &window_center);
+            let tpos = calc_tile_position(&position, tile.foot, mob.visual.scale, player_position, &window_center);
 
             draw_texture_wb(target, program, &buffer,
                 mob.visual.blend,
@@ -488,11 +536,43 @@ impl App {
                     &[0.15, 0.2, 0.1, 1.0]);
             }
 
+            // health bars over damaged creatures
+            if mob.mob_type == MobType::Creature &&
+               let Some(creature) = &mob.creature &&
+               creature.hit_points > 0 && creature.hit_points < creature.max_hit_points {
+
+                let health = creature.hit_points as f32 / creature.max_hit_points as f32;
+                let xp = tpos[0] + (tile.size[0] * mob.visual.scale - HEALTH_BAR_WIDTH) * 0.5;
+                let yp = tpos[1] - mob.visual.z_off * mob.visual.scale * 0.5 - HEALTH_BAR_HEIGHT - 4.0;
+
+                draw_texture_wb(target, program, buffer,
+                    BlendMode::Blend,
+                    display_width,
+                    display_height,
+                    tex_white,
+                    xp - 1.0,
+                    yp - 1.0,
+                    (HEALTH_BAR_WIDTH + 2.0) / 16.0,
+                    (HEALTH_BAR_HEIGHT + 2.0) / 16.0,
+                    &[0.0, 0.0, 0.0, 0.7]);
+
+                draw_texture_wb(target, program, buffer,
+                    BlendMode::Blend,
+                    display_width,
+                    display_height,
+                    tex_white,
+                    xp,
+                    yp,
+                    HEALTH_BAR_WIDTH * health / 16.0,
+                    HEALTH_BAR_HEIGHT / 16.0,
+                    &[0.8, 0.1, 0.05, 1.0]);
+            }
+
             // fake shine for glowing projectiles
             if tileset_id == 5 {
 
                 let glow_tile = &world.layer_tileset[2].tiles_by_id[&21]; // cloud set
-                let tpos = calc_tile_position(&mob.position, glow_tile.foot, 0.9, player_position, &window_center);
+                let tpos = calc_tile_position(&position, glow_tile.foot, 0.9, player_position, &window_center);
 
                 draw_texture_wb(target, program, buffer,
                     BlendMode::Add,
@@ -517,7 +597,7 @@ impl App {
 
                         let set = mob.visual.particles.spawn_tile_set;
                         let tile = &world.layer_tileset[set].tiles_by_id.get(&p.tex_id).unwrap();
-                        let tpos = calc_tile_position(&mob.position, tile.foot, mob.visual.scale, player_position, &window_center);
+                        let tpos = calc_tile_position(&position, tile.foot, mob.visual.scale, player_position, &window_center);
 
                         // world coordinates to screen coordinates
                         let xp = p.xpos as f32;
@@ -538,7 +618,28 @@ impl App {
                     }
                 }
             });
-        }    
+        }
+
+        // combat texts are drawn over all objects
+        if layer_id == MAP_OBJECT_LAYER {
+            let font = &self.ui.context.font_small;
+
+            for text in &world.map.combat_texts {
+                let tpos = calc_tile_position(&text.position, [0.0, 0.0], 1.0, player_position, &window_center);
+                let width = font.calc_string_width(&text.text);
+
+                font.draw(display, target, program,
+                          (tpos[0] - width * 0.5) as i32,
+                          (tpos[1] - text.z_off - text.rise()) as i32,
+                          &text.text, &text.faded_color());
+            }
+        }
+    }
+
+
+    fn player_display_position(world: &GameWorld, alpha: f32) -> Vector2<f32> {
+        let player = world.map.layers[MAP_OBJECT_LAYER].get(&world.map.player_id).unwrap();
+        world.display_position(player, alpha)
     }
 
 
@@ -581,14 +682,14 @@ impl App {
         println!("button event = {:?}", event);
 
         if event.args.state == ButtonState::Release {
-            if event.args.button == Button::Keyboard(Key::Character("e".into())) {    
+            if self.world.key_bindings.is(Action::EditorMode, &event.args.button) {
                 self.controllers.edit = true;
                 println!("Switching to editor mode.");
 
                 let player = self.world.map.layers[MAP_OBJECT_LAYER].get_mut(&self.world.map.player_id).unwrap();
                 player.move_end_action = MoveEndAction::None;
             }
-            if event.args.button == Button::Keyboard(Key::Character("g".into())) {                        
+            if self.world.key_bindings.is(Action::GameMode, &event.args.button) {
                 self.controllers.edit = false;
                 println!("Switching to game mode.");
                 
@@ -626,89 +727,12 @@ impl App {
 }
 
 
-fn move_player(map: &mut Map, screen_direction: Vector2<f32>) 
-{
-    // world coordinates have y components double as large
-    // as screen coordinates
-    let direction = [screen_direction[0], screen_direction[1] * 2.0];
-    
-    let distance = vec2_len(direction);
-
-    let dest;
-    let time;
-
-    {
-        let player = map.layers[MAP_OBJECT_LAYER].get(&map.player_id).unwrap();
-        let attributes = player.creature.as_ref().unwrap();
-        
-        time = distance / attributes.base_speed; // pixel per second
-        dest = vec2_add(player.position, direction);
-    }
-
-    if map.is_walkable(dest) {
-        let player = map.layers[MAP_OBJECT_LAYER].get_mut(&map.player_id).unwrap();
-
-        player.move_time_total = time;
-        player.move_time_left = time;
-        player.velocity = vec2_scale(direction, 1.0/time);
-
-        let d = player.visual.orient(direction);
-        player.visual.current_image_id = player.visual.base_image_id + d;
-
-        println!("  moving {} pixels over {} seconds, destination is {:?}", distance, time, dest);        
-    }
-}
-
-
-pub fn read_lines(pathname: &str) -> Vec<String> {
-    let path = Path::new(pathname);    
-    let rs = read_to_string(path).unwrap();
-    let mut lines = Vec::new();
-    
-    for line in rs.lines() {
-        lines.push(line.to_string());
-    }
-
-    lines
-}
-
-
-pub fn parse_rgba(color_str: &str) -> [f32; 4] {
-    let mut color_iter = color_str.split(" ");
-
-    let mut color: [f32; 4] = [0.0; 4];
-    for i in 0..4 {
-        color[i] = color_iter.next().unwrap().parse::<f32>().unwrap();
-    }
-
-    color
-}
-
-
-pub fn screen_to_world_pos(ui: &UI, player_pos: &Vector2<f32>, screen_pos: &Vector2<f32>) -> Vector2<f32>
-{
-    let rel_mouse_x = screen_pos[0] - (ui.context.window_size[0] / 2) as f32;
-    let rel_mouse_y = (screen_pos[1] - (ui.context.window_size[1] / 2) as f32) * 2.0;
-
-    // transform to world coordinates
-    // it is relatrive to player position
-    let world_pos = [rel_mouse_x + player_pos[0], rel_mouse_y + player_pos[1]];
-
-    world_pos
-}
-
-
-pub fn calc_tile_position(position: &Vector2<f32>, foot: Vector2<f32>, scale: f32, player_position: &Vector2<f32>, window_center: &Vector2<f32>) -> [f32; 2] {
-        
-    let mut pos_x = position[0] - player_position[0];
-    let mut pos_y = (position[1] - player_position[1]) * 0.5;  
-    
-    pos_x += window_center[0];
-    pos_y += window_center[1];
-    pos_x += -foot[0] * scale;
-    pos_y += -foot[1] * scale;
-    
-    [pos_x, pos_y]
+/**
+ * @return The argument after the given switch, if there is one
+ */
+fn file_from_args(args: &[String], switch: &str) -> Option<String> {
+    let position = args.iter().position(|arg| arg == switch);
+    position.and_then(|index| args.get(index + 1)).cloned()
 }
 
 
@@ -743,6 +767,7 @@ impl ApplicationHandler for App {
 
             WindowEvent::CloseRequested => {
                 println!("The close button was pressed; stopping");
+                self.save_recording();
                 event_loop.exit();
             },
 
@@ -776,7 +801,7 @@ impl ApplicationHandler for App {
 
                 // println!("Button = {:?}, state = {:?}, button_event = {:?}", button, state, button_event);
 
-                self.handle_button_event(&button_event);
+                self.handle_input(InputEvent::Button(button_event));
             },
 
             glium::winit::event::WindowEvent::CursorMoved { device_id: _, position } => {
@@ -786,7 +811,7 @@ impl ApplicationHandler for App {
                     mx: position.x as f32,
                     my: position.y as f32,
                 };
-                self.handle_mouse_move_event(&event);
+                self.handle_input(InputEvent::MouseMove(event));
             },
 
             glium::winit::event::WindowEvent::MouseWheel { device_id: _, delta, phase: _ } => {
@@ -800,7 +825,7 @@ impl ApplicationHandler for App {
                             mx: self.ui.context.mouse_state.position[0],
                             my: self.ui.context.mouse_state.position[1],
                         };
-                        self.handle_scroll_event(&event);
+                        self.handle_input(InputEvent::Scroll(event));
                     },
                     _ => {
 
@@ -823,7 +848,7 @@ impl ApplicationHandler for App {
                     my: self.ui.context.mouse_state.position[1],
                 };
 
-                self.handle_button_event(&button_event);
+                self.handle_input(InputEvent::Button(button_event));
             }
 
             /*
@@ -857,7 +882,40 @@ fn main() {
 
     let program = build_program(&display);
 
-    let mut app = App::new(window, display, program, window_size);
+    let args: Vec<String> = std::env::args().collect();
+    let mut seed = seed_from_args(&args);
+
+    let replay_file = file_from_args(&args, "--replay");
+    let record_file = file_from_args(&args, "--record");
+    let mut replay = None;
+
+    if let Some(file) = replay_file {
+        match Recording::read(Path::new(&file)) {
+            Ok(recording) => {
+                if recording.window_size != window_size {
+                    println!("Warning: the recording was made with window size {:?}, mouse input may not match", recording.window_size);
+                }
+                seed = recording.seed;
+                replay = Some(Replay::new(recording));
+            },
+            Err(message) => {
+                println!("Error: {}", message);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // the seed and the player input determine the whole game, bug reports need it
+    println!("Starting game with seed {}, use --seed {} to play it again", seed, seed);
+
+    let mut app = exit_on_error(App::new(window, display, program, window_size, seed));
+    app.replay = replay;
+
+    if let Some(file) = record_file {
+        println!("Recording input to {}", file);
+        app.recording = Some(Recording::new(seed, window_size));
+        app.recording_file = file;
+    }
 
     event_loop.run_app(&mut app).unwrap();
 }
@@ -1,19 +1,15 @@
-use vecmath::{Vector2, vec2_sub, vec2_add, vec2_scale, vec2_len, vec2_square_len};
+use vecmath::{Vector2, vec2_sub, vec2_add, vec2_scale, vec2_len, vec2_square_len, vec2_normalized};
 use geo::Polygon;
 use geo::LineString;
-use geo::Contains;
-use geo::coord;
 
 use std::f64::consts::PI;
-use std::io::prelude::*;
-use std::io::{Result, BufWriter};
-use std::fs::File;
 use std::path::PathBuf;
 use std::collections::HashMap;
 use std::boxed::Box;
 
 use rand::Rng;
 use rand::rngs::StdRng;
+use serde::{Serialize, Deserialize};
 
 use crate::shop::Shop;
 use crate::item::Item;
@@ -21,15 +17,32 @@ use crate::ItemFactory;
 use crate::creature::Creature;
 use crate::creature::CreatureFactory;
 use crate::creature::CreatureAnimation;
+use crate::creature::CREATURE_SCALE;
+use crate::creature::MAX_HIT_RADIUS;
 use crate::projectile::ProjectileBuilder;
+use crate::projectile::ProjectileState;
+use crate::rules::GameRules;
 use crate::inventory::Inventory;
 use crate::particle_driver::ParticleDriver;
 use crate::animation::*;
 use crate::sound::Sound;
 use crate::SoundPlayer;
 use crate::mob_group::MobGroup;
+use crate::mob_group::ProjectileSpawner;
+use crate::map_layer::MapLayer;
+use crate::walk_grid::WalkGrid;
+use crate::walk_grid::WALK_CELL_SIZE;
+use crate::game::launch_projectile;
+use crate::damage::Damage;
+use crate::damage::Resistances;
+use crate::damage::Element;
+use crate::character::Character;
+use crate::status_effect::StatusEffect;
+use crate::combat_text::CombatText;
 use crate::CREATURE_TILESET;
-use crate::parse_rgba;
+use crate::loader::CsvFile;
+use crate::loader::CsvRow;
+use crate::loader::LoaderError;
 use crate::gfx::gl_support::BlendMode;
 use crate::Slot;
 use crate::WHITE;
@@ -39,17 +52,45 @@ pub const MAP_GROUND_LAYER:usize = 0;
 pub const MAP_OBJECT_LAYER:usize = 1;
 pub const MAP_CLOUD_LAYER:usize = 2;
 
+// particle tiles for hits and impacts
+const SPARK_TILES: [usize; 7] = [403, 404, 1993, 1994, 1995, 1996, 1997];
+
+// for projectiles which don't know their kind, like those of older saves
+const DEFAULT_SPARK_COLOR: [f32; 4] = [0.8, 0.5, 0.1, 1.0];
+
+// projectiles fly this high above the ground, low creatures are flown over
+pub const PROJECTILE_HEIGHT: f32 = 12.0;
+
+// projectiles are about this wide, it adds to the radius of the hit boxes
+const PROJECTILE_RADIUS: f32 = 8.0;
+
+// homing projectiles look for targets this far away
+const HOMING_RANGE: f32 = 400.0;
+
+// angle in degrees between the parts of a split projectile
+const SPLIT_ANGLE: f32 = 30.0;
+
+// a dead player is shown darkened
+pub const DEAD_PLAYER_COLOR: [f32; 4] = [0.3, 0.25, 0.25, 1.0];
+
 
 pub struct Map {
 
-    pub layers: [HashMap<u64, MapObject>; 7],
+    // sorted by uid, so updates visit the objects in the same order every run
+    pub layers: [MapLayer; 7],
     pub animations: HashMap<u64, Box<dyn Animated>>,
+
+    // damage numbers and the like, rising over the places of hits
+    pub combat_texts: Vec<CombatText>,
     pub transitions: Vec<MapTransition>,
     
     // the map area which can be walked. New areas must be merged into this
     pub walkable: Vec<Polygon<f32>>,
     pub blocked: Vec<Polygon<f32>>,  // non-walkable areas inside the walkable area
 
+    // walkable minus blocked, for fast lookups. Call update_walk_grid() after changing the areas
+    pub walk_grid: WalkGrid,
+
     // 'AI' controlled objects
     pub mob_groups: Vec<MobGroup>,
 
@@ -67,15 +108,32 @@ pub struct Map {
     pub item_factory: ItemFactory,
     pub creature_factory: CreatureFactory,
     pub projectile_builder: ProjectileBuilder,
+    pub rules: GameRules,
     pub player_id: u64, 
 }
 
 
+/**
+ * The content of an old v10 map file, read completely before
+ * it replaces the current map.
+ */
+struct V10Content {
+    name: String,
+    map_image_name: String,
+    backdrop_image_name: String,
+
+    // layer and object
+    mobs: Vec<(usize, MapObject)>,
+    transitions: Vec<MapTransition>,
+    shops: Vec<Shop>,
+}
+
+
 impl Map {
     pub fn new(name: &str, 
                map_image_name: &str, backdrop_image_name: &str,
-               item_factory: ItemFactory) -> Map {
-        let mut layers = [HashMap::new(), HashMap::new(), HashMap::new(), HashMap::new(), HashMap::new(), HashMap::new(), HashMap::new(),];
+               item_factory: ItemFactory) -> Result<Map, LoaderError> {
+        let mut layers = [MapLayer::new(), MapLayer::new(), MapLayer::new(), MapLayer::new(), MapLayer::new(), MapLayer::new(), MapLayer::new(),];
 /*        
         let player_visual = Visual {
             base_image_id: 39,
@@ -105,18 +163,21 @@ impl Map {
             particles: ParticleDriver::new(),       
         };
 
-        let mut factory = MapObjectFactory {
-            next_id: 1,
-        };
+        let mut factory = MapObjectFactory::new();
 
-        let creature_factory = CreatureFactory::new();
-        let projectile_builder = ProjectileBuilder::new();
+        let creature_factory = CreatureFactory::new()?;
+        let projectile_builder = ProjectileBuilder::new()?;
+        let rules = GameRules::new()?;
 
         let mut player = factory.create_mob(39, 4, [1000.0, 1000.0], 24.0, 1.0);
         let player_id = player.uid;
         player.visual = player_visual;
         player.update_action = UpdateAction::EmitDriveParticles;
-        player.creature = Some(creature_factory.create("Player"));
+        player.mob_type = MobType::Player;
+
+        let mut player_creature = creature_factory.create("Player");
+        player_creature.character = Some(Character::new());
+        player.creature = Some(player_creature);
         player.move_end_action = MoveEndAction::PickItemsUp;
 
         layers[MAP_OBJECT_LAYER].insert(player.uid, player);
@@ -128,15 +189,17 @@ impl Map {
         let walkable = Vec::new();
         // walkable.push(area);
 
-        Map {
+        Ok(Map {
             layers,
 
             animations: HashMap::new(),
+            combat_texts: Vec::new(),
             transitions: Vec::new(),
             mob_groups: Vec::new(),
 
             walkable,
             blocked: Vec::new(),
+            walk_grid: WalkGrid::new(),
 
             shops: Vec::new(),
             has_selection: false,
@@ -151,8 +214,9 @@ impl Map {
             item_factory,
             creature_factory,
             projectile_builder,
+            rules,
             player_id,
-        }
+        })
     }
 
 
@@ -166,6 +230,13 @@ impl Map {
 
         self.walkable.clear();
         self.blocked.clear();
+        self.walk_grid = WalkGrid::new();
+
+        self.animations.clear();
+        self.combat_texts.clear();
+        self.mob_groups.clear();
+        self.shops.clear();
+        self.has_selection = false;
     }
 
 
@@ -176,56 +247,45 @@ impl Map {
     }
 
 
-    pub fn set_player_position(&mut self, position: Vector2<f32>) {
+    /**
+     * A dead player can't act until respawned in town.
+     */
+    pub fn is_player_dead(&self) -> bool {
+        let player = self.layers[MAP_OBJECT_LAYER].get(&self.player_id).unwrap();
 
-        let mob: &mut MapObject = self.layers[MAP_OBJECT_LAYER].get_mut(&self.player_id).unwrap();
-        mob.position = position;
+        player.creature.as_ref().is_some_and(|creature| creature.hit_points <= 0)
     }
 
 
-    pub fn find_nearest_object(layer: &HashMap<u64, MapObject>, position: &Vector2<f32>, search_radius: f32, ignore_uid: u64) -> Option<u64> {
-        let mut distance = search_radius * search_radius;
-        let mut best_id = 0;
+    pub fn set_player_position(&mut self, position: Vector2<f32>) {
 
-        for (_key, object) in layer {
-            let dx = object.position[0] - position[0];
-            let dy = object.position[1] - position[1];
-            let d2 = dx * dx + dy * dy;
+        self.layers[MAP_OBJECT_LAYER].set_position(self.player_id, position);
+    }
 
-            // println!("object {} has distance {}", object.uid, d2);
 
-            if d2 < distance && object.uid != ignore_uid {
-                distance = d2;
-                best_id = object.uid;
-            }
-        }
-
-        let mut result:Option<u64> = None;
+    pub fn find_nearest_object(layer: &MapLayer, position: &Vector2<f32>, search_radius: f32, ignore_uid: u64) -> Option<u64> {
+        layer.nearest(*position, search_radius, ignore_uid)
+    }
 
-        if distance < search_radius * search_radius {
-            result = Some(best_id);
-            // println!("  best object is {}", best_id);
-        }
 
-        result
+    pub fn is_walkable(&self, position: [f32; 2]) -> bool {
+        self.walk_grid.is_walkable(position)
     }
 
 
-    pub fn is_walkable(&self, position: [f32; 2]) -> bool {
-        let p = coord! { x: position[0], y: position[1] };
-        
-        let mut ok = false;
-        
-        for polygon in &self.walkable {
-            if polygon.contains(&p) {
-                ok = true;
-                break;
-            }
-        }
+    /**
+     * @return true if the straight line between the positions can be walked.
+     */
+    pub fn is_path_walkable(&self, from: [f32; 2], to: [f32; 2]) -> bool {
+        self.walk_grid.is_segment_walkable(from, to)
+    }
 
-        println!("Location {}, {} is walkable={}", position[0], position[1], ok);
 
-        ok
+    /**
+     * Must be called after the walkable or blocked areas have changed.
+     */
+    pub fn update_walk_grid(&mut self) {
+        self.walk_grid = WalkGrid::build(&self.walkable, &self.blocked);
     }
 
 
@@ -245,23 +305,62 @@ impl Map {
         {
             let groups = &mut self.mob_groups;
             let mobs = &mut self.layers[MAP_OBJECT_LAYER];
-            let factory = &mut self.factory;
-            let projectile_builder = &mut self.projectile_builder;
+            let mut spawner = ProjectileSpawner {
+                factory: &mut self.factory,
+                projectile_builder: &mut self.projectile_builder,
+                speaker,
+            };
+
+            let walk_grid = &self.walk_grid;
 
             for group in groups {
-                group.update(self.player_id, dt, mobs, rng, factory, projectile_builder, speaker);
+                group.update(self.player_id, dt, mobs, walk_grid, rng, &mut spawner);
             }
         }
 
         let mut pickup_position = None;
+        let mut moved = Vec::new();
+        let mut wall_hits = Vec::new();
+        let mut burned = Vec::new();
+
+        self.steer_projectiles(dt);
+        self.combat_texts.retain_mut(|text| text.update(dt));
 
         for (_key, mob) in &mut self.layers[MAP_OBJECT_LAYER] {
+
+            // chilled mobs move slower, burning ones lose hit points
+            let mut speed_factor = 1.0;
+
+            if let Some(creature) = &mut mob.creature &&
+               creature.hit_points > 0 && !creature.effects.is_empty() {
+                speed_factor = creature.effects.speed_factor();
+                creature.hit_points -= creature.effects.update(dt);
+
+                if creature.hit_points <= 0 {
+                    burned.push(mob.uid);
+                }
+
+                mob.visual.color = creature.effects.tint(creature.color);
+                creature.effects.emit_particles(&mut mob.visual.particles, dt, rng);
+            }
+
+            let start = mob.position;
             let before = mob.move_time_left;
-            mob.move_dt(dt);
+            mob.move_dt(dt * speed_factor, &self.walk_grid);
             let after = mob.move_time_left;
 
+            if before > 0.0 {
+                moved.push(mob.uid);
+
+                // projectiles don't pass through walls
+                if is_projectile(mob.mob_type) &&
+                   let Some(impact) = self.walk_grid.find_impact(start, mob.position) {
+                    wall_hits.push((mob.uid, impact));
+                }
+            }
+
             // did the move just end?
-            if before > 0.0 && after <= 0.0 {
+            if before > 0.0 && after <= 0.0 && !mob.next_waypoint() {
                 mob.visual.particles.clear();
                 mob.stop_moving();
 
@@ -284,7 +383,7 @@ impl Map {
                     if rng.random::<f32>() < chance {
                         let spark = particles.spawn_ids[rng.random_range(0..len)];
                         
-                        particles.add_particle(0.0, -400.0, 0.0, 0.0, 0.0, 0.0, 
+                        particles.add_particle([0.0, -400.0, 0.0], [0.0, 0.0, 0.0],
                                                0.1, spark, [0.7, 0.75, 0.9]);
                     }
                 }
@@ -311,6 +410,24 @@ impl Map {
             }
         }
 
+        for (uid, impact) in wall_hits {
+            if self.bounce_projectile(uid, impact, speaker) {
+                continue;
+            }
+
+            // the impact is shown instead
+            kill_list.retain(|id| *id != uid);
+            self.handle_wall_impact(uid, impact, inventory, rng, speaker);
+        }
+
+        for uid in burned {
+            self.handle_death(uid);
+        }
+
+        for uid in moved {
+            self.layers[MAP_OBJECT_LAYER].reindex(uid);
+        }
+
         // player might have picked something up
         if pickup_position.is_some() {
             Self::check_pickup(&mut self.layers[MAP_OBJECT_LAYER], &pickup_position.unwrap(), 
@@ -321,11 +438,10 @@ impl Map {
 
         for (_key, mob) in &self.layers[MAP_OBJECT_LAYER] {
 
-            let mob_type = mob.mob_type;
-            if mob_type == MobType::PlayerProjectile || mob_type == MobType::CreatureProjectile {
+            if is_projectile(mob.mob_type) {
 
                 // projectiles may have hit something in the move
-                let target = Self::find_nearest_object(&self.layers[MAP_OBJECT_LAYER], &mob.position, 80.0, mob.uid);
+                let target = find_projectile_target(&self.layers[MAP_OBJECT_LAYER], mob);
                 match target {
                     None => {}
                     Some(uid) => {
@@ -337,14 +453,11 @@ impl Map {
 
         for (projectile, target) in phit_list {
 
-            // some projectiles can only hit certain targets, check if the hit was valid
-            let valid = self.handle_projectile_hit(projectile, target, rng, speaker);
+            // some projectiles can only hit certain targets, and some fly on after a hit
+            let spent = self.handle_projectile_hit(projectile, target, inventory, rng, speaker);
 
-            if valid {
+            if spent {
                 kill_list.push(projectile);
-                
-                let start_time = self.layers[MAP_OBJECT_LAYER].get(&target).unwrap().animation_timer;
-                self.animations.insert(target, Box::new(RemovalAnimation::new(start_time, 0.3)));
             }
         }
 
@@ -368,7 +481,7 @@ impl Map {
     }
 
 
-    fn check_pickup(layer: &mut HashMap<u64, MapObject>, position: &Vector2<f32>,
+    fn check_pickup(layer: &mut MapLayer, position: &Vector2<f32>,
                     inventory: &mut Inventory, player_id: u64,
                     speaker: &mut SoundPlayer) 
     {
@@ -418,70 +531,375 @@ impl Map {
     }
 
 
-    fn handle_projectile_hit(&mut self, projectile_uid: u64, target_uid: u64, rng: &mut StdRng, speaker: &mut SoundPlayer) -> bool {
+    /**
+     * Applies the damage of the projectile to the target. Player projectiles
+     * hurt creatures, creature projectiles hurt the player. Piercing
+     * projectiles fly on, but hit each target only once.
+     *
+     * @return true if the hit was valid and the projectile is used up
+     */
+    fn handle_projectile_hit(&mut self, projectile_uid: u64, target_uid: u64, inventory: &Inventory,
+                             rng: &mut StdRng, speaker: &mut SoundPlayer) -> bool {
 
-        let projectile_type = self.layers[MAP_OBJECT_LAYER].get_mut(&projectile_uid).unwrap().mob_type;
-        let target = self.layers[MAP_OBJECT_LAYER].get_mut(&target_uid).unwrap();
+        let projectile = self.layers[MAP_OBJECT_LAYER].get(&projectile_uid).unwrap();
+        let projectile_type = projectile.mob_type;
+        let damage = projectile.damage;
+        let position = projectile.position;
+        let config = projectile.projectile_key.as_ref().and_then(|key| self.projectile_builder.config(key)).cloned();
+        let already_hit = projectile.projectile_state.as_ref().is_some_and(|state| state.hit.contains(&target_uid));
 
-        // projectiles can only hit "the enemy" or obstacles on the map
-        if projectile_type == MobType::PlayerProjectile &&
-            target.mob_type == MobType::Player {
-            // no, we do not shoot ourselves into the foot
-            return false;
-        }
+        let target = self.layers[MAP_OBJECT_LAYER].get(&target_uid).unwrap();
 
-        if projectile_type == MobType::CreatureProjectile && 
-           target.mob_type == MobType::Creature {
+        // projectiles can only hit "the enemy", everything else is passed by
+        if already_hit || !is_enemy(projectile_type, target.mob_type) || !is_alive(target) {
             return false;
         }
 
-        // what to do about projectile-projectile hits?
-        if (projectile_type == MobType::CreatureProjectile ||
-            projectile_type == MobType::PlayerProjectile) && 
-           (target.mob_type == MobType::CreatureProjectile ||
-            target.mob_type == MobType::PlayerProjectile) {
-            return false;
-        } 
-    
+        // each kind of projectile has its own hit effect
+        let (hit_sound, hit_volume, spark_color) = 
+            match &config {
+                Some(config) => (config.hit_sound, config.hit_volume, config.spark_color),
+                None => (Sound::FireballHit as usize, 0.5, DEFAULT_SPARK_COLOR),
+            };
 
-        println!("Handle projectile hit on {}", target.uid);
-        let sparks = [403, 404, 1993, 1994, 1995, 1996, 1997];
+        if let Some(damage) = damage {
+            self.damage_target(target_uid, damage, inventory, rng);
+        }
+
+        speaker.play_sound(hit_sound, hit_volume);
 
+        let target = self.layers[MAP_OBJECT_LAYER].get_mut(&target_uid).unwrap();
         let z_off = target.visual.z_off * target.visual.scale * 0.5;
-        let creature_opt = &mut target.creature;
+        let sparks = SPARK_TILES;
 
-        if creature_opt.is_some() {
-           let creature = creature_opt.as_mut().unwrap();
+        for _i in 0..10 {
+            let xv = rng.random::<f32>() * 2.0 - 1.0;
+            let yv = rng.random::<f32>() * 2.0 - 1.0;
+            let zv = rng.random::<f32>();
 
-           if projectile_type == MobType::PlayerProjectile && 
-              target.mob_type == MobType::Creature &&
-              creature.hit_points > 0 {
+            let color = [spark_color[0] + rng.random::<f32>() * 0.4, spark_color[1] + rng.random::<f32>() * 0.4, spark_color[2] + rng.random::<f32>() * 0.4];
+            let tile = sparks[rng.random_range(0..sparks.len())];
+
+            let speed = if tile == 403 {100.0} else {100.0 + rng.random_range(1.0..50.0)};
+
+            target.visual.particles.add_particle([0.0, 0.0, z_off], [xv * speed, yv * speed, zv * speed], 0.7, tile, color);
+        }
 
-                speaker.play(Sound::FireballHit, 0.5);
+        let config = match config {
+            Some(config) => config,
+            None => return true,
+        };
 
-                for _i in 0..10 {
-                    let xv = rng.random::<f32>() * 2.0 - 1.0;
-                    let yv = rng.random::<f32>() * 2.0 - 1.0;
-                    let zv = rng.random::<f32>();
+        if config.explosion_radius > 0.0 {
+            explosion_burst(target, config.explosion_radius, spark_color, rng);
+            self.explode(projectile_uid, position, target_uid, inventory, rng);
+        }
 
-                    let color = [0.8 + rng.random::<f32>() * 0.4, 0.5 + rng.random::<f32>() * 0.4, 0.1 + rng.random::<f32>() * 0.4];
-                    let tile = sparks[rng.random_range(0..sparks.len())];
+        let projectile = self.layers[MAP_OBJECT_LAYER].get_mut(&projectile_uid).unwrap();
 
-                    let speed = if tile == 403 {100.0} else {100.0 + rng.random_range(1.0..50.0)};
+        let state = match &mut projectile.projectile_state {
+            Some(state) => state,
+            None => return true,
+        };
 
-                    target.visual.particles.add_particle(0.0, 0.0, z_off, xv * speed, yv * speed, zv * speed, 0.7, tile, color);
-                    target.visual.color = [0.0, 0.0, 0.0, 0.0];
-                    
-                    let damage = 10; // todo
-                    creature.hit_points -= damage;
-                }
-            
-                return true;
+        state.hit.push(target_uid);
+
+        if state.splits > 0 {
+            let state = state.clone();
+            self.split_projectile(projectile_uid, &state);
+        }
+
+        let projectile = self.layers[MAP_OBJECT_LAYER].get_mut(&projectile_uid).unwrap();
+        let state = projectile.projectile_state.as_mut().unwrap();
+
+        // a projectile splits only once, piercing can keep the original flying
+        state.splits = 0;
+
+        if state.pierces_left > 0 {
+            state.pierces_left -= 1;
+            false
+        }
+        else {
+            true
+        }
+    }
+
+
+    /**
+     * Hurts the target, shocked targets take more. Elemental damage may
+     * cause a status effect, see StatusEffect::from_hit().
+     */
+    fn damage_target(&mut self, target_uid: u64, damage: Damage, inventory: &Inventory, rng: &mut StdRng) {
+
+        let target = self.layers[MAP_OBJECT_LAYER].get_mut(&target_uid).unwrap();
+
+        let creature = match &mut target.creature {
+            Some(creature) if creature.hit_points > 0 => creature,
+            _ => return,
+        };
+
+        // the player is protected by the worn items, creatures by their kind
+        let resistances = if target.mob_type == MobType::Player {
+            Resistances::from_equipment(inventory)
+        }
+        else {
+            creature.resistances
+        };
+
+        let mut resisted = damage.after_resistances(&resistances);
+
+        // the armor of the player blocks a share of physical hits
+        if let Some(character) = &creature.character && damage.element == Element::Physical {
+            resisted = resisted * (100 - character.defence()) / 100;
+        }
+
+        let amount = creature.effects.damage_taken(resisted);
+        creature.hit_points -= amount;
+
+        // shocked targets take more damage, the text tells why
+        let text_height = creature.hit_box.radius * 2.0;
+        let text = if amount == 0 && damage.amount > 0 {
+            CombatText::resisted(target.position, text_height)
+        }
+        else {
+            CombatText::damage(target.position, text_height, amount, damage.element, amount > resisted)
+        };
+        self.combat_texts.push(text);
+
+        if amount > 0 && creature.hit_points > 0 && rng.random_range(0..100) < damage.effect_chance &&
+           let Some(effect) = StatusEffect::from_hit(damage.element, amount) {
+            creature.effects.add(effect);
+        }
+
+        println!("Projectile hit on {}, {} hit points left", target.uid, creature.hit_points);
+
+        if creature.hit_points <= 0 {
+            self.handle_death(target_uid);
+        }
+    }
+
+
+    /**
+     * Hurts all enemies around the center, less the farther they are away.
+     *
+     * @param spared The target of the direct hit, it already took the full damage
+     */
+    fn explode(&mut self, projectile_uid: u64, center: Vector2<f32>, spared: u64, inventory: &Inventory, rng: &mut StdRng) {
+
+        let layer = &self.layers[MAP_OBJECT_LAYER];
+        let projectile = layer.get(&projectile_uid).unwrap();
+        let projectile_type = projectile.mob_type;
+
+        let (damage, config) = match (projectile.damage, projectile.projectile_key.as_ref().and_then(|key| self.projectile_builder.config(key))) {
+            (Some(damage), Some(config)) => (damage, config.clone()),
+            _ => return,
+        };
+
+        let radius = config.explosion_radius;
+
+        // big creatures are caught by the rim of the explosion earlier
+        let victims: Vec<(u64, f32)> = layer.in_radius(center, radius + MAX_HIT_RADIUS).into_iter()
+            .filter(|uid| *uid != spared)
+            .filter_map(|uid| layer.get(&uid))
+            .filter(|mob| is_enemy(projectile_type, mob.mob_type) && is_alive(mob))
+            .map(|mob| (mob.uid, (vec2_len(vec2_sub(mob.position, center)) - hit_radius(mob)).max(0.0)))
+            .filter(|(_, distance)| *distance < radius)
+            .collect();
+
+        for (uid, distance) in victims {
+            let factor = 1.0 - config.falloff.clamp(0.0, 100.0) / 100.0 * (distance / radius);
+            let amount = (damage.amount as f32 * factor).round() as i32;
+
+            self.damage_target(uid, Damage { amount, .. damage }, inventory, rng);
+
+            let victim = self.layers[MAP_OBJECT_LAYER].get_mut(&uid).unwrap();
+            explosion_burst(victim, radius * 0.5, config.spark_color, rng);
+        }
+    }
+
+
+    /**
+     * Launches the parts of a split projectile, fanned out around its
+     * direction. The parts do half the damage and don't split again.
+     */
+    fn split_projectile(&mut self, projectile_uid: u64, state: &ProjectileState) {
+
+        let projectile = self.layers[MAP_OBJECT_LAYER].get(&projectile_uid).unwrap();
+        let key = projectile.projectile_key.clone().unwrap();
+        let projectile_type = projectile.mob_type;
+        let position = projectile.position;
+        let damage = projectile.damage;
+        let heading = projectile.velocity[1].atan2(projectile.velocity[0]);
+
+        for i in 0 .. state.splits {
+            let angle = heading + (i as f32 - (state.splits - 1) as f32 * 0.5) * SPLIT_ANGLE.to_radians();
+            let fire_at = [position[0] + angle.cos(), position[1] + angle.sin()];
+
+            let mut part = launch_projectile(position, fire_at, 0.0, projectile_type, &mut self.factory);
+            self.projectile_builder.configure_without_sound(&key, &mut part.visual, &mut part.velocity);
+            self.projectile_builder.arm_projectile(&key, &mut part);
+
+            part.visual.scale *= 0.7;
+            part.damage = damage.map(|damage| Damage { amount: (damage.amount + 1) / 2, .. damage });
+
+            if let Some(part_state) = &mut part.projectile_state {
+                part_state.splits = 0;
+                part_state.hit = state.hit.clone();
             }
+
+            self.layers[MAP_OBJECT_LAYER].insert(part.uid, part);
+        }
+    }
+
+
+    /**
+     * Turns homing projectiles towards the nearest enemy in sight.
+     */
+    fn steer_projectiles(&mut self, dt: f32) {
+
+        let layer = &self.layers[MAP_OBJECT_LAYER];
+        let mut steering = Vec::new();
+
+        for projectile in layer.values() {
+            if !is_projectile(projectile.mob_type) || projectile.move_time_left <= 0.0 {
+                continue;
+            }
+
+            let turn_rate = match projectile.projectile_key.as_ref().and_then(|key| self.projectile_builder.config(key)) {
+                Some(config) if config.homing > 0.0 => config.homing,
+                _ => continue,
+            };
+
+            let already_hit = |uid: u64| projectile.projectile_state.as_ref().is_some_and(|state| state.hit.contains(&uid));
+
+            let target = layer.in_radius(projectile.position, HOMING_RANGE).into_iter()
+                .filter_map(|uid| layer.get(&uid))
+                .filter(|mob| is_enemy(projectile.mob_type, mob.mob_type) && is_alive(mob) && !already_hit(mob.uid))
+                .min_by(|a, b| {
+                    let da = vec2_square_len(vec2_sub(a.position, projectile.position));
+                    let db = vec2_square_len(vec2_sub(b.position, projectile.position));
+                    da.total_cmp(&db)
+                });
+
+            if let Some(target) = target {
+                let wanted = vec2_sub(target.position, projectile.position);
+                steering.push((projectile.uid, turn_towards(projectile.velocity, wanted, turn_rate.to_radians() * dt)));
+            }
+        }
+
+        for (uid, velocity) in steering {
+            let projectile = self.layers[MAP_OBJECT_LAYER].get_mut(&uid).unwrap();
+            projectile.velocity = velocity;
+            projectile.visual.orient_in_direction(velocity);
+        }
+    }
+
+
+    /**
+     * Bouncing projectiles are reflected by the wall they hit.
+     *
+     * @return true if the projectile bounced, false if it must stop at the wall
+     */
+    fn bounce_projectile(&mut self, projectile_uid: u64, impact: Vector2<f32>, speaker: &mut SoundPlayer) -> bool {
+
+        let walk_grid = &self.walk_grid;
+        let projectile = self.layers[MAP_OBJECT_LAYER].get_mut(&projectile_uid).unwrap();
+
+        match &mut projectile.projectile_state {
+            Some(state) if state.bounces_left > 0 => state.bounces_left -= 1,
+            _ => return false,
+        }
+
+        // which way is blocked? In corners, both are
+        let step = vec2_scale(vec2_normalized(projectile.velocity), WALK_CELL_SIZE * 0.5);
+        let blocked_x = !walk_grid.is_walkable([impact[0] + step[0], impact[1]]);
+        let blocked_y = !walk_grid.is_walkable([impact[0], impact[1] + step[1]]);
+
+        let velocity = projectile.velocity;
+        let flip_x = blocked_x || !blocked_y;
+        let flip_y = blocked_y || !blocked_x;
+
+        projectile.velocity = [if flip_x {-velocity[0]} else {velocity[0]}, if flip_y {-velocity[1]} else {velocity[1]}];
+        projectile.position = impact;
+        projectile.visual.orient_in_direction(projectile.velocity);
+
+        speaker.play(Sound::FireballHit, 0.15);
+
+        true
+    }
+
+
+    /**
+     * Creatures fade away, the player stays on the map until respawned.
+     */
+    fn handle_death(&mut self, uid: u64) {
+        let mob = match self.layers[MAP_OBJECT_LAYER].get_mut(&uid) {
+            Some(mob) => mob,
+            None => return,
+        };
+
+        if let Some(creature) = &mut mob.creature {
+            creature.effects.clear();
+        }
+
+        if mob.mob_type == MobType::Creature {
+            mob.visual.color = [0.0, 0.0, 0.0, 0.0];
+
+            let start_time = mob.animation_timer;
+            self.animations.insert(uid, Box::new(RemovalAnimation::new(start_time, 0.3)));
+        }
+        else {
+            println!("The player died");
+            mob.stop_moving();
+            mob.visual.color = DEAD_PLAYER_COLOR;
+        }
+    }
+
+
+    /**
+     * The projectile stops at the wall and bursts into sparks. It stays
+     * on the map for the impact animation, but can't hit anything anymore.
+     */
+    fn handle_wall_impact(&mut self, projectile_uid: u64, impact: Vector2<f32>, inventory: &Inventory,
+                          rng: &mut StdRng, speaker: &mut SoundPlayer) {
+
+        let projectile = self.layers[MAP_OBJECT_LAYER].get(&projectile_uid).unwrap();
+        let config = projectile.projectile_key.as_ref().and_then(|key| self.projectile_builder.config(key)).cloned();
+
+        // exploding projectiles also explode at walls
+        if let Some(config) = &config && config.explosion_radius > 0.0 {
+            self.explode(projectile_uid, impact, projectile_uid, inventory, rng);
+
+            let projectile = self.layers[MAP_OBJECT_LAYER].get_mut(&projectile_uid).unwrap();
+            explosion_burst(projectile, config.explosion_radius, config.spark_color, rng);
+        }
+
+        let projectile = self.layers[MAP_OBJECT_LAYER].get_mut(&projectile_uid).unwrap();
+
+        projectile.position = impact;
+        projectile.velocity = [0.0, 0.0];
+        projectile.stop_moving();
+        projectile.mob_type = MobType::MapObject;
+        projectile.move_end_action = MoveEndAction::None;
+
+        let glow = projectile.visual.glow;
+
+        for _i in 0..6 {
+            let xv = rng.random::<f32>() * 2.0 - 1.0;
+            let yv = rng.random::<f32>() * 2.0 - 1.0;
+            let zv = rng.random::<f32>();
+
+            let color = [glow[0] + rng.random::<f32>() * 0.4, glow[1] + rng.random::<f32>() * 0.4, glow[2] + rng.random::<f32>() * 0.4];
+            let tile = SPARK_TILES[rng.random_range(0..SPARK_TILES.len())];
+            let speed = 60.0 + rng.random_range(1.0..40.0);
+
+            projectile.visual.particles.add_particle([0.0, 0.0, 12.0], [xv * speed, yv * speed, zv * speed], 0.3, tile, color);
         }
 
-        // don't kill the player (yet)
-        false
+        speaker.play(Sound::FireballHit, 0.3);
+
+        let start_time = projectile.animation_timer;
+        self.animations.insert(projectile_uid, Box::new(RemovalAnimation::new(start_time, 0.3)));
     }
 
 
@@ -520,52 +938,58 @@ impl Map {
     }
 
 
-    pub fn load(&mut self, filename: &str) {
-
-        // preserve player
-        let mut player = self.layers[MAP_OBJECT_LAYER].remove(&self.player_id).unwrap();
-
-        self.clear();
+    /**
+     * Loads a map file from the map resources. Old v10 files are imported,
+     * everything else must be in the current map file format.
+     *
+     * @param rng Stocks the shops of imported maps
+     */
+    pub fn load(&mut self, filename: &str, rng: &mut StdRng) -> Result<(), LoaderError> {
 
         let mut path = PathBuf::new();
         path.push("resources/maps/");
         path.push(filename);
 
-        println!("Loading map {}", path.display());
+        let path_name = path.display().to_string();
+        println!("Loading map {}", path_name);
 
-        let content = std::fs::read_to_string(path.as_path()).unwrap();
-        let mut lines = content.lines();
+        let content = std::fs::read_to_string(path.as_path())
+            .map_err(|e| LoaderError::new(&path_name, &e.to_string()))?;
 
-        lines.next(); // version
+        if content.starts_with("v10") {
+            self.import_v10(&path_name, &content, rng)
+        }
+        else {
+            self.load_map_file(&path_name, &content)
+        }
+    }
 
-        lines.next(); // header start
-        self.name = lines.next().unwrap().to_string();
-        self.map_image_name = lines.next().unwrap().to_string();
-        self.backdrop_image_name = lines.next().unwrap().to_string();
-        println!("map name={} image={} backdrop={}", self.name, self.map_image_name, self.backdrop_image_name);
-        lines.next(); // header end
 
-        lines.next(); // objects start
-        let mut line = lines.next().unwrap();
+    /**
+     * Reads the old line based map format. It has no walkable area, so
+     * the diamond shaped area of the old maps is used.
+     */
+    pub fn import_v10(&mut self, path_name: &str, content: &str, rng: &mut StdRng) -> Result<(), LoaderError> {
 
-        let object_end_marker = "end map objects".to_string();
-        while object_end_marker != line {
-            // println!("line='{}'", line);
-            self.load_mob(line);
-            line = lines.next().unwrap();
-        }
+        // a broken file must leave the current map as it was
+        let v10 = self.read_v10(path_name, content, rng)?;
 
-        lines.next(); // transitions start
-        line = lines.next().unwrap();
+        println!("player_id={}", self.player_id);
 
-        let transition_end_marker = "end map transitions".to_string();
-        while transition_end_marker != line {
-            // println!("line='{}'", line);
-            self.load_transition(line);
-            line = lines.next().unwrap();
-        }
+        // preserve player
+        let mut player = self.layers[MAP_OBJECT_LAYER].remove(&self.player_id).unwrap();
 
-        println!("player_id={}", self.player_id);
+        self.clear();
+
+        self.name = v10.name;
+        self.map_image_name = v10.map_image_name;
+        self.backdrop_image_name = v10.backdrop_image_name;
+        self.transitions = v10.transitions;
+        self.shops = v10.shops;
+
+        for (layer, mob) in v10.mobs {
+            self.layers[layer].insert(mob.uid, mob);
+        }
 
         // stop player movement
         player.stop_moving();
@@ -585,24 +1009,84 @@ impl Map {
                                                     vec![]);
 
         self.walkable.push(area);
+        self.update_walk_grid();
+
+        Ok(())
     }
 
 
-    fn load_mob(&mut self, line: &str) {
-        let parts: Vec<&str> = line.split(",").collect();
+    fn read_v10(&mut self, path_name: &str, content: &str, rng: &mut StdRng) -> Result<V10Content, LoaderError> {
 
-        let layer = parts[0].parse::<usize>().unwrap();
-        let tile_id = parts[1].parse::<usize>().unwrap();
-        let directions = parts[2].parse::<usize>().unwrap();
+        // v10 files have no column headers, these are for the error messages
+        let mob_columns = CsvFile::with_columns(path_name, &["Layer", "Tile id", "Directions", "X", "Y", "Z offset", "Scale", "Color", "Blend"]);
+        let transition_columns = CsvFile::with_columns(path_name, &["X", "Y", "Radius", "Destination", "Destination x", "Destination y"]);
+        
+        let line_count = content.lines().count();
+        let mut lines = content.lines().enumerate();
+        let mut next_line = || {
+            lines.next().ok_or(LoaderError::at_line(path_name, line_count, "unexpected end of file"))
+        };
 
-        let x = parts[3].parse::<f32>().unwrap();
-        let y = parts[4].parse::<f32>().unwrap();
-        let z_off = parts[5].parse::<f32>().unwrap();
-        let scale = parts[6].parse::<f32>().unwrap();
+        next_line()?; // version
 
-        // parts[7] is an RGBA tuple
-        let color = parse_rgba(parts[7]);
-        let blend = key_to_blend(parts[8]);
+        next_line()?; // header start
+        let name = next_line()?.1.to_string();
+        let map_image_name = next_line()?.1.to_string();
+        let backdrop_image_name = next_line()?.1.to_string();
+        println!("map name={} image={} backdrop={}", name, map_image_name, backdrop_image_name);
+        next_line()?; // header end
+
+        let mut v10 = V10Content {
+            name,
+            map_image_name,
+            backdrop_image_name,
+            mobs: Vec::new(),
+            transitions: Vec::new(),
+            shops: Vec::new(),
+        };
+
+        next_line()?; // objects start
+        let (mut line_no, mut line) = next_line()?;
+
+        while line != "end map objects" {
+            // println!("line='{}'", line);
+            v10.mobs.push(self.load_mob(mob_columns.row(line_no + 1, line))?);
+            (line_no, line) = next_line()?;
+        }
+
+        next_line()?; // transitions start
+        (line_no, line) = next_line()?;
+
+        while line != "end map transitions" {
+            // println!("line='{}'", line);
+            self.load_transition(transition_columns.row(line_no + 1, line), &mut v10, rng)?;
+            (line_no, line) = next_line()?;
+        }
+
+        Ok(v10)
+    }
+
+
+    /**
+     * @return The layer and the object, it is not placed on the map yet
+     */
+    fn load_mob(&mut self, mut row: CsvRow) -> Result<(usize, MapObject), LoaderError> {
+
+        let layer = row.next_usize()?;
+        if layer >= self.layers.len() {
+            return Err(row.error("layer does not exist", &layer.to_string()));
+        }
+
+        let tile_id = row.next_usize()?;
+        let directions = row.next_usize()?;
+
+        let x = row.next_f32()?;
+        let y = row.next_f32()?;
+        let z_off = row.next_f32()?;
+        let scale = row.next_f32()?;
+
+        let color = row.next_rgba()?;
+        let blend = key_to_blend(row.next_str()?);
 
         println!("{}, {}, {}, {}, {}, {}, {:?}, {:?}", layer, tile_id, x, y, z_off, scale, color, blend);
 
@@ -611,39 +1095,42 @@ impl Map {
         mob.visual.blend = blend;
         mob.visual.directions = directions;
 
-        self.layers[layer].insert(mob.uid, mob);
+        Ok((layer, mob))
     }
 
 
-    fn load_transition(&mut self, line: &str) 
+    fn load_transition(&mut self, mut row: CsvRow, v10: &mut V10Content, rng: &mut StdRng) -> Result<(), LoaderError>
     {
-        let mut parts = line.split(",");
-
-        let x = parts.next().unwrap().parse::<f32>().unwrap();
-        let y = parts.next().unwrap().parse::<f32>().unwrap();
-        let r = parts.next().unwrap().parse::<f32>().unwrap();
+        let x = row.next_f32()?;
+        let y = row.next_f32()?;
+        let r = row.next_f32()?;
         
-        let dest_str = parts.next().unwrap();
-        let c = dest_str.chars().next().unwrap();
+        let dest_str = row.next_str()?;
         let destination;
 
-        if c.is_ascii_digit() {
-            let map_id = dest_str.parse::<i32>().unwrap();
-            let dest_x = parts.next().unwrap().parse::<f32>().unwrap();
-            let dest_y = parts.next().unwrap().parse::<f32>().unwrap();
+        if dest_str.starts_with(|c: char| c.is_ascii_digit()) {
+            let map_id = dest_str.parse::<i32>().map_err(|_| row.error("expected integer", dest_str))?;
+            let dest_x = row.next_f32()?;
+            let dest_y = row.next_f32()?;
             let to_location = [dest_x, dest_y];
 
             destination = TransitionDestination::Map {to_map: map_id, to_location};
         }
         else {
             let mut shop = Shop::new();
-            shop.restock(&mut self.item_factory);
-            let index = self.shops.len();
+            shop.restock(&mut self.item_factory, rng);
+            let index = v10.shops.len();
             destination = TransitionDestination::Shop {index};
-            self.shops.push(shop);
+            v10.shops.push(shop);
         }
 
-        self.add_transition([x, y], r, destination);
+        v10.transitions.push(MapTransition {
+            from: [x, y],
+            rad: r,
+            destination,
+        });
+
+        Ok(())
     }
 
 
@@ -658,109 +1145,29 @@ impl Map {
     }
 
 
-    pub fn save(&self, filename: &str) -> Result<()> {
+    /**
+     * Writes the map, without the player, to the map resources
+     * in the current map file format.
+     */
+    pub fn save(&self, filename: &str) -> Result<(), String> {
         let mut path = PathBuf::new();
         path.push("resources/maps");
         path.push(filename);
 
-        let f = File::create(path.as_path())?;
-        {        
-            let mut writer = BufWriter::new(f);
+        let content = self.to_map_file_string()?;
 
-            writer.write("v10\n".as_bytes())?;
-            
-            writer.write("begin map header\n".as_bytes())?;
-            let name = self.name.to_string()  + "\n";
-            writer.write(name.as_bytes())?;
-            let map_image_name = self.map_image_name.to_string() + "\n";
-            writer.write(map_image_name.as_bytes())?;
-            let backdrop_image_name = self.backdrop_image_name.to_string() + "\n";
-            writer.write(backdrop_image_name.as_bytes())?;
-            writer.write("end map header\n".as_bytes())?;
-
-            writer.write("begin map objects\n".as_bytes())?;
-            self.save_layer(&mut writer, MAP_GROUND_LAYER)?;
-            self.save_layer(&mut writer, MAP_OBJECT_LAYER)?;
-            self.save_layer(&mut writer, MAP_CLOUD_LAYER)?;
-            writer.write("end map objects\n".as_bytes())?;
-
-            self.save_map_transitions(&mut writer)?
-        }
-
-        Ok(())
-    }
-    
-    
-    fn save_layer(&self, writer: &mut BufWriter<File>, layer: usize) -> Result<()> {
-        let objects = &self.layers[layer];
-
-        for (_key, object) in objects {
-
-            if object.uid != self.player_id {
-
-                let color = object.visual.color; 
-
-                let line = 
-                layer.to_string() + "," +
-                &object.visual.base_image_id.to_string() + "," +
-                &object.visual.directions.to_string() + "," +
-                &object.position[0].to_string() + "," +
-                &object.position[1].to_string() + "," +
-                &object.visual.z_off.to_string() + "," +
-                &object.visual.scale.to_string() + "," +
-                &color[0].to_string() + " " +
-                &color[1].to_string() + " " +
-                &color[2].to_string() + " " +
-                &color[3].to_string() + "," +            
-                &blend_to_key(&object.visual.blend) +
-                "\n";
-                
-                writer.write(line.as_bytes())?;
-            }
-        }
-
-        Ok(())
-    }
-
-    
-    fn save_map_transitions(&self, writer: &mut BufWriter<File>) -> Result<()> {
-        writer.write("begin map transitions\n".as_bytes())?;
-
-        for transit in &self.transitions {
-
-            let destination =
-                match &transit.destination  {
-                    TransitionDestination::Map { to_map, to_location } => {
-                        to_map.to_string() + "," +
-                        &to_location[0].to_string() + "," +
-                        &to_location[1].to_string()
-                    },
-                    TransitionDestination::Shop { index } => {
-                        index.to_string()
-                    }
-                };
-
-
-            let line = 
-                transit.from[0].to_string() + "," +
-                &transit.from[1].to_string() + "," +
-                &transit.rad.to_string() + "," +
-                &destination + "\n";
-
-            writer.write(line.as_bytes())?;
-        }
-
-        writer.write("end map transitions\n".as_bytes())?;
-
-        Ok(())
+        std::fs::write(path.as_path(), content)
+            .map_err(|e| format!("Cannot write {}: {}", path.display(), e))
     }
 
     
     pub fn move_selected_object(&mut self, dx: f32, dy: f32) {        
         if self.has_selection {
-            let object = self.layers[self.selected_layer].get_mut(&self.selected_item).unwrap();
+            let layer = &mut self.layers[self.selected_layer];
+            let object = layer.get_mut(&self.selected_item).unwrap();
             object.position[0] += dx;
             object.position[1] += dy;
+            layer.reindex(self.selected_item);
         }
     }
 
@@ -809,6 +1216,7 @@ impl Map {
                     let mut mob = self.factory.create_mob(creature.base_tile_id, CREATURE_TILESET, [x, y], 32.0, scale);
                     mob.visual.directions = creature.frames;
                     mob.visual.blend = creature.blend_mode;
+                    mob.visual.color = creature.color;
                     mob.mob_type = MobType::Creature;
                     mob.creature = Some(creature);
                     mob.animation_timer = rng.random::<f32>(); // otherwise all start with the very same frame
@@ -831,7 +1239,7 @@ impl Map {
         
         println!("Placing creatures at {}, {}", center[0], center[1]);
 
-        let mut mobs = self.make_creatures(id, min_count, max_count, center, spacing, 0.5, rng);
+        let mut mobs = self.make_creatures(id, min_count, max_count, center, spacing, CREATURE_SCALE, rng);
         let mut list = Vec::new();
 
         for i in (0..mobs.len()).rev() {
@@ -857,6 +1265,86 @@ impl Map {
 }
 
 
+fn is_projectile(mob_type: MobType) -> bool {
+    mob_type == MobType::PlayerProjectile || mob_type == MobType::CreatureProjectile
+}
+
+
+// player projectiles hurt creatures, creature projectiles hurt the player
+fn is_enemy(projectile_type: MobType, target_type: MobType) -> bool {
+    (projectile_type == MobType::PlayerProjectile && target_type == MobType::Creature) ||
+    (projectile_type == MobType::CreatureProjectile && target_type == MobType::Player)
+}
+
+
+fn is_alive(mob: &MapObject) -> bool {
+    mob.creature.as_ref().is_some_and(|creature| creature.hit_points > 0)
+}
+
+
+fn hit_radius(mob: &MapObject) -> f32 {
+    mob.creature.as_ref().map(|creature| creature.hit_box.radius).unwrap_or(0.0)
+}
+
+
+/**
+ * The closest enemy whose hit box the projectile touches. Loot, decoration
+ * and friends can't be hit, projectiles pass them by.
+ */
+fn find_projectile_target(layer: &MapLayer, projectile: &MapObject) -> Option<u64> {
+
+    let already_hit = |uid: u64| projectile.projectile_state.as_ref().is_some_and(|state| state.hit.contains(&uid));
+
+    // how far the projectile is inside the hit box, the deepest one counts
+    let depth = |mob: &MapObject| {
+        let hit_box = mob.creature.as_ref().unwrap().hit_box;
+        hit_box.radius + PROJECTILE_RADIUS - vec2_len(vec2_sub(mob.position, projectile.position))
+    };
+
+    layer.in_radius(projectile.position, MAX_HIT_RADIUS + PROJECTILE_RADIUS).into_iter()
+        .filter_map(|uid| layer.get(&uid))
+        .filter(|mob| is_enemy(projectile.mob_type, mob.mob_type) && is_alive(mob) && !already_hit(mob.uid))
+        .filter(|mob| {
+            let hit_box = mob.creature.as_ref().unwrap().hit_box;
+            depth(mob) > 0.0 && (hit_box.height <= 0.0 || PROJECTILE_HEIGHT <= hit_box.height)
+        })
+        .max_by(|a, b| depth(a).total_cmp(&depth(b)).then(b.uid.cmp(&a.uid)))
+        .map(|mob| mob.uid)
+}
+
+
+/**
+ * Rotates the velocity towards the wanted direction, but not more than max_angle.
+ */
+fn turn_towards(velocity: Vector2<f32>, wanted: Vector2<f32>, max_angle: f32) -> Vector2<f32> {
+    let heading = velocity[1].atan2(velocity[0]);
+    let mut turn = wanted[1].atan2(wanted[0]) - heading;
+
+    // the short way round
+    while turn > std::f32::consts::PI { turn -= 2.0 * std::f32::consts::PI; }
+    while turn < -std::f32::consts::PI { turn += 2.0 * std::f32::consts::PI; }
+
+    let angle = heading + turn.clamp(-max_angle, max_angle);
+    let speed = vec2_len(velocity);
+
+    [angle.cos() * speed, angle.sin() * speed]
+}
+
+
+// a ring of sparks, about as wide as the explosion
+fn explosion_burst(mob: &mut MapObject, radius: f32, color: [f32; 4], rng: &mut StdRng) {
+
+    for i in 0..20 {
+        let angle = i as f32 * std::f32::consts::PI / 10.0;
+        let speed = radius * (1.5 + rng.random::<f32>());
+        let tile = SPARK_TILES[rng.random_range(0..SPARK_TILES.len())];
+
+        mob.visual.particles.add_particle([0.0, 0.0, 10.0], [angle.cos() * speed, angle.sin() * speed, rng.random::<f32>() * 30.0],
+                                          0.5, tile, [color[0], color[1], color[2]]);
+    }
+}
+
+
 fn emit_drive_particles(mob: &mut MapObject, dt: f32, rng: &mut StdRng) {
 
     let direction = vec2_scale(mob.velocity, -1.0);
@@ -878,12 +1366,30 @@ fn emit_drive_particles(mob: &mut MapObject, dt: f32, rng: &mut StdRng) {
 
         let spark = 1993 + (rng.random::<f32>() * 5.0) as usize;
 
-        mob.visual.particles.add_particle(xp, yp, 25.0, xv * speed, yv * speed, zv * speed, 1.0, spark, [0.5, 0.8, 1.0]);
+        mob.visual.particles.add_particle([xp, yp, 25.0], [xv * speed, yv * speed, zv * speed], 1.0, spark, [0.5, 0.8, 1.0]);
     }
 }
 
 
 pub fn move_mob(mob: &mut MapObject, destination: Vector2<f32>, base_speed: f32) {
+    mob.path.clear();
+    head_for(mob, destination, base_speed);
+}
+
+
+/**
+ * Moves the object along the waypoints, see pathfinding::find_path()
+ */
+pub fn follow_path(mob: &mut MapObject, path: Vec<Vector2<f32>>, base_speed: f32) {
+    if let Some(waypoint) = path.first() {
+        let waypoint = *waypoint;
+        mob.path = path;
+        head_for(mob, waypoint, base_speed);
+    }
+}
+
+
+fn head_for(mob: &mut MapObject, destination: Vector2<f32>, base_speed: f32) {
 
     let direction = vec2_sub(destination, mob.position);
 
@@ -892,6 +1398,7 @@ pub fn move_mob(mob: &mut MapObject, destination: Vector2<f32>, base_speed: f32)
     let distance = vec2_len(direction);
     let time = distance / base_speed; // pixel per second
 
+    mob.move_time_total = time;
     mob.move_time_left = time;
     mob.velocity = vec2_scale(direction, 1.0 / time);
 
@@ -901,15 +1408,32 @@ pub fn move_mob(mob: &mut MapObject, destination: Vector2<f32>, base_speed: f32)
 }
 
 
-fn blend_to_key(blend: &BlendMode) -> String {
-    let key =
-        match blend {
-            BlendMode::Blend => {"n"}, 
-            BlendMode::Add => {"a"},
-            // _ => {panic!("Unsupported blend mode")},
-        };
+/**
+ * Where a step of this distance ends. If the full step would leave the
+ * walkable area, only the part along the wall is done.
+ *
+ * @return None if no part of the step can be done
+ */
+fn slide(walk_grid: &WalkGrid, position: Vector2<f32>, distance: Vector2<f32>, radius: f32) -> Option<Vector2<f32>> {
+
+    // objects which already overlap a wall may still move, as long as their center stays inside
+    let stuck = !walk_grid.is_circle_walkable(position, radius);
 
-    key.to_string()
+    let steps = [distance, [distance[0], 0.0], [0.0, distance[1]]];
+
+    for step in steps {
+        if step == [0.0, 0.0] {
+            continue;
+        }
+
+        let next = vec2_add(position, step);
+
+        if walk_grid.is_circle_walkable(next, radius) || (stuck && walk_grid.is_walkable(next)) {
+            return Some(next);
+        }
+    }
+
+    None
 }
 
 
@@ -927,6 +1451,7 @@ fn key_to_blend(key: &str) -> BlendMode {
 }
 
 
+#[derive(Serialize, Deserialize)]
 pub struct MapObject {
 
     pub mob_type: MobType,
@@ -942,6 +1467,22 @@ pub struct MapObject {
     pub move_time_total: f32,
     pub move_time_left: f32,
 
+    // waypoints still ahead, the first one is the current move target
+    #[serde(default)]
+    pub path: Vec<Vector2<f32>>,
+
+    // what a projectile does to the target it hits
+    #[serde(default)]
+    pub damage: Option<Damage>,
+
+    // the kind of projectile, for lookups in the projectile builder
+    #[serde(default)]
+    pub projectile_key: Option<String>,
+
+    // pierces, bounces and splits the projectile has left
+    #[serde(default)]
+    pub projectile_state: Option<ProjectileState>,
+
     pub move_end_action: MoveEndAction,
     pub update_action: UpdateAction,
     pub animation_timer: f32,
@@ -950,10 +1491,16 @@ pub struct MapObject {
 
 impl MapObject 
 {   
-    pub fn move_dt(&mut self, dt: f32) 
+    /**
+     * Advances the current move. Creatures can't leave the walkable area,
+     * they slide along the walls, or stop if they can't move on at all.
+     */
+    pub fn move_dt(&mut self, dt: f32, walk_grid: &WalkGrid) 
     {
         if self.move_time_left > 0.0 {
-            let distance = vec2_scale(self.velocity, dt);
+            // the last step only goes as far as the destination
+            let step = dt.min(self.move_time_left);
+            let distance = vec2_scale(self.velocity, step);
             let z_off;
             if self.creature.is_some() {
                 let creature = self.creature.as_ref().unwrap();
@@ -962,8 +1509,24 @@ impl MapObject
             else {
                 z_off = 0.0;
             }
-            
-            self.position = vec2_add(self.position, distance);
+
+            match &self.creature {
+                None => {
+                    self.position = vec2_add(self.position, distance);
+                },
+                Some(creature) => {
+                    match slide(walk_grid, self.position, distance, creature.collision_radius) {
+                        Some(position) => {
+                            self.position = position;
+                        },
+                        None => {
+                            self.stop_moving();
+                            return;
+                        }
+                    }
+                }
+            }
+
             self.move_time_left -= dt;
             self.visual.z_off = z_off;
 
@@ -977,17 +1540,74 @@ impl MapObject
         self.move_time_total = 0.0;
         self.move_time_left = 0.0;
         self.visual.z_off = 0.0;
+        self.path.clear();
+    }
+
+
+    /**
+     * Called when a move ended. If there are waypoints left, the object
+     * heads for the next one.
+     *
+     * @return true if the object is still on its way
+     */
+    fn next_waypoint(&mut self) -> bool
+    {
+        if self.path.is_empty() {
+            return false;
+        }
+
+        self.path.remove(0);
+
+        match (self.path.first(), &self.creature) {
+            (Some(waypoint), Some(creature)) => {
+                let waypoint = *waypoint;
+                let speed = creature.move_speed();
+                head_for(self, waypoint, speed);
+                true
+            },
+            _ => {
+                self.path.clear();
+                false
+            }
+        }
     }
 }
 
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct MapObjectFactory {
     next_id: u64,
 }
 
 
+impl Default for MapObjectFactory {
+    fn default() -> MapObjectFactory {
+        MapObjectFactory::new()
+    }
+}
+
+
 impl MapObjectFactory 
 {
+    pub fn new() -> MapObjectFactory
+    {
+        MapObjectFactory {
+            next_id: 1,
+        }
+    }
+
+
+    pub fn next_uid(&mut self) -> u64
+    {
+        let uid = self.next_id;
+        self.next_id += 1;
+
+        // println!("MapObjectFactory: next id will be {}", self.next_id);
+
+        uid
+    }
+
+
     pub fn create_mob(&mut self, tile_id: usize, tileset_id: usize, position: Vector2<f32>, z_off: f32, scale: f32) -> MapObject 
     {
         let visual = Visual {
@@ -1004,10 +1624,7 @@ impl MapObjectFactory
             particles: ParticleDriver::new(),
         };
 
-        let uid = self.next_id;
-        self.next_id += 1;
-
-        // println!("MapObjectFactory: next id will be {}", self.next_id);
+        let uid = self.next_uid();
 
         MapObject {
             mob_type: MobType::MapObject,
@@ -1020,6 +1637,10 @@ impl MapObjectFactory
             velocity: [0.0, 0.0],
             move_time_total: 0.0,
             move_time_left: 0.0,
+            path: Vec::new(),
+            damage: None,
+            projectile_key: None,
+            projectile_state: None,
 
             move_end_action: MoveEndAction::None,
             update_action: UpdateAction::None,
@@ -1029,7 +1650,7 @@ impl MapObjectFactory
 }
 
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum MoveEndAction {
     None,
     RemoveFromMap,
@@ -1037,7 +1658,7 @@ pub enum MoveEndAction {
 }
 
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum UpdateAction {
     None,
     RemoveFromMap,
@@ -1045,6 +1666,7 @@ pub enum UpdateAction {
 }
 
 
+#[derive(Serialize, Deserialize)]
 pub struct Visual {
     pub base_image_id: usize,
     pub current_image_id: usize,
@@ -1103,7 +1725,7 @@ impl Visual {
 }
 
 
-#[derive(PartialEq, Clone, Copy)]
+#[derive(PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum MobType 
 {
     MapObject,
@@ -1113,6 +1735,7 @@ pub enum MobType
     CreatureProjectile,
 }
 
+#[derive(Serialize, Deserialize)]
 pub enum TransitionDestination 
 {
     Map {
@@ -1126,6 +1749,7 @@ pub enum TransitionDestination
     }, 
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct MapTransition 
 {
 
@@ -0,0 +1,333 @@
+use std::collections::HashMap;
+
+use geo::Polygon;
+use serde::{Serialize, Deserialize};
+use ron::ser::PrettyConfig;
+
+use crate::map::Map;
+use crate::map::MapObject;
+use crate::map::MapObjectFactory;
+use crate::map::MobType;
+use crate::map::MAP_OBJECT_LAYER;
+use crate::map::MapTransition;
+use crate::mob_group::MobGroup;
+use crate::shop::Shop;
+use crate::animation::AnimationData;
+use crate::loader::LoaderError;
+
+
+/**
+ * The savable state of a map, as read from a file. Factories and prototypes
+ * are not part of this, they come from the game resources.
+ */
+#[derive(Deserialize)]
+pub struct MapData {
+    pub name: String,
+    pub map_image_name: String,
+    pub backdrop_image_name: String,
+
+    pub layers: Vec<Vec<MapObject>>,
+    pub animations: Vec<(u64, AnimationData)>,
+    pub transitions: Vec<MapTransition>,
+
+    pub walkable: Vec<Polygon<f32>>,
+    pub blocked: Vec<Polygon<f32>>,
+
+    pub mob_groups: Vec<MobGroup>,
+    pub shops: Vec<Shop>,
+
+    pub factory: MapObjectFactory,
+    pub player_id: u64,
+}
+
+
+/**
+ * Same layout as MapData, but borrowing everything from the map so
+ * it can be written without taking the map apart.
+ */
+#[derive(Serialize)]
+pub struct MapDataRef<'a> {
+    pub name: &'a str,
+    pub map_image_name: &'a str,
+    pub backdrop_image_name: &'a str,
+
+    pub layers: Vec<Vec<&'a MapObject>>,
+    pub animations: Vec<(u64, AnimationData)>,
+    pub transitions: &'a Vec<MapTransition>,
+
+    pub walkable: &'a Vec<Polygon<f32>>,
+    pub blocked: &'a Vec<Polygon<f32>>,
+
+    pub mob_groups: &'a Vec<MobGroup>,
+    pub shops: &'a Vec<Shop>,
+
+    pub factory: &'a MapObjectFactory,
+    pub player_id: u64,
+}
+
+
+/**
+ * Map files are versioned on their own. The old line based format
+ * was v10, the first structured format is 11.
+ */
+pub const MAP_FILE_VERSION: u32 = 11;
+
+
+#[derive(Deserialize)]
+struct MapFile {
+    version: u32,
+    map: MapData,
+}
+
+
+#[derive(Serialize)]
+struct MapFileRef<'a> {
+    version: u32,
+    map: MapDataRef<'a>,
+}
+
+
+impl Map {
+
+    /**
+     * @param include_player Map files don't contain the player, savegames do
+     */
+    pub fn to_data(&self, include_player: bool) -> MapDataRef<'_> {
+
+        let mut layers = Vec::with_capacity(self.layers.len());
+
+        for layer in &self.layers {
+            // sorted by uid, so saving the same map twice gives the same file
+            let mut objects: Vec<&MapObject> = layer.values()
+                .filter(|mob| include_player || mob.uid != self.player_id)
+                .collect();
+            objects.sort_by_key(|mob| mob.uid);
+            layers.push(objects);
+        }
+
+        let mut animations = Vec::new();
+
+        for (uid, animation) in &self.animations {
+            if let Some(data) = animation.to_data() {
+                animations.push((*uid, data));
+            }
+        }
+
+        animations.sort_by_key(|(uid, _data)| *uid);
+
+        MapDataRef {
+            name: &self.name,
+            map_image_name: &self.map_image_name,
+            backdrop_image_name: &self.backdrop_image_name,
+            layers,
+            animations,
+            transitions: &self.transitions,
+            walkable: &self.walkable,
+            blocked: &self.blocked,
+            mob_groups: &self.mob_groups,
+            shops: &self.shops,
+            factory: &self.factory,
+            player_id: self.player_id,
+        }
+    }
+
+
+    /**
+     * Replaces the current map content with the loaded data, including
+     * the player. Factories and prototypes are kept.
+     */
+    pub fn apply_data(&mut self, mut data: MapData) -> Result<(), String> {
+
+        if data.layers.len() > self.layers.len() {
+            return Err(format!("Map data has {} layers, only {} are supported", data.layers.len(), self.layers.len()));
+        }
+
+        let has_player = data.layers.get(MAP_OBJECT_LAYER)
+            .is_some_and(|objects| objects.iter().any(|mob| mob.uid == data.player_id));
+
+        if !has_player {
+            return Err(format!("Map data has no player object with id {}", data.player_id));
+        }
+
+        self.clear();
+
+        let layers = std::mem::take(&mut data.layers);
+
+        for (index, objects) in layers.into_iter().enumerate() {
+            for mut mob in objects {
+                if let Some(creature) = &mut mob.creature {
+                    self.creature_factory.restore(creature);
+                }
+
+                self.layers[index].insert(mob.uid, mob);
+            }
+        }
+
+        for (uid, animation) in &data.animations {
+            self.animations.insert(*uid, animation.to_animation());
+        }
+
+        self.player_id = data.player_id;
+
+        // older saves didn't mark the player
+        if let Some(player) = self.layers[MAP_OBJECT_LAYER].get_mut(&data.player_id) {
+            player.mob_type = MobType::Player;
+        }
+        self.factory = data.factory.clone();
+        self.take_content(data);
+
+        Ok(())
+    }
+
+
+    pub fn to_map_file_string(&self) -> Result<String, String> {
+
+        let file = MapFileRef {
+            version: MAP_FILE_VERSION,
+            map: self.to_data(false),
+        };
+
+        ron::ser::to_string_pretty(&file, PrettyConfig::new()).map_err(|e| e.to_string())
+    }
+
+
+    /**
+     * Replaces the map content with a map file, the player is kept. The
+     * objects get new ids, so they can't clash with the player.
+     */
+    pub fn load_map_file(&mut self, path_name: &str, content: &str) -> Result<(), LoaderError> {
+
+        let mut file: MapFile = ron::from_str(content).map_err(|e| {
+            LoaderError::at_line(path_name, e.position.line, &e.code.to_string())
+        })?;
+
+        if file.version != MAP_FILE_VERSION {
+            return Err(LoaderError {
+                text: Some(file.version.to_string()),
+                .. LoaderError::new(path_name, "unsupported map file version")
+            });
+        }
+
+        if file.map.layers.len() > self.layers.len() {
+            let message = format!("map file has {} layers, only {} are supported", file.map.layers.len(), self.layers.len());
+            return Err(LoaderError::new(path_name, &message));
+        }
+
+        // preserve player
+        let mut player = self.layers[MAP_OBJECT_LAYER].remove(&self.player_id).unwrap();
+        player.stop_moving();
+
+        self.clear();
+
+        let mut new_ids = HashMap::new();
+        let layers = std::mem::take(&mut file.map.layers);
+
+        for (index, objects) in layers.into_iter().enumerate() {
+            for mut mob in objects {
+                let uid = self.factory.next_uid();
+                new_ids.insert(mob.uid, uid);
+                mob.uid = uid;
+
+                if let Some(creature) = &mut mob.creature {
+                    self.creature_factory.restore(creature);
+                }
+
+                self.layers[index].insert(uid, mob);
+            }
+        }
+
+        self.layers[MAP_OBJECT_LAYER].insert(self.player_id, player);
+
+        for (uid, animation) in &file.map.animations {
+            if let Some(new_uid) = new_ids.get(uid) {
+                self.animations.insert(*new_uid, animation.to_animation());
+            }
+        }
+
+        for group in &mut file.map.mob_groups {
+            group.remap_ids(&new_ids);
+        }
+
+        self.take_content(file.map);
+
+        Ok(())
+    }
+
+
+    // everything except objects, animations and ids, which need special treatment
+    fn take_content(&mut self, data: MapData) {
+        self.name = data.name;
+        self.map_image_name = data.map_image_name;
+        self.backdrop_image_name = data.backdrop_image_name;
+        self.transitions = data.transitions;
+        self.walkable = data.walkable;
+        self.blocked = data.blocked;
+        self.mob_groups = data.mob_groups;
+        self.shops = data.shops;
+
+        self.update_walk_grid();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ItemFactory;
+    use crate::map::TransitionDestination;
+    use crate::map::MAP_GROUND_LAYER;
+    use crate::animation::SpinAnimation;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+
+    #[test]
+    fn test_map_file_round_trip() {
+        let mut map = Map::new("Test Map", "", "", ItemFactory::new().unwrap()).unwrap();
+        map.load("town.map", &mut StdRng::seed_from_u64(1)).unwrap();
+
+        let mob = map.factory.create_mob(12, MAP_OBJECT_LAYER, [300.0, 400.0], 0.0, 1.0);
+        let uid = mob.uid;
+        map.layers[MAP_OBJECT_LAYER].insert(uid, mob);
+        map.animations.insert(uid, Box::new(SpinAnimation::new(5.0)));
+        map.add_transition([1.0, 2.0], 50.0, TransitionDestination::Map { to_map: 501, to_location: [3.0, 4.0] });
+
+        let text = map.to_map_file_string().unwrap();
+
+        let mut copy = Map::new("Other Map", "", "", ItemFactory::new().unwrap()).unwrap();
+        copy.set_player_position([77.0, 88.0]);
+        copy.load_map_file("test.map", &text).unwrap();
+
+        assert_eq!(copy.name, "Town Map");
+        assert_eq!(copy.map_image_name, map.map_image_name);
+        assert_eq!(copy.layers[MAP_GROUND_LAYER].len(), map.layers[MAP_GROUND_LAYER].len());
+        assert_eq!(copy.layers[MAP_OBJECT_LAYER].len(), map.layers[MAP_OBJECT_LAYER].len());
+        assert_eq!(copy.transitions.len(), map.transitions.len());
+        assert_eq!(copy.shops.len(), map.shops.len());
+        assert_eq!(copy.walkable, map.walkable);
+        assert_eq!(copy.animations.len(), 1);
+
+        // the player of the loading map stays where it was
+        assert_eq!(copy.get_player_position(), [77.0, 88.0]);
+
+        // saving the loaded map again gives the same file
+        assert_eq!(copy.to_map_file_string().unwrap().len(), text.len());
+    }
+
+
+    #[test]
+    fn test_malformed_v10_line() {
+        let mut map = Map::new("Test Map", "", "", ItemFactory::new().unwrap()).unwrap();
+        let content = "v10\nbegin map header\nBroken\na.png\nb.png\nend map header\nbegin map objects\n0,50,8,x,1675,0,1,1 1 1 1,n\nend map objects\n";
+
+        let walkable = map.walkable.clone();
+        let result = map.import_v10("broken.map", content, &mut StdRng::seed_from_u64(1));
+
+        assert_eq!(result.unwrap_err().to_string(), "broken.map:8 column 'X': expected number, got 'x'");
+        assert!(map.layers[MAP_OBJECT_LAYER].contains_key(&map.player_id));
+
+        // the map was not touched
+        assert_eq!(map.name, "Test Map");
+        assert_eq!(map.walkable, walkable);
+    }
+}
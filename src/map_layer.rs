@@ -0,0 +1,346 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::btree_map;
+
+use vecmath::Vector2;
+
+use crate::map::MapObject;
+
+
+/**
+ * Edge length of the index cells in world units. About the size of a
+ * floor tile, so a cell holds a handful of objects.
+ */
+pub const LAYER_CELL_SIZE: f32 = 256.0;
+
+
+/**
+ * The objects of one map layer, with a uniform grid index over their
+ * positions. Objects are stored sorted by uid, so iterating a layer
+ * visits them in the same order every run.
+ *
+ * The index is kept up to date on insert and remove. Code which changes
+ * the position of an object must call reindex() or use set_position(),
+ * otherwise queries may look for the object in the wrong cell.
+ */
+pub struct MapLayer {
+    objects: BTreeMap<u64, MapObject>,
+    cells: HashMap<[i32; 2], Vec<u64>>,
+    cell_of: HashMap<u64, [i32; 2]>,
+}
+
+
+impl MapLayer {
+
+    pub fn new() -> MapLayer {
+        MapLayer {
+            objects: BTreeMap::new(),
+            cells: HashMap::new(),
+            cell_of: HashMap::new(),
+        }
+    }
+
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+
+    pub fn contains_key(&self, uid: &u64) -> bool {
+        self.objects.contains_key(uid)
+    }
+
+
+    pub fn get(&self, uid: &u64) -> Option<&MapObject> {
+        self.objects.get(uid)
+    }
+
+
+    /**
+     * Call reindex() afterwards if the position of the object was changed.
+     */
+    pub fn get_mut(&mut self, uid: &u64) -> Option<&mut MapObject> {
+        self.objects.get_mut(uid)
+    }
+
+
+    pub fn insert(&mut self, uid: u64, mob: MapObject) -> Option<MapObject> {
+        let cell = cell_at(mob.position);
+        let old = self.objects.insert(uid, mob);
+
+        self.unlink(uid);
+        self.link(uid, cell);
+
+        old
+    }
+
+
+    pub fn remove(&mut self, uid: &u64) -> Option<MapObject> {
+        self.unlink(*uid);
+        self.objects.remove(uid)
+    }
+
+
+    pub fn clear(&mut self) {
+        self.objects.clear();
+        self.cells.clear();
+        self.cell_of.clear();
+    }
+
+
+    pub fn values(&self) -> btree_map::Values<'_, u64, MapObject> {
+        self.objects.values()
+    }
+
+
+    pub fn values_mut(&mut self) -> btree_map::ValuesMut<'_, u64, MapObject> {
+        self.objects.values_mut()
+    }
+
+
+    pub fn iter(&self) -> btree_map::Iter<'_, u64, MapObject> {
+        self.objects.iter()
+    }
+
+
+    pub fn iter_mut(&mut self) -> btree_map::IterMut<'_, u64, MapObject> {
+        self.objects.iter_mut()
+    }
+
+
+    pub fn set_position(&mut self, uid: u64, position: Vector2<f32>) {
+        if let Some(mob) = self.objects.get_mut(&uid) {
+            mob.position = position;
+            self.reindex(uid);
+        }
+    }
+
+
+    /**
+     * Moves the object to the cell of its current position.
+     */
+    pub fn reindex(&mut self, uid: u64) {
+        let cell = match self.objects.get(&uid) {
+            Some(mob) => cell_at(mob.position),
+            None => return,
+        };
+
+        if self.cell_of.get(&uid) != Some(&cell) {
+            self.unlink(uid);
+            self.link(uid, cell);
+        }
+    }
+
+
+    /**
+     * The object closest to the position, if there is one inside the
+     * radius. If two objects are equally close, the one with the lower
+     * uid wins.
+     */
+    pub fn nearest(&self, position: Vector2<f32>, radius: f32, ignore_uid: u64) -> Option<u64> {
+        let mut distance = radius * radius;
+        let mut best = None;
+
+        let min = [position[0] - radius, position[1] - radius];
+        let max = [position[0] + radius, position[1] + radius];
+
+        for uid in self.candidates(min, max) {
+            if uid == ignore_uid {
+                continue;
+            }
+
+            let d2 = square_distance(self.objects[&uid].position, position);
+
+            if d2 < distance || (d2 == distance && best.is_some_and(|best_uid| uid < best_uid)) {
+                distance = d2;
+                best = Some(uid);
+            }
+        }
+
+        best
+    }
+
+
+    /**
+     * @return The uids of all objects inside the circle, sorted.
+     */
+    pub fn in_radius(&self, position: Vector2<f32>, radius: f32) -> Vec<u64> {
+        let min = [position[0] - radius, position[1] - radius];
+        let max = [position[0] + radius, position[1] + radius];
+
+        let mut result: Vec<u64> = self.candidates(min, max)
+            .filter(|uid| square_distance(self.objects[uid].position, position) < radius * radius)
+            .collect();
+
+        result.sort_unstable();
+        result
+    }
+
+
+    /**
+     * @return The uids of all objects inside the rectangle, sorted.
+     */
+    pub fn in_rect(&self, min: Vector2<f32>, max: Vector2<f32>) -> Vec<u64> {
+        let mut result: Vec<u64> = self.candidates(min, max)
+            .filter(|uid| {
+                let p = self.objects[uid].position;
+                p[0] >= min[0] && p[0] <= max[0] && p[1] >= min[1] && p[1] <= max[1]
+            })
+            .collect();
+
+        result.sort_unstable();
+        result
+    }
+
+
+    // all objects in the cells touched by the rectangle, in no particular order
+    fn candidates(&self, min: Vector2<f32>, max: Vector2<f32>) -> impl Iterator<Item = u64> + '_ {
+        let from = cell_at(min);
+        let to = cell_at(max);
+
+        let cell_count = (to[0] - from[0] + 1) as i64 * (to[1] - from[1] + 1) as i64;
+        let in_range = move |cell: &[i32; 2]| {
+            cell[0] >= from[0] && cell[0] <= to[0] && cell[1] >= from[1] && cell[1] <= to[1]
+        };
+
+        // big areas have more cells than the layer has occupied ones
+        let cells: Vec<&Vec<u64>> = if cell_count > self.cells.len() as i64 {
+            self.cells.iter().filter(|(cell, _)| in_range(cell)).map(|(_, uids)| uids).collect()
+        }
+        else {
+            let mut cells = Vec::new();
+            for x in from[0] ..= to[0] {
+                for y in from[1] ..= to[1] {
+                    if let Some(uids) = self.cells.get(&[x, y]) {
+                        cells.push(uids);
+                    }
+                }
+            }
+            cells
+        };
+
+        cells.into_iter().flatten().copied()
+    }
+
+
+    fn link(&mut self, uid: u64, cell: [i32; 2]) {
+        self.cells.entry(cell).or_default().push(uid);
+        self.cell_of.insert(uid, cell);
+    }
+
+
+    fn unlink(&mut self, uid: u64) {
+        if let Some(cell) = self.cell_of.remove(&uid) &&
+           let Some(uids) = self.cells.get_mut(&cell) {
+            uids.retain(|id| *id != uid);
+
+            if uids.is_empty() {
+                self.cells.remove(&cell);
+            }
+        }
+    }
+}
+
+
+impl Default for MapLayer {
+    fn default() -> MapLayer {
+        MapLayer::new()
+    }
+}
+
+
+impl<'a> IntoIterator for &'a MapLayer {
+    type Item = (&'a u64, &'a MapObject);
+    type IntoIter = btree_map::Iter<'a, u64, MapObject>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.objects.iter()
+    }
+}
+
+
+impl<'a> IntoIterator for &'a mut MapLayer {
+    type Item = (&'a u64, &'a mut MapObject);
+    type IntoIter = btree_map::IterMut<'a, u64, MapObject>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.objects.iter_mut()
+    }
+}
+
+
+fn cell_at(position: Vector2<f32>) -> [i32; 2] {
+    [(position[0] / LAYER_CELL_SIZE).floor() as i32, (position[1] / LAYER_CELL_SIZE).floor() as i32]
+}
+
+
+fn square_distance(a: Vector2<f32>, b: Vector2<f32>) -> f32 {
+    let dx = a[0] - b[0];
+    let dy = a[1] - b[1];
+    dx * dx + dy * dy
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::MapObjectFactory;
+
+
+    fn layer_with(positions: &[Vector2<f32>]) -> (MapLayer, Vec<u64>) {
+        let mut factory = MapObjectFactory::new();
+        let mut layer = MapLayer::new();
+        let mut uids = Vec::new();
+
+        for position in positions {
+            let mob = factory.create_mob(1, 1, *position, 0.0, 1.0);
+            uids.push(mob.uid);
+            layer.insert(mob.uid, mob);
+        }
+
+        (layer, uids)
+    }
+
+
+    #[test]
+    fn test_queries() {
+        let (layer, uids) = layer_with(&[[10.0, 10.0], [300.0, 10.0], [-300.0, -300.0], [1000.0, 1000.0], [-10.0, 10.0]]);
+
+        assert_eq!(layer.nearest([250.0, 10.0], 100.0, 0), Some(uids[1]));
+        assert_eq!(layer.nearest([250.0, 10.0], 100.0, uids[1]), None);
+        assert_eq!(layer.nearest([5000.0, 0.0], 100.0, 0), None);
+
+        // same distance, lower uid wins
+        assert_eq!(layer.nearest([0.0, 10.0], 100.0, 0), Some(uids[0]));
+
+        assert_eq!(layer.in_radius([0.0, 0.0], 400.0), vec![uids[0], uids[1], uids[4]]);
+        assert_eq!(layer.in_rect([-400.0, -400.0], [20.0, 20.0]), vec![uids[0], uids[2], uids[4]]);
+        assert_eq!(layer.in_rect([-1.0e6, -1.0e6], [1.0e6, 1.0e6]).len(), 5);
+    }
+
+
+    #[test]
+    fn test_move_and_remove() {
+        let (mut layer, uids) = layer_with(&[[10.0, 10.0], [20.0, 20.0]]);
+
+        layer.set_position(uids[0], [2000.0, 2000.0]);
+        assert_eq!(layer.nearest([15.0, 15.0], 100.0, 0), Some(uids[1]));
+        assert_eq!(layer.nearest([1990.0, 1990.0], 100.0, 0), Some(uids[0]));
+
+        layer.get_mut(&uids[1]).unwrap().position = [2010.0, 2010.0];
+        layer.reindex(uids[1]);
+        assert_eq!(layer.in_radius([2000.0, 2000.0], 50.0), uids);
+
+        layer.remove(&uids[0]);
+        assert_eq!(layer.nearest([1990.0, 1990.0], 100.0, 0), Some(uids[1]));
+        assert_eq!(layer.len(), 1);
+
+        layer.clear();
+        assert!(layer.in_rect([-1.0e6, -1.0e6], [1.0e6, 1.0e6]).is_empty());
+    }
+}
@@ -2,17 +2,37 @@ use std::collections::HashMap;
 
 use rand::Rng;
 use rand::rngs::StdRng;
-use vecmath::{Vector2, vec2_sub, vec2_square_len};
+use vecmath::{Vector2, vec2_add, vec2_sub, vec2_scale, vec2_len, vec2_normalized};
+use serde::{Serialize, Deserialize};
 
+use crate::map_layer::MapLayer;
+use crate::walk_grid::WalkGrid;
+use crate::pathfinding::find_path_within;
+use crate::pathfinding::CREATURE_PATH_NODES;
 use crate::map::MapObject;
 use crate::map::MapObjectFactory;
 use crate::map::MobType;
-use crate::map::move_mob;
+use crate::map::follow_path;
 use crate::game::launch_projectile;
 use crate::projectile::ProjectileBuilder;
+use crate::creature::CreatureAttack;
+use crate::damage::Damage;
+use crate::creature_ai::{AiState, Senses, next_state, ALERT_TIME, LOSE_FACTOR};
 use crate::SoundPlayer;
 
 
+/**
+ * Everything the members need to shoot: ids for the projectiles,
+ * the projectile settings and the launch sounds.
+ */
+pub struct ProjectileSpawner<'a> {
+    pub factory: &'a mut MapObjectFactory,
+    pub projectile_builder: &'a mut ProjectileBuilder,
+    pub speaker: &'a mut SoundPlayer,
+}
+
+
+#[derive(Serialize, Deserialize)]
 pub struct MobGroup {
 
     // Group center x and y - the group should move as a whole
@@ -21,17 +41,28 @@ pub struct MobGroup {
     members: Vec<MobGroupMember>,
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct MobGroupMember {
     id: u64,
 
     // seconds till next action
     action_countdown: f32,
     mobile: bool,
+
+    // older saves don't have this, the members start idle then
+    #[serde(default)]
+    state: AiState,
+
+    // seconds until each attack of the creature can be used again
+    #[serde(default)]
+    cooldowns: Vec<f32>,
 }
 
+#[allow(clippy::upper_case_acronyms)]
 enum MemberAction {
-    SHOOT (Vector2<f32>),
+    SHOOT (Vector2<f32>, usize),
     MOVE (Vector2<f32>),
+    STOP,
 }
 
 impl MobGroup {
@@ -45,6 +76,8 @@ impl MobGroup {
                 id,
                 action_countdown: 0.1 + rng.random::<f32>(),
                 mobile,
+                state: if mobile {AiState::Patrol} else {AiState::Idle},
+                cooldowns: Vec::new(),
             });
         }
 
@@ -55,23 +88,54 @@ impl MobGroup {
     }
 
 
-    pub fn update(&mut self, player_id: u64, dt: f32, mobs: &mut HashMap<u64, MapObject>, rng: &mut StdRng, 
-                  factory: &mut MapObjectFactory, projectile_builder: &mut ProjectileBuilder,
-                  speaker: &mut SoundPlayer) {
+    /**
+     * Members are stored by object id. If the objects got new ids,
+     * members without a new id are dropped.
+     */
+    pub fn remap_ids(&mut self, new_ids: &HashMap<u64, u64>) {
+        self.members.retain_mut(|member| {
+            match new_ids.get(&member.id) {
+                Some(id) => {
+                    member.id = *id;
+                    true
+                },
+                None => false,
+            }
+        });
+    }
+
+
+    pub fn update(&mut self, player_id: u64, dt: f32, mobs: &mut MapLayer, walk_grid: &WalkGrid, rng: &mut StdRng, 
+                  spawner: &mut ProjectileSpawner) {
             
-        let player_position = mobs.get(&player_id).unwrap().position;
+        let player = mobs.get(&player_id).unwrap();
+        let player_position = player.position;
+        let player_alive = player.creature.as_ref().is_some_and(|creature| creature.hit_points > 0);
 
         let mut kill_list = Vec::new();
-        let mut action_map: HashMap<u64, MemberAction> = HashMap::new();
-        let mut index = 0;
+        let mut state_map: HashMap<u64, AiState> = HashMap::new();
+        let mut action_map: HashMap<u64, (MemberAction, f32)> = HashMap::new();
 
-        // count down the action timers
+        // count down the action timers, chilled members act slower
         for member in &mut self.members {
-            member.action_countdown -= dt;
+            let speed_factor = match mobs.get(&member.id).and_then(|mob| mob.creature.as_ref()) {
+                Some(creature) => creature.effects.speed_factor(),
+                None => 1.0,
+            };
+
+            member.action_countdown -= dt * speed_factor;
+
+            for cooldown in &mut member.cooldowns {
+                *cooldown -= dt * speed_factor;
+            }
         }
 
+        // a member which has seen the player calls the others
+        let group_alerted = self.members.iter()
+            .any(|member| member.state == AiState::Alert || member.state == AiState::Chase || member.state == AiState::Attack);
+
         // now see who is ready to do something
-        for member in &self.members {
+        for (index, member) in self.members.iter().enumerate() {
 
             let mob_opt = mobs.get(&member.id);
 
@@ -81,76 +145,127 @@ impl MobGroup {
                     kill_list.insert(0, index);
                 }
                 Some(mob) => {
+                    let creature = mob.creature.as_ref().unwrap();
+
+                    // the dead are about to fade away
+                    if creature.hit_points <= 0 {
+                        continue;
+                    }
 
-                    if member.action_countdown < 0.0 {
+                    let player_distance = vec2_len(vec2_sub(player_position, mob.position));
+
+                    let senses = Senses {
+                        player_distance,
+                        // looking is expensive, only done if the player is near
+                        player_visible: player_distance <= creature.ai.aggro_range * LOSE_FACTOR &&
+                                        walk_grid.is_segment_walkable(mob.position, player_position),
+                        player_alive,
+                        home_distance: vec2_len(vec2_sub(self.center, mob.position)),
+                        health_percent: creature.hit_points * 100 / creature.max_hit_points.max(1),
+                        group_alerted,
+                        mobile: member.mobile,
+                        ready: member.action_countdown < 0.0,
+                    };
+
+                    let state = next_state(member.state, &creature.ai, &senses);
+                    let changed = state != member.state;
+
+                    if changed {
+                        state_map.insert(member.id, state);
+                    }
 
-                        // fire at a player?
-                        if rng.random::<f32>() < 0.25 {
+                    // moving states react at once, the others when their timer ran out
+                    let ready = senses.ready || 
+                        (changed && (state == AiState::Chase || state == AiState::Flee || state == AiState::Return));
 
-                            // player in range?
-                            let len = vec2_square_len(vec2_sub(mob.position, player_position));
-                            let reach = 500.0 * 500.0;
-                            if len < reach {
-                                let action = MemberAction::SHOOT(player_position);
-                                action_map.insert(member.id, action);
-                            }
+                    let action = 
+                        if changed && state == AiState::Alert {
+                            // stare at the player for a moment
+                            Some((MemberAction::STOP, ALERT_TIME + rng.random::<f32>() * 0.3))
                         }
-                        else if member.mobile {
-                            
-                            // move
-                            let mut count = 0;
-                            let mut x;
-                            let mut y;
-
-                            loop {
-                                x = mob.position[0] + 100.0 - rng.random::<f32>() * 200.0;
-                                y = mob.position[1] + 100.0 - rng.random::<f32>() * 200.0;
-
-                                let dx = x - self.center[0];
-                                let dy = y - self.center[1];
-
-                                let len = dx * dx + dy * dy;
-                                count += 1;
-
-                                // println!("len={}", len);
-
-                                if len < 100.0 * 100.0 
-                                   && self.is_destination_clear(mobs, x, y)
-                                   || count >= 5 { break; }
-                            } 
-
-                            if count >= 5 {
-                                println!("make {} return from {:?} to group center at {:?}", mob.uid, mob.position, self.center);
-                                x = self.center[0] + 50.0 - rng.random::<f32>() * 100.0;
-                                y = self.center[1] + 50.0 - rng.random::<f32>() * 100.0;
+                        else if ready {
+                            match state {
+                                AiState::Idle => Some((MemberAction::STOP, 0.5)),
+                                AiState::Alert => Some((MemberAction::STOP, 0.3)),
+                                AiState::Patrol => Some((self.wander(mob, mobs, walk_grid, rng), 3.0 + rng.random::<f32>() * 2.0)),
+                                AiState::Chase => {
+                                    // close enough to shoot is good enough
+                                    let keep = (creature.ai.attack_range * 0.7).min(player_distance);
+                                    let target = vec2_add(player_position, vec2_scale(direction(player_position, mob.position), keep));
+                                    let target = if walk_grid.is_walkable(target) {target} else {player_position};
+                                    Some((MemberAction::MOVE(target), 0.5))
+                                },
+                                AiState::Attack => {
+                                    let reach = creature.attacks.iter().map(|attack| attack.range).fold(0.0, f32::max);
+
+                                    match choose_attack(&creature.attacks, &member.cooldowns, player_distance, rng) {
+                                        Some(index) => Some((MemberAction::SHOOT(player_position, index), 0.3 + rng.random::<f32>() * 0.3)),
+                                        // all attacks are cooling down
+                                        None if reach >= player_distance => Some((MemberAction::STOP, 0.2)),
+                                        // no attack reaches, get closer like in the chase, or wait like when idle
+                                        None if member.mobile => {
+                                            let keep = reach * 0.7;
+                                            let target = vec2_add(player_position, vec2_scale(direction(player_position, mob.position), keep));
+                                            let target = if walk_grid.is_walkable(target) {target} else {player_position};
+                                            Some((MemberAction::MOVE(target), 0.5))
+                                        },
+                                        None => Some((MemberAction::STOP, 0.5)),
+                                    }
+                                },
+                                AiState::Flee => {
+                                    let target = vec2_add(mob.position, vec2_scale(direction(player_position, mob.position), 150.0));
+                                    let target = if walk_grid.is_walkable(target) {target} else {self.center};
+                                    Some((MemberAction::MOVE(target), 1.0))
+                                },
+                                AiState::Return => Some((MemberAction::MOVE(self.center), 2.0)),
                             }
-
-                            // println!("id=" + creature.id + "moves to " + x + ", " + y);
-                            let action = MemberAction::MOVE([x, y]);
-                            action_map.insert(member.id, action);
                         }
+                        else if changed && state == AiState::Attack {
+                            // stand and shoot when the timer runs out
+                            Some((MemberAction::STOP, member.action_countdown))
+                        }
+                        else {
+                            None
+                        };
+
+                    if let Some(action) = action {
+                        action_map.insert(member.id, action);
                     }
                 }
             }
-
-            index += 1;
         }
 
         // now perform the chosen actions
         for member in &mut self.members {
-            let action = action_map.get(&member.id);
-            if action.is_some() {
-                match action.unwrap() {
-                    MemberAction::SHOOT(target_position) => {
-                        fire_at(mobs, member.id, *target_position, 
-                                factory, projectile_builder, speaker);
-                        member.action_countdown = 1.0 + rng.random::<f32>() * 1.0;
+            if let Some(state) = state_map.get(&member.id) {
+                member.state = *state;
+            }
+
+            if let Some((action, countdown)) = action_map.get(&member.id) {
+                match action {
+                    MemberAction::SHOOT(target_position, index) => {
+                        let mob = mobs.get_mut(&member.id).unwrap();
+                        mob.stop_moving();
+
+                        let attacks = &mob.creature.as_ref().unwrap().attacks;
+                        let attack = attacks[*index].clone();
+
+                        if member.cooldowns.len() != attacks.len() {
+                            member.cooldowns.resize(attacks.len(), 0.0);
+                        }
+                        member.cooldowns[*index] = attack.cooldown;
+
+                        fire_at(mobs, member.id, *target_position, &attack, spawner);
                     },
                     MemberAction::MOVE(target_position) => {
-                        move_to(mobs, member.id, *target_position);
-                        member.action_countdown = 3.0 + rng.random::<f32>() * 2.0;
+                        move_to(mobs, walk_grid, member.id, *target_position);
+                    },
+                    MemberAction::STOP => {
+                        mobs.get_mut(&member.id).unwrap().stop_moving();
                     },
                 }
+
+                member.action_countdown = *countdown;
             }
         }
 
@@ -161,7 +276,45 @@ impl MobGroup {
         // todo: cleaup of groups with no members left?
     }
 
-    fn is_destination_clear(&self, mobs: &HashMap<u64, MapObject>, x: f32, y: f32) -> bool
+
+    /**
+     * A random spot near the group center, away from the other members.
+     */
+    fn wander(&self, mob: &MapObject, mobs: &MapLayer, walk_grid: &WalkGrid, rng: &mut StdRng) -> MemberAction {
+        let mut count = 0;
+        let mut x;
+        let mut y;
+
+        loop {
+            x = mob.position[0] + 100.0 - rng.random::<f32>() * 200.0;
+            y = mob.position[1] + 100.0 - rng.random::<f32>() * 200.0;
+
+            let dx = x - self.center[0];
+            let dy = y - self.center[1];
+
+            let len = dx * dx + dy * dy;
+            count += 1;
+
+            // println!("len={}", len);
+
+            if len < 100.0 * 100.0 
+               && self.is_destination_clear(mobs, x, y)
+               && walk_grid.is_sweep_walkable(mob.position, [x, y], mob.creature.as_ref().unwrap().collision_radius)
+               || count >= 5 { break; }
+        } 
+
+        if count >= 5 {
+            println!("make {} return from {:?} to group center at {:?}", mob.uid, mob.position, self.center);
+            x = self.center[0] + 50.0 - rng.random::<f32>() * 100.0;
+            y = self.center[1] + 50.0 - rng.random::<f32>() * 100.0;
+        }
+
+        // println!("id=" + creature.id + "moves to " + x + ", " + y);
+        MemberAction::MOVE([x, y])
+    }
+
+
+    fn is_destination_clear(&self, mobs: &MapLayer, x: f32, y: f32) -> bool
     {
          for member in &self.members {
             let mob_opt = mobs.get(&member.id);
@@ -195,21 +348,63 @@ impl MobGroup {
 }
 
 
-fn move_to(mobs: &mut HashMap<u64, MapObject>, member_id: u64, target_position: Vector2::<f32>) 
+fn move_to(mobs: &mut MapLayer, walk_grid: &WalkGrid, member_id: u64, target_position: Vector2::<f32>) 
 {
     let mob = mobs.get_mut(&member_id).unwrap();
     let creature = mob.creature.as_ref().unwrap();
-    move_mob(mob, target_position, creature.base_speed);
+    let base_speed = creature.base_speed;
+
+    // stay if there is no way, the next move will try another spot
+    if let Some(path) = find_path_within(walk_grid, mob.position, target_position, creature.collision_radius, CREATURE_PATH_NODES) {
+        follow_path(mob, path, base_speed);
+    }
 }
 
 
-fn fire_at(mobs: &mut HashMap<u64, MapObject>, member_id: u64, target_position: Vector2::<f32>, 
-           factory: &mut MapObjectFactory, projectile_builder: &mut ProjectileBuilder,
-           speaker: &mut SoundPlayer) 
+fn fire_at(mobs: &mut MapLayer, member_id: u64, target_position: Vector2::<f32>, attack: &CreatureAttack,
+           spawner: &mut ProjectileSpawner) 
 {
+    let projectile_builder = &mut *spawner.projectile_builder;
     let mob = mobs.get(&member_id).unwrap();
     let projectile_spawn_distance = mob.creature.as_ref().unwrap().projectile_spawn_distance;
-    let mut projectile = launch_projectile(mob.position, target_position, projectile_spawn_distance, MobType::CreatureProjectile, factory);
-    projectile_builder.configure_projectile("Iron shot", &mut projectile.visual, &mut projectile.velocity, speaker);
+    let mut projectile = launch_projectile(mob.position, target_position, projectile_spawn_distance, MobType::CreatureProjectile, spawner.factory);
+    projectile_builder.configure_projectile(&attack.projectile, &mut projectile.visual, &mut projectile.velocity, spawner.speaker);
+    projectile_builder.arm_projectile(&attack.projectile, &mut projectile);
+
+    // the attack decides how hard the projectile hits
+    projectile.damage = Some(Damage {
+        amount: attack.damage,
+        element: attack.element,
+        .. projectile_builder.base_damage(&attack.projectile)
+    });
+
     mobs.insert(projectile.uid, projectile);
 }
+
+
+/**
+ * Picks one of the attacks which reach the player and are not cooling down.
+ *
+ * @return The index of the attack, None if there is no such attack.
+ */
+fn choose_attack(attacks: &[CreatureAttack], cooldowns: &[f32], distance: f32, rng: &mut StdRng) -> Option<usize>
+{
+    let usable: Vec<usize> = (0 .. attacks.len())
+        .filter(|index| attacks[*index].range >= distance && cooldowns.get(*index).is_none_or(|cooldown| *cooldown <= 0.0))
+        .collect();
+
+    if usable.is_empty() {
+        None
+    }
+    else {
+        Some(usable[rng.random_range(0 .. usable.len())])
+    }
+}
+
+
+// unit vector pointing from one position to the other, zero if they are the same
+fn direction(from: Vector2<f32>, to: Vector2<f32>) -> Vector2<f32>
+{
+    let d = vec2_sub(to, from);
+    if vec2_len(d) > 0.0 {vec2_normalized(d)} else {[0.0, 0.0]}
+}
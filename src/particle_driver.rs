@@ -1,3 +1,5 @@
+use serde::{Serialize, Deserialize};
+
 const PMAX: usize = 1024;
 
 pub struct Particle 
@@ -31,11 +33,20 @@ const NEW_PARTICLE: Particle = Particle {
 };
 
 
+/**
+ * Only the spawn settings are persisted, live particles are
+ * short lived and start out empty after loading.
+ */
+#[derive(Serialize, Deserialize)]
 pub struct ParticleDriver {
+    #[serde(skip)]
     start_search_mark: usize,
+    #[serde(skip)]
     last_particle_mark: usize,
 
-    particles: [Particle; PMAX],
+    // boxed, map objects are moved around a lot
+    #[serde(skip, default = "no_particles")]
+    particles: Box<[Particle; PMAX]>,
 
     // for auto spawning
     pub spawn_ids: Vec<usize>,
@@ -47,13 +58,25 @@ pub struct ParticleDriver {
 }
 
 
+fn no_particles() -> Box<[Particle; PMAX]> {
+    Box::new([NEW_PARTICLE; PMAX])
+}
+
+
+impl Default for ParticleDriver {
+    fn default() -> ParticleDriver {
+        ParticleDriver::new()
+    }
+}
+
+
 impl ParticleDriver {
 
     pub fn new() -> ParticleDriver {
         ParticleDriver {
             start_search_mark: 0,
             last_particle_mark: 0,
-            particles: [NEW_PARTICLE; PMAX],
+            particles: no_particles(),
 
             spawn_ids: Vec::new(),
             spawn_chance: 0.0,
@@ -62,10 +85,14 @@ impl ParticleDriver {
     }
     
 
-    pub fn add_particle(&mut self, x: f32, y: f32, z: f32, xv: f32, yv: f32, zv: f32, lifetime: f32, tex_id: usize, color: [f32; 3]) -> bool {
+    /**
+     * @param position Offset x, y, z from the owner of the driver
+     * @param velocity Speed along x, y and z
+     */
+    pub fn add_particle(&mut self, position: [f32; 3], velocity: [f32; 3], lifetime: f32, tex_id: usize, color: [f32; 3]) -> bool {
 
         for i in self.start_search_mark .. PMAX {
-            if self.particles[i].active == false {
+            if !self.particles[i].active {
                 // found a free entry
 
                 let particle = &mut self.particles[i];
@@ -73,12 +100,12 @@ impl ParticleDriver {
                 particle.active = true;               // now allocated
                 particle.lifetime = lifetime;
                 particle.age = 0.0;
-                particle.xpos = x;
-                particle.ypos = y;
-                particle.zpos = z;
-                particle.xvel = xv;
-                particle.yvel = yv;
-                particle.zvel = zv;
+                particle.xpos = position[0];
+                particle.ypos = position[1];
+                particle.zpos = position[2];
+                particle.xvel = velocity[0];
+                particle.yvel = velocity[1];
+                particle.zvel = velocity[2];
                 particle.tex_id = tex_id;
                 particle.color = color;
                 
@@ -151,6 +178,6 @@ impl ParticleDriver {
     
     #[allow(dead_code)]
     pub fn has_particles(&self) -> bool {
-        return self.last_particle_mark > 0;
+        self.last_particle_mark > 0
     }
 }
@@ -0,0 +1,234 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use vecmath::Vector2;
+
+use crate::walk_grid::WalkGrid;
+
+
+/**
+ * The search gives up after this many cells. That is a good part of a
+ * dungeon, a destination which needs more is treated as unreachable.
+ */
+pub const MAX_PATH_NODES: usize = 200000;
+
+/**
+ * Creatures search again and again while they chase the player, and they
+ * never walk far from home. This covers the cells in their leash range.
+ */
+pub const CREATURE_PATH_NODES: usize = 5000;
+
+// step costs, straight and diagonal, scaled to integers so the open list can be ordered
+const STRAIGHT_COST: u32 = 10;
+const DIAGONAL_COST: u32 = 14;
+
+const NEIGHBOURS: [(i32, i32); 8] = [(1, 0), (0, 1), (-1, 0), (0, -1), (1, 1), (-1, 1), (-1, -1), (1, -1)];
+
+
+/**
+ * Finds a way through the walkable area of the grid, wide enough for
+ * an object with this collision radius. The destination itself may be
+ * closer to a wall, the object will slide into it.
+ *
+ * @return The waypoints after the start, ending with the destination.
+ *         None if the destination can't be reached.
+ */
+pub fn find_path(grid: &WalkGrid, from: Vector2<f32>, to: Vector2<f32>, radius: f32) -> Option<Vec<Vector2<f32>>> {
+    find_path_within(grid, from, to, radius, MAX_PATH_NODES)
+}
+
+
+/**
+ * Like find_path(), but gives up after max_nodes cells.
+ */
+pub fn find_path_within(grid: &WalkGrid, from: Vector2<f32>, to: Vector2<f32>, radius: f32,
+                        max_nodes: usize) -> Option<Vec<Vector2<f32>>> {
+
+    if !grid.is_walkable(to) {
+        return None;
+    }
+
+    if grid.is_sweep_walkable(from, to, radius) {
+        return Some(vec![to]);
+    }
+
+    let start = grid.cell_at(from)?;
+    let goal = grid.cell_at(to)?;
+
+    let cells = find_cell_path(grid, start, goal, radius, max_nodes)?;
+
+    let mut points: Vec<Vector2<f32>> = cells.iter().skip(1).map(|cell| grid.cell_center(*cell)).collect();
+    points.pop();
+    points.push(to);
+
+    Some(pull_string(grid, from, &points, radius))
+}
+
+
+/**
+ * A* over the grid cells, 8 directions. Diagonal steps must not cut
+ * corners, otherwise the path could squeeze through wall edges.
+ *
+ * @return The cells from start to goal, both included
+ */
+fn find_cell_path(grid: &WalkGrid, start: [usize; 2], goal: [usize; 2], radius: f32, max_nodes: usize) -> Option<Vec<[usize; 2]>> {
+
+    let width = grid.width;
+    let index = |cell: [usize; 2]| cell[1] * width + cell[0];
+
+    // cells too close to a wall are left out, except the goal
+    let is_open = |cell: [usize; 2]| {
+        grid.is_cell_walkable(cell) && (cell == goal || grid.is_circle_walkable(grid.cell_center(cell), radius))
+    };
+
+    let mut cost = vec![u32::MAX; grid.width * grid.height];
+    let mut came_from = vec![usize::MAX; grid.width * grid.height];
+    let mut open = BinaryHeap::new();
+    let mut visited = 0;
+
+    cost[index(start)] = 0;
+    open.push(Reverse((estimate(start, goal), 0, index(start))));
+
+    while let Some(Reverse((_estimate, cell_cost, current))) = open.pop() {
+
+        if current == index(goal) {
+            return Some(backtrack(&came_from, current, width));
+        }
+
+        // already reached on a cheaper way
+        if cell_cost > cost[current] {
+            continue;
+        }
+
+        visited += 1;
+        if visited > max_nodes {
+            return None;
+        }
+
+        let x = (current % width) as i32;
+        let y = (current / width) as i32;
+
+        for (dx, dy) in NEIGHBOURS {
+            let next = [(x + dx) as usize, (y + dy) as usize];
+
+            if x + dx < 0 || y + dy < 0 || !is_open(next) {
+                continue;
+            }
+
+            let step = if dx != 0 && dy != 0 {
+                if !is_open([(x + dx) as usize, y as usize]) ||
+                   !is_open([x as usize, (y + dy) as usize]) {
+                    continue;
+                }
+                DIAGONAL_COST
+            }
+            else {
+                STRAIGHT_COST
+            };
+
+            let next_cost = cell_cost + step;
+            let next_index = index(next);
+
+            if next_cost < cost[next_index] {
+                cost[next_index] = next_cost;
+                came_from[next_index] = current;
+                open.push(Reverse((next_cost + estimate(next, goal), next_cost, next_index)));
+            }
+        }
+    }
+
+    None
+}
+
+
+// octile distance, never more than the real cost
+fn estimate(cell: [usize; 2], goal: [usize; 2]) -> u32 {
+    let dx = cell[0].abs_diff(goal[0]) as u32;
+    let dy = cell[1].abs_diff(goal[1]) as u32;
+
+    STRAIGHT_COST * dx.max(dy) + (DIAGONAL_COST - STRAIGHT_COST) * dx.min(dy)
+}
+
+
+fn backtrack(came_from: &[usize], goal: usize, width: usize) -> Vec<[usize; 2]> {
+    let mut cells = Vec::new();
+    let mut current = goal;
+
+    while current != usize::MAX {
+        cells.push([current % width, current / width]);
+        current = came_from[current];
+    }
+
+    cells.reverse();
+    cells
+}
+
+
+/**
+ * Removes all waypoints which can be skipped because the next one
+ * can be seen directly. Turns the zig-zag of grid cells into a few
+ * straight lines.
+ */
+fn pull_string(grid: &WalkGrid, from: Vector2<f32>, points: &[Vector2<f32>], radius: f32) -> Vec<Vector2<f32>> {
+
+    let mut result = Vec::new();
+    let mut anchor = from;
+
+    for i in 0 .. points.len() - 1 {
+        if !grid.is_sweep_walkable(anchor, points[i + 1], radius) {
+            result.push(points[i]);
+            anchor = points[i];
+        }
+    }
+
+    result.push(points[points.len() - 1]);
+    result
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::{Polygon, LineString};
+
+
+    fn square(x1: f32, y1: f32, x2: f32, y2: f32) -> Polygon<f32> {
+        Polygon::new(LineString::from(vec![(x1, y1), (x2, y1), (x2, y2), (x1, y2)]), vec![])
+    }
+
+
+    #[test]
+    fn test_find_path() {
+        // two rooms, connected by a corridor at the bottom
+        let walkable = vec![square(0.0, 0.0, 200.0, 400.0), square(200.0, 300.0, 600.0, 400.0), square(600.0, 0.0, 800.0, 400.0)];
+        let grid = WalkGrid::build(&walkable, &[]);
+
+        let from = [100.0, 50.0];
+        let to = [700.0, 50.0];
+
+        let path = find_path(&grid, from, to, 10.0).unwrap();
+
+        assert_eq!(*path.last().unwrap(), to);
+        assert!(path.len() >= 3 && path.len() < 10);
+
+        let mut position = from;
+        for waypoint in &path {
+            assert!(grid.is_sweep_walkable(position, *waypoint, 10.0));
+            position = *waypoint;
+        }
+
+        // straight lines need no search
+        assert_eq!(find_path(&grid, from, [150.0, 350.0], 10.0), Some(vec![[150.0, 350.0]]));
+
+        assert_eq!(find_path(&grid, from, [400.0, 50.0], 10.0), None);
+
+        // the corridor is too narrow for big objects
+        assert_eq!(find_path(&grid, from, to, 60.0), None);
+
+        // the way through the corridor is too long for a short search
+        assert_eq!(find_path_within(&grid, from, to, 10.0, 50), None);
+
+        let islands = WalkGrid::build(&[square(0.0, 0.0, 200.0, 200.0), square(400.0, 0.0, 600.0, 200.0)], &[]);
+        assert_eq!(find_path(&islands, from, [500.0, 50.0], 10.0), None);
+    }
+}
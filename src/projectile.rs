@@ -1,9 +1,14 @@
 use vecmath::Vector2;
+use serde::{Serialize, Deserialize};
 use std::collections::HashMap;
 
-use crate::read_lines;
-use crate::parse_rgba;
+use crate::loader::CsvFile;
+use crate::loader::LoaderError;
+use crate::damage::Damage;
+use crate::damage::Element;
+use crate::damage::parse_element;
 use crate::map::Visual;
+use crate::map::MapObject;
 use crate::SoundPlayer;
 use crate::gfx::gl_support::BlendMode;
 
@@ -14,35 +19,104 @@ pub struct ProjectileBuilder
 }
 
 
+#[derive(Clone)]
 pub struct ProjectileConfig 
 {
-    speed: f32, // movement speed
-    base_tile_id: usize,
-    directions: usize,
-    phases: usize,
-    glow: [f32;4],
-    sound: usize,
-    volume: f32,
+    pub speed: f32, // movement speed
+    pub base_tile_id: usize,
+    pub directions: usize,
+    pub phases: usize,
+    pub glow: [f32;4],
+    pub sound: usize,
+    pub volume: f32,
+    pub damage: i32,
+    pub element: Element,
+    pub effect_chance: i32,
+
+    // what happens when the projectile hits a target
+    pub hit_sound: usize,
+    pub hit_volume: f32,
+    pub spark_color: [f32; 4],
+
+    // behaviour, see ProjectileState for what is counted per projectile
+    pub lifetime: f32,
+
+    // degrees per second the projectile turns towards the nearest enemy, 0 doesn't seek
+    pub homing: f32,
+    pub pierce: u32,
+    pub bounce: u32,
+
+    // number of smaller projectiles which fly on after a hit
+    pub split: u32,
+    pub explosion_radius: f32,
+
+    // the explosion does this many percent less damage at its rim than in the center
+    pub falloff: f32,
+}
+
+
+/**
+ * What a projectile in flight has left of its behaviour.
+ */
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProjectileState
+{
+    pub pierces_left: u32,
+    pub bounces_left: u32,
+    pub splits: u32,
+
+    // targets which were hit already, piercing projectiles don't hit them twice
+    pub hit: Vec<u64>,
 }
 
 
 impl ProjectileBuilder 
 {
-    pub fn new() -> ProjectileBuilder 
+    pub fn new() -> Result<ProjectileBuilder, LoaderError> 
     {
-        let projectile_data = read_projectile_config();
+        let projectile_data = read_projectile_config()?;
 
-        ProjectileBuilder {
+        Ok(ProjectileBuilder {
             projectile_data
-        }
+        })
+    }
+
+    pub fn configs(&self) -> impl Iterator<Item = (&String, &ProjectileConfig)>
+    {
+        self.projectile_data.iter()
+    }
+
+
+    pub fn config(&self, key: &str) -> Option<&ProjectileConfig>
+    {
+        self.projectile_data.get(key)
+    }
+
+
+    pub fn add(&mut self, key: &str, config: ProjectileConfig)
+    {
+        self.projectile_data.insert(key.to_string(), config);
     }
 
+
     pub fn configure_projectile(&self, key: &str, visual: &mut Visual, velocity: &mut Vector2<f32>, speaker: &mut SoundPlayer) 
     {
         let pd = self.projectile_data.get(&key.to_string()).unwrap();
 
         speaker.play_sound(pd.sound, pd.volume);
 
+        self.configure_without_sound(key, visual, velocity);
+    }
+
+
+    /**
+     * Like configure_projectile(), for projectiles which don't need their own
+     * launch sound, e.g. the parts of a split projectile.
+     */
+    pub fn configure_without_sound(&self, key: &str, visual: &mut Visual, velocity: &mut Vector2<f32>) 
+    {
+        let pd = self.projectile_data.get(key).unwrap();
+
         visual.base_image_id = pd.base_tile_id;
         visual.directions = pd.directions;
         visual.phases = pd.phases;
@@ -53,30 +127,76 @@ impl ProjectileBuilder
         velocity[0] *= pd.speed;
         velocity[1] *= pd.speed;
     }
+
+
+    /**
+     * The damage of this kind of projectile, before any equipment bonus.
+     */
+    pub fn base_damage(&self, key: &str) -> Damage
+    {
+        let pd = self.projectile_data.get(key).unwrap();
+
+        Damage {
+            amount: pd.damage,
+            element: pd.element,
+            effect_chance: pd.effect_chance,
+        }
+    }
+
+
+    /**
+     * Gives a launched projectile the damage, lifetime and behaviour of its kind.
+     */
+    pub fn arm_projectile(&self, key: &str, projectile: &mut MapObject)
+    {
+        let pd = self.projectile_data.get(key).unwrap();
+
+        projectile.move_time_total = pd.lifetime;
+        projectile.move_time_left = pd.lifetime;
+        projectile.damage = Some(self.base_damage(key));
+        projectile.projectile_key = Some(key.to_string());
+        projectile.projectile_state = Some(ProjectileState {
+            pierces_left: pd.pierce,
+            bounces_left: pd.bounce,
+            splits: pd.split,
+            hit: Vec::new(),
+        });
+    }
 }
 
 
-fn read_projectile_config() -> HashMap <String, ProjectileConfig> 
+fn read_projectile_config() -> Result<HashMap <String, ProjectileConfig>, LoaderError> 
 {
-    let lines = read_lines("resources/creatures/projectiles.csv");
+    let file = CsvFile::read("resources/creatures/projectiles.csv")?;
     let mut projectiles = HashMap::new();
 
-    for i in 1..lines.len() {
-        let mut parts = lines[i].split(",");
-
-        let name = parts.next().unwrap().to_string();
+    for mut row in file.rows() {
+        let name = row.next_str()?.to_string();
 
         projectiles.insert(name, 
             ProjectileConfig {
-                speed: parts.next().unwrap().parse::<f32>().unwrap(),
-                base_tile_id: parts.next().unwrap().parse::<usize>().unwrap(),
-                directions: parts.next().unwrap().parse::<usize>().unwrap(),
-                phases: parts.next().unwrap().parse::<usize>().unwrap(),
-                glow: parse_rgba(parts.next().unwrap()),
-                sound: parts.next().unwrap().parse::<usize>().unwrap(),
-                volume: parts.next().unwrap().parse::<f32>().unwrap(),
+                speed: row.next_f32()?,
+                base_tile_id: row.next_usize()?,
+                directions: row.next_usize()?,
+                phases: row.next_usize()?,
+                glow: row.next_rgba()?,
+                sound: row.next_usize()?,
+                volume: row.next_f32()?,
+                damage: row.next_i32()?,
+                element: row.next_with(parse_element)?,
+                effect_chance: row.next_i32()?,
+                hit_sound: row.next_usize()?,
+                hit_volume: row.next_f32()?,
+                spark_color: row.next_rgba()?,
+                lifetime: row.next_f32()?,
+                homing: row.next_f32()?,
+                pierce: row.next_u32()?,
+                bounce: row.next_u32()?,
+                split: row.next_u32()?,
+                explosion_radius: row.next_f32()?,
+                falloff: row.next_f32()?,
             });
     }
 
-    projectiles
+    Ok(projectiles)
 }
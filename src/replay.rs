@@ -0,0 +1,246 @@
+use std::path::Path;
+
+use serde::{Serialize, Deserialize};
+use ron::ser::PrettyConfig;
+
+use crate::ui::ButtonEvent;
+use crate::ui::MouseMoveEvent;
+use crate::ui::ScrollEvent;
+
+
+/**
+ * Version of the recording format written by this build.
+ */
+pub const RECORDING_VERSION: u32 = 1;
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum InputEvent {
+    Button(ButtonEvent),
+    MouseMove(MouseMoveEvent),
+    Scroll(ScrollEvent),
+}
+
+
+/**
+ * An input event and the simulation tick it arrived at. The tick is the
+ * number of simulation steps which had been done before the event.
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedInput {
+    pub tick: u64,
+    pub event: InputEvent,
+}
+
+
+/**
+ * A play session. With the fixed time step the seed and the input
+ * decide everything, so this is enough to play the session again.
+ *
+ * Keys and clicks into the inventory, shop or character sheet are
+ * recorded too, but only the windowed game can replay them.
+ */
+#[derive(Serialize, Deserialize)]
+pub struct Recording {
+    pub version: u32,
+    pub seed: u64,
+
+    // mouse positions are screen coordinates, they need the window size to make sense
+    pub window_size: [u32; 2],
+
+    pub inputs: Vec<RecordedInput>,
+
+    // the tick at which the recording was closed, 0 if it wasn't closed properly
+    #[serde(default)]
+    pub end_tick: u64,
+}
+
+
+impl Recording {
+
+    pub fn new(seed: u64, window_size: [u32; 2]) -> Recording {
+        Recording {
+            version: RECORDING_VERSION,
+            seed,
+            window_size,
+            inputs: Vec::new(),
+            end_tick: 0,
+        }
+    }
+
+
+    pub fn record(&mut self, tick: u64, event: InputEvent) {
+        self.inputs.push(RecordedInput { tick, event });
+    }
+
+
+    /**
+     * Must be called when the session ends, so the replay also
+     * covers the time after the last input.
+     *
+     * @param tick The number of simulation steps done in the session
+     */
+    pub fn finish(&mut self, tick: u64) {
+        self.end_tick = tick;
+    }
+
+
+    /**
+     * @return The tick of the last recorded input, 0 if there is none.
+     */
+    pub fn last_tick(&self) -> u64 {
+        self.inputs.last().map(|input| input.tick).unwrap_or(0)
+    }
+
+
+    /**
+     * @return The number of simulation steps to replay. Without end tick
+     *         the replay stops right after the step of the last input.
+     */
+    pub fn duration(&self) -> u64 {
+        self.end_tick.max(self.last_tick() + 1)
+    }
+
+
+    pub fn to_ron_string(&self) -> Result<String, String> {
+        ron::ser::to_string_pretty(self, PrettyConfig::new()).map_err(|e| e.to_string())
+    }
+
+
+    pub fn parse(content: &str) -> Result<Recording, String> {
+
+        let recording: Recording = ron::from_str(content).map_err(|e| e.to_string())?;
+
+        if recording.version != RECORDING_VERSION {
+            return Err(format!("Recording version {} is not supported, expected version {}", recording.version, RECORDING_VERSION));
+        }
+
+        Ok(recording)
+    }
+
+
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+
+        let content = self.to_ron_string()?;
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+        }
+
+        std::fs::write(path, content).map_err(|e| format!("Cannot write {}: {}", path.display(), e))
+    }
+
+
+    pub fn read(path: &Path) -> Result<Recording, String> {
+
+        let content = std::fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+
+        Recording::parse(&content).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+
+/**
+ * Hands out the recorded input, tick by tick.
+ */
+pub struct Replay {
+    pub recording: Recording,
+    next: usize,
+}
+
+
+impl Replay {
+
+    pub fn new(recording: Recording) -> Replay {
+        Replay {
+            recording,
+            next: 0,
+        }
+    }
+
+
+    /**
+     * All inputs which must be applied before the simulation step
+     * with this tick runs. Each input is handed out only once.
+     */
+    pub fn inputs_for_tick(&mut self, tick: u64) -> Vec<InputEvent> {
+
+        let mut result = Vec::new();
+
+        while let Some(input) = self.recording.inputs.get(self.next) {
+            if input.tick > tick {
+                break;
+            }
+
+            result.push(input.event.clone());
+            self.next += 1;
+        }
+
+        result
+    }
+
+
+    /**
+     * @param tick The number of simulation steps done so far
+     */
+    pub fn is_finished(&self, tick: u64) -> bool {
+        tick >= self.recording.duration()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::{ButtonArgs, ButtonState, Button, MouseButton};
+    use glium::winit::keyboard::{Key, NamedKey};
+
+
+    #[test]
+    fn test_recording_round_trip() {
+        let mut recording = Recording::new(4711, [1200, 770]);
+
+        recording.record(3, InputEvent::MouseMove(MouseMoveEvent { mx: 700.0, my: 300.0 }));
+        recording.record(3, InputEvent::Button(ButtonEvent {
+            args: ButtonArgs {
+                state: ButtonState::Release,
+                button: Button::Mouse(MouseButton::Left),
+                scancode: None,
+            },
+            mx: 700.0,
+            my: 300.0,
+        }));
+        recording.record(10, InputEvent::Scroll(ScrollEvent { dx: 0.0, dy: 1.0, mx: 700.0, my: 300.0 }));
+        recording.record(10, InputEvent::Button(ButtonEvent {
+            args: ButtonArgs {
+                state: ButtonState::Press,
+                button: Button::Keyboard(Key::Named(NamedKey::F5)),
+                scancode: None,
+            },
+            mx: 700.0,
+            my: 300.0,
+        }));
+
+        let text = recording.to_ron_string().unwrap();
+        let loaded = Recording::parse(&text).unwrap();
+
+        assert_eq!(loaded.seed, 4711);
+        assert_eq!(loaded.last_tick(), 10);
+
+        let mut replay = Replay::new(loaded);
+
+        assert_eq!(replay.inputs_for_tick(2).len(), 0);
+        assert_eq!(replay.inputs_for_tick(3).len(), 2);
+        assert_eq!(replay.inputs_for_tick(9).len(), 0);
+        assert_eq!(replay.inputs_for_tick(10).len(), 2);
+        assert!(!replay.is_finished(10));
+        assert!(replay.is_finished(11));
+
+        // a closed recording lasts until its end
+        recording.finish(25);
+        let loaded = Recording::parse(&recording.to_ron_string().unwrap()).unwrap();
+
+        assert_eq!(loaded.duration(), 25);
+        assert!(!Replay::new(loaded).is_finished(24));
+    }
+}
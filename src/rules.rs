@@ -0,0 +1,67 @@
+use std::path::Path;
+
+use crate::loader::CsvFile;
+use crate::loader::LoaderError;
+
+
+pub const RULES_FILE: &str = "resources/rules.csv";
+
+
+/**
+ * Game design numbers which are not tied to a creature, item or projectile.
+ * Rules which are missing in the file keep their default value.
+ */
+pub struct GameRules {
+    // share of the player's money which is lost on death, in percent
+    pub death_money_loss: u32,
+}
+
+
+impl Default for GameRules {
+    fn default() -> GameRules {
+        GameRules {
+            death_money_loss: 25,
+        }
+    }
+}
+
+
+impl GameRules {
+
+    /**
+     * Without a rules file all rules keep their default value.
+     */
+    pub fn new() -> Result<GameRules, LoaderError> {
+        if Path::new(RULES_FILE).exists() {
+            read_rules(RULES_FILE)
+        }
+        else {
+            Ok(GameRules::default())
+        }
+    }
+}
+
+
+fn read_rules(path: &str) -> Result<GameRules, LoaderError> {
+
+    let file = CsvFile::read(path)?;
+    let mut rules = GameRules::default();
+
+    for mut row in file.rows() {
+        let key = row.next_str()?.trim();
+
+        match key {
+            "" => {},
+            "death_money_loss" => {
+                rules.death_money_loss = row.next_u32()?;
+
+                if rules.death_money_loss > 100 {
+                    return Err(row.error("expected a share of 0 to 100 percent", &rules.death_money_loss.to_string()));
+                }
+            },
+            _ => return Err(row.error("unknown rule", key)),
+        }
+    }
+
+    Ok(rules)
+}
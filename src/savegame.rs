@@ -0,0 +1,266 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+
+use serde::{Serialize, Deserialize};
+use ron::ser::PrettyConfig;
+
+use crate::Map;
+use crate::Inventory;
+use crate::map_data::MapData;
+use crate::map_data::MapDataRef;
+use crate::character::Character;
+
+
+/**
+ * Version of the savegame format written by this build. Bump this whenever
+ * the saved data changes. New fields should get a #[serde(default)] so
+ * older saves still parse, anything else needs a step in upgrade().
+ */
+pub const SAVEGAME_VERSION: u32 = 3;
+
+
+/**
+ * Everything needed to continue a play session.
+ */
+#[derive(Deserialize)]
+pub struct SaveGame {
+    pub version: u32,
+    pub next_item_id: u64,
+    pub player_inventory: Inventory,
+    pub map: MapData,
+}
+
+
+#[derive(Serialize)]
+struct SaveGameRef<'a> {
+    version: u32,
+    next_item_id: u64,
+    player_inventory: &'a Inventory,
+    map: MapDataRef<'a>,
+}
+
+
+// only used to find out which version a file has before reading it fully
+#[derive(Deserialize)]
+struct SaveGameHeader {
+    version: u32,
+}
+
+
+pub fn write_savegame(path: &Path, map: &Map, player_inventory: &Inventory) -> Result<(), String> {
+
+    let content = savegame_to_string(map, player_inventory)?;
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+    }
+
+    let file = File::create(path).map_err(|e| format!("Cannot create {}: {}", path.display(), e))?;
+    let mut writer = BufWriter::new(file);
+
+    writer.write_all(content.as_bytes()).map_err(|e| format!("Cannot write {}: {}", path.display(), e))
+}
+
+
+pub fn savegame_to_string(map: &Map, player_inventory: &Inventory) -> Result<String, String> {
+
+    let save = SaveGameRef {
+        version: SAVEGAME_VERSION,
+        next_item_id: map.item_factory.next_id(),
+        player_inventory,
+        map: map.to_data(true),
+    };
+
+    ron::ser::to_string_pretty(&save, PrettyConfig::new()).map_err(|e| e.to_string())
+}
+
+
+pub fn read_savegame(path: &Path) -> Result<SaveGame, String> {
+
+    let content = std::fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+
+    parse_savegame(&content).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+
+pub fn parse_savegame(content: &str) -> Result<SaveGame, String> {
+
+    let header: SaveGameHeader = ron::from_str(content).map_err(|e| e.to_string())?;
+
+    if header.version > SAVEGAME_VERSION {
+        return Err(format!("Savegame version {} is newer than the supported version {}", header.version, SAVEGAME_VERSION));
+    }
+
+    let mut save: SaveGame = ron::from_str(content).map_err(|e| e.to_string())?;
+    upgrade(&mut save)?;
+
+    Ok(save)
+}
+
+
+/**
+ * Brings an older savegame up to the current version, one version at a time.
+ */
+fn upgrade(save: &mut SaveGame) -> Result<(), String> {
+
+    // version 2 added maximum hit points, CreatureFactory::restore() sets
+    // them when the map is applied. The player of a version 1 save had a
+    // single hit point and is healed there.
+    if save.version == 1 {
+        save.version = 2;
+    }
+
+    // version 3 added experience and levels, the player starts at level 1
+    if save.version == 2 {
+        let player_id = save.map.player_id;
+
+        for layer in &mut save.map.layers {
+            for mob in layer.iter_mut().filter(|mob| mob.uid == player_id) {
+                if let Some(creature) = &mut mob.creature && creature.character.is_none() {
+                    creature.character = Some(Character::new());
+                }
+            }
+        }
+
+        save.version = 3;
+    }
+
+    if save.version < SAVEGAME_VERSION {
+        return Err(format!("Don't know how to upgrade savegame version {}", save.version));
+    }
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ItemFactory;
+    use crate::Slot;
+    use crate::item::Activation;
+    use crate::shop::Shop;
+    use crate::map::TransitionDestination;
+    use crate::map::MAP_OBJECT_LAYER;
+    use crate::headless::HeadlessWorld;
+    use crate::headless::HEADLESS_DT;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+
+    #[test]
+    fn test_savegame_round_trip() {
+        let mut map = Map::new("Test Map", "map.png", "backdrop.png", ItemFactory::new().unwrap()).unwrap();
+        let mut inventory = Inventory::new();
+
+        let mut wand = map.item_factory.create_base("engraved_wand").unwrap();
+        wand.activation = Activation::Fireball;
+        let wand_id = wand.id;
+        inventory.put_item(wand, Slot::RHand);
+
+        let mut coins = map.item_factory.create_base("copper_coin").unwrap();
+        coins.stack_size = 321;
+        inventory.put_item(coins, Slot::Bag);
+
+        let mut shop = Shop::new();
+        shop.restock(&mut map.item_factory, &mut StdRng::seed_from_u64(1));
+        map.shops.push(shop);
+        map.add_transition([10.0, 20.0], 30.0, TransitionDestination::Shop { index: 0 });
+        map.set_player_position([123.0, 456.0]);
+
+        let text = savegame_to_string(&map, &inventory).unwrap();
+        let loaded = parse_savegame(&text).unwrap();
+
+        assert_eq!(loaded.version, SAVEGAME_VERSION);
+        assert_eq!(loaded.next_item_id, map.item_factory.next_id());
+        assert_eq!(loaded.player_inventory.entries.len(), 2);
+        assert_eq!(loaded.player_inventory.bag[&wand_id].activation, Activation::Fireball);
+
+        let mut copy = Map::new("Other Map", "", "", ItemFactory::new().unwrap()).unwrap();
+        copy.apply_data(loaded.map).unwrap();
+
+        assert_eq!(copy.name, "Test Map");
+        assert_eq!(copy.get_player_position(), [123.0, 456.0]);
+        assert_eq!(copy.shops[0].items.len(), map.shops[0].items.len());
+        assert_eq!(copy.transitions.len(), 1);
+        assert_eq!(copy.layers[MAP_OBJECT_LAYER].len(), map.layers[MAP_OBJECT_LAYER].len());
+    }
+
+
+    #[test]
+    fn test_dungeon_round_trip() {
+        let mut world = HeadlessWorld::new(12345678901).unwrap();
+        world.run(1.0, HEADLESS_DT);
+
+        let text = savegame_to_string(&world.map, &world.player_inventory).unwrap();
+        let loaded = parse_savegame(&text).unwrap();
+
+        let mut copy = HeadlessWorld::new(98765).unwrap();
+        copy.map.apply_data(loaded.map).unwrap();
+
+        assert_eq!(copy.map.mob_groups.len(), world.map.mob_groups.len());
+        assert_eq!(copy.map.animations.len(), world.map.animations.len());
+        assert_eq!(copy.map.walkable.len(), world.map.walkable.len());
+        assert_eq!(copy.count_creatures(), world.count_creatures());
+
+        // the loaded world must keep running
+        copy.run(1.0, HEADLESS_DT);
+    }
+
+
+    #[test]
+    fn test_upgrade_version_1() {
+        let mut world = HeadlessWorld::new(12345678901).unwrap();
+        world.map.layers[MAP_OBJECT_LAYER].get_mut(&world.map.player_id).unwrap().creature.as_mut().unwrap().hit_points = 1;
+
+        // version 1 had no maximum hit points
+        let text = savegame_to_string(&world.map, &world.player_inventory).unwrap()
+            .replace(&format!("version: {}", SAVEGAME_VERSION), "version: 1")
+            .lines()
+            .filter(|line| !line.contains("max_hit_points"))
+            .collect::<Vec<&str>>()
+            .join("\n");
+
+        let loaded = parse_savegame(&text).unwrap();
+        assert_eq!(loaded.version, SAVEGAME_VERSION);
+
+        let mut copy = HeadlessWorld::new(98765).unwrap();
+        copy.map.apply_data(loaded.map).unwrap();
+
+        let player = copy.map.layers[MAP_OBJECT_LAYER].get(&copy.map.player_id).unwrap();
+        let creature = player.creature.as_ref().unwrap();
+
+        assert!(creature.max_hit_points > 1);
+        assert_eq!(creature.hit_points, creature.max_hit_points);
+        assert!(!copy.map.is_player_dead());
+    }
+
+
+    #[test]
+    fn test_upgrade_version_2() {
+        let mut world = HeadlessWorld::new(12345678901).unwrap();
+        world.map.layers[MAP_OBJECT_LAYER].get_mut(&world.map.player_id).unwrap().creature.as_mut().unwrap().character = None;
+
+        // version 2 had no experience
+        let text = savegame_to_string(&world.map, &world.player_inventory).unwrap()
+            .replace(&format!("version: {}", SAVEGAME_VERSION), "version: 2");
+
+        let loaded = parse_savegame(&text).unwrap();
+        assert_eq!(loaded.version, SAVEGAME_VERSION);
+
+        let mut copy = HeadlessWorld::new(98765).unwrap();
+        copy.map.apply_data(loaded.map).unwrap();
+
+        let player = copy.map.layers[MAP_OBJECT_LAYER].get(&copy.map.player_id).unwrap();
+        assert_eq!(player.creature.as_ref().unwrap().character, Some(Character::new()));
+    }
+
+
+    #[test]
+    fn test_reject_newer_version() {
+        let text = format!("(version: {})", SAVEGAME_VERSION + 1);
+        assert!(parse_savegame(&text).is_err());
+    }
+}
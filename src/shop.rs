@@ -1,5 +1,6 @@
 use std::vec::Vec;
-use rand::SeedableRng;
+use rand::Rng;
+use serde::{Serialize, Deserialize};
 
 use crate::ItemFactory;
 use crate::item::Item;
@@ -9,6 +10,7 @@ use crate::Slot;
 const MAGIC_ITEM_CHANCE: f32 = 0.4;
 const MAGIC_FIND_FACTOR: f32 = 0.8;
 
+#[derive(Serialize, Deserialize)]
 pub struct Shop
 {
     pub name: String,
@@ -32,18 +34,16 @@ impl Shop
     }
 
 
-    pub fn restock(&mut self, item_factory: &mut ItemFactory)
+    pub fn restock<R: Rng + ?Sized>(&mut self, item_factory: &mut ItemFactory, rng: &mut R)
     {
         self.items.clear();
 
-        let mut rng = rand::rngs::StdRng::seed_from_u64(12345678901);
-
         for _i in 0 .. 30 {
-            let mut item = item_factory.create_random_item(&mut rng, 1, 6, MAGIC_ITEM_CHANCE, MAGIC_FIND_FACTOR);
+            let mut item = item_factory.create_random_item(rng, 1, 6, MAGIC_ITEM_CHANCE, MAGIC_FIND_FACTOR);
 
             // we need a better way to generate shop items ...
             while item.key == "copper_coin" {
-                item = item_factory.create_random_item(&mut rng, 1, 6, MAGIC_ITEM_CHANCE, MAGIC_FIND_FACTOR);
+                item = item_factory.create_random_item(rng, 1, 6, MAGIC_ITEM_CHANCE, MAGIC_FIND_FACTOR);
             }
 
             self.items.push(item);
@@ -59,13 +59,13 @@ impl Shop
         let copper = price % 100;
 
         if silver > 0 {
-            let mut silver_coins = item_factory.create_base("silver_coin");
+            let mut silver_coins = item_factory.create_base("silver_coin").expect("coins are required items");
             silver_coins.stack_size = silver;
             player_inventory.put_item(silver_coins, Slot::Bag);
         }
 
         if copper > 0 {
-            let mut copper_coins = item_factory.create_base("copper_coin");
+            let mut copper_coins = item_factory.create_base("copper_coin").expect("coins are required items");
             copper_coins.stack_size = copper;
             player_inventory.put_item(copper_coins, Slot::Bag);
         }
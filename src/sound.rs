@@ -1,11 +1,15 @@
 use std::fs::File;
 use std::io::BufReader;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::cell::Cell;
 
 use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink};
 use rodio::source::{Source, Buffered};
 
 
 #[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Sound {
     Click = 0,
     FireballLaunch = 1,
@@ -13,7 +17,79 @@ pub enum Sound {
 }
 
 
+// sound files, in the order of the sound indices
+pub const SOUND_FILES: [&str; 3] = [
+    "resources/sfx/click.wav",
+    "resources/sfx/fireball_launch.wav",
+    "resources/sfx/fireball_hit_3a.wav",
+];
+
+
+/**
+ * Something which can make the sounds audible - or pretend to.
+ */
+pub trait AudioBackend {
+    fn play_sound(&mut self, index: usize, volume: f32);
+}
+
+
+/**
+ * The audio service handed around in the game. It forwards all
+ * requests to the backend which was chosen at startup.
+ */
 pub struct SoundPlayer {
+    backend: Box<dyn AudioBackend>,
+}
+
+
+impl SoundPlayer {
+
+    /**
+     * Opens the default audio device. If there is none, the player
+     * stays silent instead of failing.
+     */
+    pub fn new() -> SoundPlayer {
+
+        match RodioBackend::new() {
+            Ok(backend) => {
+                SoundPlayer::with_backend(Box::new(backend))
+            },
+            Err(message) => {
+                println!("No audio device available, sound is disabled: {}", message);
+                SoundPlayer::silent()
+            }
+        }
+    }
+
+
+    /**
+     * A player which never opens an audio device, for headless runs.
+     */
+    pub fn silent() -> SoundPlayer {
+        SoundPlayer::with_backend(Box::new(NullBackend {}))
+    }
+
+
+    pub fn with_backend(backend: Box<dyn AudioBackend>) -> SoundPlayer {
+        SoundPlayer {
+            backend,
+        }
+    }
+
+
+    pub fn play(&mut self, id: Sound, volume: f32) {
+        let index = id as usize;
+        self.play_sound(index, volume)
+    }
+
+
+    pub fn play_sound(&mut self, index: usize, volume: f32) {
+        self.backend.play_sound(index, volume);
+    }
+}
+
+
+pub struct RodioBackend {
     _stream: OutputStream,
     _stream_handle: OutputStreamHandle,
     sinks: Vec <Sink>,
@@ -21,44 +97,43 @@ pub struct SoundPlayer {
 }
 
 
-impl SoundPlayer {
+impl RodioBackend {
 
-    pub fn new() -> SoundPlayer {
+    pub fn new() -> Result<RodioBackend, String> {
 
         // stream must live as long as the sink
-        let (stream, stream_handle) = OutputStream::try_default().unwrap();
+        let (stream, stream_handle) = OutputStream::try_default().map_err(|e| e.to_string())?;
 
         // Play the sound directly on the device
         // stream_handle.play_raw(source.clone().convert_samples());
 
         let mut sources = Vec::new();
 
-        sources.push(load_sound("resources/sfx/click.wav"));
-        sources.push(load_sound("resources/sfx/fireball_launch.wav"));
-        sources.push(load_sound("resources/sfx/fireball_hit_3a.wav"));
+        for path in SOUND_FILES {
+            sources.push(load_sound(path));
+        }
 
         let mut sinks = Vec::new();
 
         // we need as many sinks as there should be sounds played in parallel
 
         for _i in 0..4 {
-            sinks.push(Sink::try_new(&stream_handle).unwrap());
+            sinks.push(Sink::try_new(&stream_handle).map_err(|e| e.to_string())?);
         }
 
-        SoundPlayer {
+        Ok(RodioBackend {
             _stream: stream,
             _stream_handle: stream_handle,
             sinks,
             sources,
-        }
+        })
     }
+}
 
-    pub fn play(&self, id: Sound, volume: f32) {
-        let index = id as usize;
-        self.play_sound(index, volume)
-    }
 
-    pub fn play_sound(&self, index: usize, volume: f32) {
+impl AudioBackend for RodioBackend {
+
+    fn play_sound(&mut self, index: usize, volume: f32) {
 
         println!("Playing sound {}", index);
 
@@ -73,10 +148,102 @@ impl SoundPlayer {
 }
 
 
+/**
+ * Swallows all sounds.
+ */
+pub struct NullBackend {
+}
+
+
+impl AudioBackend for NullBackend {
+
+    fn play_sound(&mut self, _index: usize, _volume: f32) {
+    }
+}
+
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SoundRecord {
+    pub sound: usize,
+    pub volume: f32,
+
+    // simulation step in which the sound was played
+    pub tick: u64,
+}
+
+
+impl SoundRecord {
+    pub fn is(&self, id: Sound) -> bool {
+        self.sound == id as usize
+    }
+}
+
+
+pub type SoundLog = Rc<RefCell<Vec<SoundRecord>>>;
+
+// the simulation step, shared between the world and the recording backend
+pub type SoundClock = Rc<Cell<u64>>;
+
+
+/**
+ * Logs all sounds instead of playing them. The log is shared, so it can
+ * still be inspected after the backend was handed to a SoundPlayer.
+ */
+pub struct RecordingBackend {
+    clock: SoundClock,
+    log: SoundLog,
+}
+
+
+impl Default for RecordingBackend {
+    fn default() -> RecordingBackend {
+        RecordingBackend::new()
+    }
+}
+
+
+impl RecordingBackend {
+
+    pub fn new() -> RecordingBackend {
+        RecordingBackend {
+            clock: Rc::new(Cell::new(0)),
+            log: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+
+
+    pub fn log(&self) -> SoundLog {
+        self.log.clone()
+    }
+
+
+    /**
+     * The owner of the simulation sets the current step here, so
+     * the records are the same in each run with the same input.
+     */
+    pub fn clock(&self) -> SoundClock {
+        self.clock.clone()
+    }
+}
+
+
+impl AudioBackend for RecordingBackend {
+
+    fn play_sound(&mut self, index: usize, volume: f32) {
+        self.log.borrow_mut().push(SoundRecord {
+            sound: index,
+            volume,
+            tick: self.clock.get(),
+        });
+    }
+}
+
+
 fn load_sound(path: &str) -> Buffered<Decoder<BufReader<File>>> {
     // Load a sound from a file, using a path relative to Cargo.toml
     let file = BufReader::new(File::open(path).unwrap());
 
     // Decode that sound file into a source
     Decoder::new(file).unwrap().buffered()
-}
\ No newline at end of file
+}
@@ -0,0 +1,300 @@
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+
+use crate::damage::Element;
+use crate::particle_driver::ParticleDriver;
+
+
+// burning takes this share of the igniting hit per second, in percent
+pub const BURNING_DAMAGE: f32 = 25.0;
+pub const BURNING_DURATION: f32 = 4.0;
+
+// burning is the only effect which stacks, up to this many fires at once
+pub const MAX_BURNING_STACKS: usize = 3;
+
+// chilled creatures move and act slower by this many percent
+pub const CHILL_SLOW: f32 = 30.0;
+pub const CHILL_DURATION: f32 = 2.0;
+
+// shocked creatures take this many percent more damage from hits
+pub const SHOCK_BONUS: f32 = 20.0;
+pub const SHOCK_DURATION: f32 = 3.0;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StatusKind {
+    Burning,
+    Chilled,
+    Shocked,
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatusEffect {
+    pub kind: StatusKind,
+    pub time_left: f32,
+
+    // burning: damage per second, chilled: slow in percent, shocked: extra damage in percent
+    pub strength: f32,
+}
+
+
+impl StatusEffect {
+
+    /**
+     * The effect an elemental hit causes. Physical hits cause none.
+     *
+     * @param damage The damage the hit did
+     */
+    pub fn from_hit(element: Element, damage: i32) -> Option<StatusEffect> {
+        match element {
+            Element::Physical => None,
+            Element::Fire => Some(StatusEffect {
+                kind: StatusKind::Burning,
+                time_left: BURNING_DURATION,
+                strength: damage as f32 * BURNING_DAMAGE / 100.0,
+            }),
+            Element::Cold => Some(StatusEffect {
+                kind: StatusKind::Chilled,
+                time_left: CHILL_DURATION,
+                strength: CHILL_SLOW,
+            }),
+            Element::Lightning => Some(StatusEffect {
+                kind: StatusKind::Shocked,
+                time_left: SHOCK_DURATION,
+                strength: SHOCK_BONUS,
+            }),
+        }
+    }
+}
+
+
+/**
+ * The lasting effects on a creature or the player.
+ */
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StatusEffects {
+    pub effects: Vec<StatusEffect>,
+
+    // burn damage of less than a hit point, kept for the next update
+    #[serde(default)]
+    damage_carry: f32,
+}
+
+
+impl StatusEffects {
+
+    pub fn new() -> StatusEffects {
+        StatusEffects {
+            effects: Vec::new(),
+            damage_carry: 0.0,
+        }
+    }
+
+
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+
+
+    pub fn has(&self, kind: StatusKind) -> bool {
+        self.effects.iter().any(|effect| effect.kind == kind)
+    }
+
+
+    pub fn clear(&mut self) {
+        self.effects.clear();
+        self.damage_carry = 0.0;
+    }
+
+
+    /**
+     * Fires stack, if there are too many the one which would end first
+     * goes out. Chill and shock don't stack, the stronger effect stays
+     * and the longer duration counts.
+     */
+    pub fn add(&mut self, effect: StatusEffect) {
+
+        if effect.kind == StatusKind::Burning {
+            self.effects.push(effect);
+
+            while self.count(StatusKind::Burning) > MAX_BURNING_STACKS {
+                let shortest = self.effects.iter().enumerate()
+                    .filter(|(_, effect)| effect.kind == StatusKind::Burning)
+                    .min_by(|a, b| a.1.time_left.total_cmp(&b.1.time_left))
+                    .map(|(index, _)| index)
+                    .unwrap();
+
+                self.effects.remove(shortest);
+            }
+        }
+        else {
+            match self.effects.iter_mut().find(|old| old.kind == effect.kind) {
+                Some(old) => {
+                    old.strength = old.strength.max(effect.strength);
+                    old.time_left = old.time_left.max(effect.time_left);
+                },
+                None => self.effects.push(effect),
+            }
+        }
+    }
+
+
+    fn count(&self, kind: StatusKind) -> usize {
+        self.effects.iter().filter(|effect| effect.kind == kind).count()
+    }
+
+
+    /**
+     * Counts the effects down and removes the expired ones.
+     *
+     * @return The burn damage in this time step
+     */
+    pub fn update(&mut self, dt: f32) -> i32 {
+
+        for effect in &self.effects {
+            if effect.kind == StatusKind::Burning {
+                self.damage_carry += effect.strength * dt.min(effect.time_left);
+            }
+        }
+
+        for effect in &mut self.effects {
+            effect.time_left -= dt;
+        }
+
+        self.effects.retain(|effect| effect.time_left > 0.0);
+
+        let damage = self.damage_carry.floor();
+        self.damage_carry -= damage;
+
+        if self.effects.is_empty() {
+            self.damage_carry = 0.0;
+        }
+
+        damage as i32
+    }
+
+
+    /**
+     * Chilled creatures move and act slower.
+     *
+     * @return 1.0 for normal speed, less if chilled
+     */
+    pub fn speed_factor(&self) -> f32 {
+        let slow = self.strongest(StatusKind::Chilled);
+        (100.0 - slow).max(0.0) / 100.0
+    }
+
+
+    /**
+     * Shocked creatures take more damage from hits.
+     */
+    pub fn damage_taken(&self, damage: i32) -> i32 {
+        let bonus = self.strongest(StatusKind::Shocked);
+        (damage as f32 * (100.0 + bonus) / 100.0).round() as i32
+    }
+
+
+    fn strongest(&self, kind: StatusKind) -> f32 {
+        self.effects.iter()
+            .filter(|effect| effect.kind == kind)
+            .map(|effect| effect.strength)
+            .fold(0.0, f32::max)
+    }
+
+
+    /**
+     * The color to draw the affected object with, its own color if there are no effects.
+     */
+    pub fn tint(&self, mut color: [f32; 4]) -> [f32; 4] {
+
+        for kind in [StatusKind::Burning, StatusKind::Chilled, StatusKind::Shocked] {
+            if self.has(kind) {
+                let tint = tint_of(kind);
+                color = [color[0] * tint[0], color[1] * tint[1], color[2] * tint[2], color[3]];
+            }
+        }
+
+        color
+    }
+
+
+    /**
+     * Flames, frost and sparks rise from the affected object.
+     */
+    pub fn emit_particles<R: Rng + ?Sized>(&self, particles: &mut ParticleDriver, dt: f32, rng: &mut R) {
+
+        let chance_per_second = 12.0;
+
+        for kind in [StatusKind::Burning, StatusKind::Chilled, StatusKind::Shocked] {
+            if self.has(kind) && rng.random::<f32>() < chance_per_second * dt {
+                let xv = (rng.random::<f32>() * 2.0 - 1.0) * 10.0;
+                let yv = (rng.random::<f32>() * 2.0 - 1.0) * 10.0;
+                let zv = 30.0 + rng.random::<f32>() * 20.0;
+
+                let tint = tint_of(kind);
+                let spark = 1993 + (rng.random::<f32>() * 5.0) as usize;
+
+                particles.add_particle([0.0, 0.0, 10.0], [xv, yv, zv], 0.8, spark, [tint[0], tint[1], tint[2]]);
+            }
+        }
+    }
+}
+
+
+fn tint_of(kind: StatusKind) -> [f32; 4] {
+    match kind {
+        StatusKind::Burning => [1.0, 0.6, 0.4, 1.0],
+        StatusKind::Chilled => [0.6, 0.8, 1.0, 1.0],
+        StatusKind::Shocked => [1.0, 1.0, 0.5, 1.0],
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WHITE;
+
+
+    #[test]
+    fn test_status_effects() {
+        let mut effects = StatusEffects::new();
+        assert_eq!(effects.tint(WHITE), WHITE);
+
+        // 20 damage ignite for 5 per second, three fires at most
+        for _i in 0 .. 4 {
+            effects.add(StatusEffect::from_hit(Element::Fire, 20).unwrap());
+        }
+
+        assert_eq!(effects.effects.len(), MAX_BURNING_STACKS);
+        assert_eq!(effects.update(1.0), 15);
+
+        // chill doesn't stack, the longer time counts
+        effects.add(StatusEffect { kind: StatusKind::Chilled, time_left: 0.5, strength: 50.0 });
+        effects.add(StatusEffect::from_hit(Element::Cold, 10).unwrap());
+
+        assert_eq!(effects.effects.len(), MAX_BURNING_STACKS + 1);
+        assert_eq!(effects.speed_factor(), 0.5);
+        assert_ne!(effects.tint(WHITE), WHITE);
+
+        // the tint goes over the own color of the object
+        let tint = effects.tint(WHITE);
+        assert_eq!(effects.tint([0.5, 1.0, 1.0, 0.5]), [tint[0] * 0.5, tint[1], tint[2], 0.5]);
+
+        effects.add(StatusEffect::from_hit(Element::Lightning, 10).unwrap());
+        assert_eq!(effects.damage_taken(10), 12);
+
+        assert!(StatusEffect::from_hit(Element::Physical, 10).is_none());
+
+        // the chill ends first, the fires burn to the end
+        assert_eq!(effects.update(CHILL_DURATION), 30);
+        assert!(!effects.has(StatusKind::Chilled));
+        assert!(effects.has(StatusKind::Burning));
+
+        assert_eq!(effects.update(BURNING_DURATION), 15);
+        assert!(effects.is_empty());
+        assert_eq!(effects.speed_factor(), 1.0);
+        assert_eq!(effects.tint(WHITE), WHITE);
+    }
+}
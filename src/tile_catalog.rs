@@ -0,0 +1,136 @@
+use vecmath::Vector2;
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+
+use crate::loader::LoaderError;
+
+
+/**
+ * A tile as described in a .tica catalog, without the image. Tiles of
+ * size 1x1 or smaller are placeholders and have no image.
+ */
+pub struct TileDescription {
+    pub id: usize,
+    pub size: Vector2<f32>,
+    pub foot: Vector2<f32>,
+    pub name: String,
+
+    // line of the description start in the catalog, counting from 1
+    pub line: usize,
+}
+
+
+impl TileDescription {
+
+    pub fn has_image(&self) -> bool {
+        self.size[0] > 1.0 || self.size[1] > 1.0
+    }
+
+
+    pub fn image_path(&self, path_str: &str) -> String {
+        path_str.to_string() + "/" + &self.id.to_string() + "-" + &self.name + ".png"
+    }
+}
+
+
+pub struct TileCatalog {
+    pub path: String,
+    pub tiles: Vec<TileDescription>,
+}
+
+
+/**
+ * Reads a .tica tile catalog. This doesn't need a display, so data
+ * can be checked without opening a window.
+ */
+pub fn read_tile_catalog(path_str: &str, file_str: &str) -> Result<TileCatalog, LoaderError> {
+
+    let mut fullpath = PathBuf::new();
+    fullpath.push(path_str);
+    fullpath.push(file_str);
+    
+    let path = Path::new(fullpath.as_path());    
+    let path_name = path.display().to_string();
+    let rs = read_to_string(path).map_err(|e| LoaderError::new(&path_name, &e.to_string()))?;
+    let line_vec: Vec<&str> = rs.lines().collect();
+
+    println!("Read {} lines from {:?}", line_vec.len(), path);
+
+    let mut tiles = Vec::new();
+
+    for i in 0..line_vec.len() {
+        if line_vec[i].starts_with("Tile Description") {
+            tiles.push(read_tile_description(&path_name, &line_vec, i)?);
+        }
+    }
+
+    Ok(TileCatalog {
+        path: path_name,
+        tiles,
+    })
+}
+
+
+/**
+ * Reads the tile description starting at the given line. Lines are
+ * counted from 0 here, but reported counting from 1.
+ */
+fn read_tile_description(catalog: &str, lines: &[&str], start: usize) -> Result<TileDescription, LoaderError> {
+
+    let line = |offset: usize| {
+        lines.get(start + offset).copied()
+            .ok_or(LoaderError::at_line(catalog, start + offset + 1, "tile description is incomplete"))
+    };
+
+    let number = |offset: usize, column: &str, text: &str| {
+        text.parse::<f32>().map_err(|_| LoaderError {
+            column: Some(column.to_string()),
+            text: Some(text.to_string()),
+            .. LoaderError::at_line(catalog, start + offset + 1, "expected number")
+        })
+    };
+
+    let id_text = line(2)?;
+    let id = id_text.parse::<usize>().map_err(|_| LoaderError {
+        column: Some("id".to_string()),
+        text: Some(id_text.to_string()),
+        .. LoaderError::at_line(catalog, start + 3, "expected integer")
+    })?;
+
+    let mut size = line(3)?.split(" ");
+    let width = number(3, "width", size.next().unwrap_or(""))?;
+    let height = number(3, "height", size.next().unwrap_or(""))?;
+
+    let mut foot = line(5)?.split(" ");
+    let foot_x = number(5, "foot x", foot.next().unwrap_or(""))?;
+    let foot_y = number(5, "foot y", foot.next().unwrap_or(""))?;
+
+    let name = line(11)?;
+
+    // println!("Item {} is {} size={}x{} foot={}x{}", id, name, width, height, foot_x, foot_y);
+
+    Ok(TileDescription {
+        id,
+        size: [width, height],
+        foot: [foot_x, foot_y],
+        name: name.to_string(),
+        line: start + 1,
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn test_load_tileset() {
+        let catalog = read_tile_catalog("resources/gfx/items", "items.tica").unwrap();
+
+        assert_eq!(catalog.tiles.len(), 400);
+        assert_eq!(catalog.tiles[0].id, 1);
+        assert_eq!(catalog.tiles[0].name, "ruby_ring_map");
+        assert!(catalog.tiles[0].has_image());
+    }
+}
\ No newline at end of file
@@ -17,6 +17,8 @@ use glium::Frame;
 use glium::Texture2d;
 use glium::VertexBuffer;
 
+use serde::{Serialize, Deserialize};
+
 pub use tileset::*;
 pub use font::UiFont;
 pub use color::*;
@@ -25,25 +27,25 @@ use crate::gfx::gl_support::Vertex;
 use crate::gfx::gl_support::RectF32;
 use crate::gfx::gl_support::*;
 
-#[derive(PartialEq, Clone, Debug)]
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
 pub enum MouseButton {
     Left,
     Right,
 }
 
-#[derive(PartialEq, Clone, Debug)]
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
 pub enum Button {
     Keyboard(glium::winit::keyboard::Key),
     Mouse(MouseButton),
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum ButtonState {
     Press,
     Release, 
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct ButtonArgs {
     pub state: ButtonState,
     pub button: Button,
@@ -51,7 +53,7 @@ pub struct ButtonArgs {
 }
 
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ButtonEvent {
     pub args: ButtonArgs,
     pub mx: f32,
@@ -406,12 +408,14 @@ impl UI {
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MouseMoveEvent {
     pub mx: f32,
     pub my: f32,
 }
 
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ScrollEvent {
     pub dx: f32,
     pub dy: f32,
@@ -1,11 +1,13 @@
 use vecmath::Vector2;
-use std::{rc::Rc, collections::HashMap, fs::read_to_string, path::{Path, PathBuf}};
+use std::{rc::Rc, collections::HashMap, path::Path};
 
 use glutin::surface::WindowSurface;
 use glium::Display;
 use glium::Texture2d;
 
 use crate::load_texture;
+use crate::loader::LoaderError;
+use crate::tile_catalog::read_tile_catalog;
 
 pub struct Tile {
     pub id: usize,
@@ -48,48 +50,44 @@ impl TileSet {
     }
     */
     
-    pub fn load(display: &Display<WindowSurface>, path_str: &str, file_str: &str) -> TileSet {
+    pub fn load(display: &Display<WindowSurface>, path_str: &str, file_str: &str) -> Result<TileSet, LoaderError> {
         
-        let mut fullpath = PathBuf::new();
-        fullpath.push(path_str);
-        fullpath.push(file_str);
-        
-        let path = Path::new(fullpath.as_path());    
-        let rs = read_to_string(path).unwrap();
-        let mut line_vec = Vec::new();
-        
-        for line in rs.lines() {
-            line_vec.push(line);
-        }
-
-        println!("Read {} lines from {:?}", line_vec.len(), path);
+        let catalog = read_tile_catalog(path_str, file_str)?;
         
         let mut tileset = TileSet {
             tiles_by_id: HashMap::new(),
             tiles_order_to_id: HashMap::new(),
         };
         
-        let mut ordinal = 0;    
-        for i in 0..line_vec.len() {
-            let line = line_vec[i];
+        for (ordinal, description) in catalog.tiles.into_iter().enumerate() {
             
-            if line.starts_with("Tile Description") {
-                
-                let tile_opt = load_tile(display, path_str, &line_vec, i);
-                
-                if tile_opt.is_some() {
-                    let tile = tile_opt.unwrap();
-                    let id = tile.id;
-
-                    tileset.tiles_by_id.insert(id, Rc::new(tile));
-                    tileset.tiles_order_to_id.insert(ordinal, id);
+            if description.has_image() {
+                let filename = description.image_path(path_str);
+
+                if !Path::new(&filename).exists() {
+                    return Err(LoaderError {
+                        text: Some(filename),
+                        .. LoaderError::at_line(&catalog.path, description.line, "image file for tile is missing")
+                    });
                 }
-                
-                ordinal += 1;
+
+                let tex = load_texture(display, &filename);
+                let id = description.id;
+
+                let tile = Tile {
+                    id,
+                    size: description.size,
+                    foot: description.foot,
+                    tex,
+                    name: description.name,
+                };
+
+                tileset.tiles_by_id.insert(id, Rc::new(tile));
+                tileset.tiles_order_to_id.insert(ordinal, id);
             }
         }
 
-        tileset        
+        Ok(tileset)
     }
 
     
@@ -139,51 +137,3 @@ impl TileSet {
         last_id + 1
     }
 }
-
-
-fn load_tile(display: &Display<WindowSurface>, path_str: &str, lines: &Vec<&str>, start: usize) -> Option<Tile> {
-
-    let id = lines[start + 2].parse::<usize>().unwrap();
-
-    let mut size = lines[start + 3].split(" ");
-    let width = size.next().unwrap().parse::<f32>().unwrap();
-    let height = size.next().unwrap().parse::<f32>().unwrap();
-
-    let mut foot = lines[start + 5].split(" ");
-    let foot_x = foot.next().unwrap().parse::<f32>().unwrap();
-    let foot_y = foot.next().unwrap().parse::<f32>().unwrap();
-
-    let name = lines[start + 11];
-
-    let mut result: Option<Tile> = None;
-
-    if width > 1.0 || height > 1.0 {
-        // println!("Item {} is {} size={}x{} foot={}x{}", id, name, width, height, foot_x, foot_y);
-
-        let filename = 
-            path_str.to_string() + "/" + &id.to_string() + "-" + name + ".png";
-        
-        let tex = load_texture(display, &filename);
-
-        result = Some(Tile {
-            id,
-            size: [width, height],
-            foot: [foot_x, foot_y],
-            tex,
-            name: name.to_string(),            
-        });      
-    }
-
-    result
-}
-
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-
-    #[test]
-    fn test_load_tileset() {
-    }
-}
\ No newline at end of file
@@ -0,0 +1,486 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::path::Path;
+
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+use crate::Map;
+use crate::ItemFactory;
+use crate::item::Item;
+use crate::item::ItemPrototype;
+use crate::item::Activation;
+use crate::creature::CreatureFactory;
+use crate::creature::MAX_HIT_RADIUS;
+use crate::creature_ai::LOSE_FACTOR;
+use crate::damage::MAX_RESISTANCE;
+use crate::projectile::ProjectileBuilder;
+use crate::rules::GameRules;
+use crate::rules::RULES_FILE;
+use crate::sound::SOUND_FILES;
+use crate::loader::CsvFile;
+use crate::loader::LoaderError;
+use crate::tile_catalog::read_tile_catalog;
+use crate::tile_catalog::TileCatalog;
+use crate::CREATURE_TILESET;
+use crate::PROJECTILE_TILESET;
+
+
+// same order as GameWorld::layer_tileset
+const TILESET_FILES: [(&str, &str); 8] = [
+    ("resources/gfx/grounds", "map_objects.tica"),
+    ("resources/gfx/objects", "map_objects.tica"),
+    ("resources/gfx/clouds", "map_objects.tica"),
+    ("resources/gfx/creatures", "creatures.tica"),
+    ("resources/gfx/players", "players.tica"),
+    ("resources/gfx/projectiles", "projectiles.tica"),
+    ("resources/gfx/items", "items.tica"),
+    ("resources/gfx/animations", "animations.tica"),
+];
+
+const PLAYER_TILESET: usize = 4;
+const ITEM_TILESET: usize = 6;
+
+const CSV_FILES: [&str; 4] = [
+    "resources/items/items.csv",
+    "resources/items/modifiers.csv",
+    "resources/creatures/creatures.csv",
+    "resources/creatures/projectiles.csv",
+];
+
+const MAP_PATH: &str = "resources/maps";
+
+
+/**
+ * The tile ids which can actually be displayed, per tile set.
+ */
+struct KnownTiles {
+    names: Vec<String>,
+    ids: Vec<HashSet<usize>>,
+}
+
+
+impl KnownTiles {
+
+    fn has(&self, tileset: usize, id: usize) -> bool {
+        self.ids.get(tileset).is_some_and(|ids| ids.contains(&id))
+    }
+
+
+    fn name(&self, tileset: usize) -> &str {
+        self.names.get(tileset).map(|name| name.as_str()).unwrap_or("unknown tile set")
+    }
+}
+
+
+/**
+ * Cross-checks all game data files without opening a window. Problems
+ * are collected, so one run shows everything that needs fixing.
+ */
+pub struct Validator {
+    pub problems: Vec<String>,
+}
+
+
+impl Default for Validator {
+    fn default() -> Validator {
+        Validator::new()
+    }
+}
+
+
+impl Validator {
+
+    pub fn new() -> Validator {
+        Validator {
+            problems: Vec::new(),
+        }
+    }
+
+
+    fn report(&mut self, problem: String) {
+        println!("{}", problem);
+        self.problems.push(problem);
+    }
+
+
+    fn report_error(&mut self, error: LoaderError) {
+        self.report(error.to_string());
+    }
+
+
+    pub fn validate_all(&mut self) {
+
+        let tiles = self.check_tile_catalogs();
+
+        for path in CSV_FILES {
+            self.check_duplicate_keys(path);
+        }
+
+        match ItemFactory::new() {
+            Ok(item_factory) => self.check_items(&item_factory, &tiles),
+            Err(error) => self.report_error(error),
+        }
+
+        let projectile_builder = ProjectileBuilder::new();
+
+        match CreatureFactory::new() {
+            Ok(creature_factory) => self.check_creatures(&creature_factory, projectile_builder.as_ref().ok(), &tiles),
+            Err(error) => self.report_error(error),
+        }
+
+        match projectile_builder {
+            Ok(projectile_builder) => self.check_projectiles(&projectile_builder, &tiles),
+            Err(error) => self.report_error(error),
+        }
+
+        // the rules file is optional
+        if Path::new(RULES_FILE).exists() {
+            self.check_duplicate_keys(RULES_FILE);
+        }
+
+        if let Err(error) = GameRules::new() {
+            self.report_error(error);
+        }
+
+        self.check_maps(&tiles);
+    }
+
+
+    fn check_tile_catalogs(&mut self) -> KnownTiles {
+
+        let mut tiles = KnownTiles {
+            names: Vec::new(),
+            ids: Vec::new(),
+        };
+
+        for (path, file) in TILESET_FILES {
+            tiles.names.push(path.to_string() + "/" + file);
+
+            match read_tile_catalog(path, file) {
+                Ok(catalog) => {
+                    let ids = self.check_catalog(path, &catalog);
+                    tiles.ids.push(ids);
+                },
+                Err(error) => {
+                    self.report_error(error);
+                    tiles.ids.push(HashSet::new());
+                }
+            }
+        }
+
+        tiles
+    }
+
+
+    fn check_catalog(&mut self, path: &str, catalog: &TileCatalog) -> HashSet<usize> {
+
+        let mut ids = HashSet::new();
+        let mut lines = HashMap::new();
+
+        for tile in &catalog.tiles {
+
+            if let Some(first_line) = lines.insert(tile.id, tile.line) {
+                self.report(format!("{}:{}: duplicate tile id {}, first used in line {}", catalog.path, tile.line, tile.id, first_line));
+            }
+
+            if tile.has_image() {
+                let filename = tile.image_path(path);
+
+                if std::path::Path::new(&filename).exists() {
+                    ids.insert(tile.id);
+                }
+                else {
+                    self.report(format!("{}:{}: image file for tile {} is missing, expected '{}'", catalog.path, tile.line, tile.id, filename));
+                }
+            }
+        }
+
+        ids
+    }
+
+
+    fn check_duplicate_keys(&mut self, path: &str) {
+
+        let file = match CsvFile::read(path) {
+            Ok(file) => file,
+            Err(error) => {
+                self.report_error(error);
+                return;
+            }
+        };
+
+        let mut lines = HashMap::new();
+
+        for mut row in file.rows() {
+            let key = row.next_str().unwrap_or("");
+
+            // empty lines separate sections
+            if key.is_empty() {
+                continue;
+            }
+
+            if let Some(first_line) = lines.insert(key.to_string(), row.line) {
+                self.report(format!("{}:{}: duplicate key '{}', first used in line {}", path, row.line, key, first_line));
+            }
+        }
+    }
+
+
+    fn check_items(&mut self, item_factory: &ItemFactory, tiles: &KnownTiles) {
+
+        let mut prototypes: Vec<&ItemPrototype> = item_factory.prototypes().collect();
+        prototypes.sort_by(|a, b| a.key.cmp(&b.key));
+
+        for proto in prototypes {
+
+            if !tiles.has(ITEM_TILESET, proto.inventory_tile_id) {
+                self.report(format!("Item '{}': inventory tile {} is missing in {}", proto.key, proto.inventory_tile_id, tiles.name(ITEM_TILESET)));
+            }
+
+            for offset in stack_image_offsets(proto.max_stack_size) {
+                let id = proto.map_tile_id + offset;
+
+                if !tiles.has(ITEM_TILESET, id) {
+                    self.report(format!("Item '{}': map tile {} is missing in {}", proto.key, id, tiles.name(ITEM_TILESET)));
+                }
+            }
+        }
+    }
+
+
+    /**
+     * @param projectile_builder To check the attacks, None if the projectiles could not be read
+     */
+    fn check_creatures(&mut self, creature_factory: &CreatureFactory, projectile_builder: Option<&ProjectileBuilder>, tiles: &KnownTiles) {
+
+        let mut prototypes: Vec<_> = creature_factory.prototypes().collect();
+        prototypes.sort_by(|a, b| a.0.cmp(b.0));
+
+        for (name, proto) in prototypes {
+
+            // the player has its own tile set
+            let tileset = if name == "Player" {PLAYER_TILESET} else {CREATURE_TILESET};
+
+            for id in proto.base_tile_id .. proto.base_tile_id + proto.frames {
+                if !tiles.has(tileset, id) {
+                    self.report(format!("Creature '{}': frame tile {} (base {} + {} frames) is missing in {}",
+                                        name, id, proto.base_tile_id, proto.frames, tiles.name(tileset)));
+                    break;
+                }
+            }
+
+            // such creatures would chase the player without ever getting close enough to shoot
+            if proto.ai.attack_range > proto.ai.aggro_range * LOSE_FACTOR {
+                self.report(format!("Creature '{}': attack range {} is larger than the range it follows the player, {}",
+                                    name, proto.ai.attack_range, proto.ai.aggro_range * LOSE_FACTOR));
+            }
+
+            if proto.level == 0 {
+                self.report(format!("Creature '{}': level must be 1 or higher", name));
+            }
+
+            // projectiles look for targets only this far, bigger hit boxes would be missed
+            if proto.hit_box.radius <= 0.0 || proto.hit_box.radius > MAX_HIT_RADIUS {
+                self.report(format!("Creature '{}': hit radius {} must be above 0 and at most {}", name, proto.hit_box.radius, MAX_HIT_RADIUS));
+            }
+
+            if proto.hit_box.height < 0.0 {
+                self.report(format!("Creature '{}': hit height {} can't be negative", name, proto.hit_box.height));
+            }
+
+            if proto.ai.flee_percent < 0 || proto.ai.flee_percent > 100 {
+                self.report(format!("Creature '{}': flee health {}% must be between 0 and 100", name, proto.ai.flee_percent));
+            }
+
+            let resistances = proto.resistances;
+
+            for (element, resistance) in [("fire", resistances.fire), ("cold", resistances.cold), ("lightning", resistances.lightning)] {
+                if !(0 ..= MAX_RESISTANCE).contains(&resistance) {
+                    self.report(format!("Creature '{}': {} resistance {}% must be between 0 and {}", name, element, resistance, MAX_RESISTANCE));
+                }
+            }
+
+            for attack in &proto.attacks {
+                if let Some(projectile_builder) = projectile_builder &&
+                   projectile_builder.config(&attack.projectile).is_none() {
+                    self.report(format!("Creature '{}': attack projectile '{}' is missing", name, attack.projectile));
+                }
+
+                if attack.cooldown <= 0.0 {
+                    self.report(format!("Creature '{}': attack '{}' needs a cooldown above 0", name, attack.projectile));
+                }
+            }
+
+            // creatures stop to attack at their attack range, there must be an attack which reaches that far
+            let reach = proto.attacks.iter().map(|attack| attack.range).fold(0.0, f32::max);
+
+            if !proto.attacks.is_empty() && reach < proto.ai.attack_range {
+                self.report(format!("Creature '{}': no attack reaches the attack range {}, the longest reaches {}",
+                                    name, proto.ai.attack_range, reach));
+            }
+        }
+    }
+
+
+    fn check_projectiles(&mut self, projectile_builder: &ProjectileBuilder, tiles: &KnownTiles) {
+
+        let mut configs: Vec<_> = projectile_builder.configs().collect();
+        configs.sort_by(|a, b| a.0.cmp(b.0));
+
+        for (name, config) in configs {
+
+            if config.sound >= SOUND_FILES.len() {
+                self.report(format!("Projectile '{}': sound index {} is unknown, there are {} sounds", name, config.sound, SOUND_FILES.len()));
+            }
+
+            if config.hit_sound >= SOUND_FILES.len() {
+                self.report(format!("Projectile '{}': hit sound index {} is unknown, there are {} sounds", name, config.hit_sound, SOUND_FILES.len()));
+            }
+
+            if config.lifetime <= 0.0 {
+                self.report(format!("Projectile '{}': lifetime {} must be above 0", name, config.lifetime));
+            }
+
+            if config.homing < 0.0 || config.explosion_radius < 0.0 {
+                self.report(format!("Projectile '{}': homing and explosion radius can't be negative", name));
+            }
+
+            if config.falloff < 0.0 || config.falloff > 100.0 {
+                self.report(format!("Projectile '{}': falloff {}% must be between 0 and 100", name, config.falloff));
+            }
+
+            for id in config.base_tile_id .. config.base_tile_id + config.directions {
+                if !tiles.has(PROJECTILE_TILESET, id) {
+                    self.report(format!("Projectile '{}': direction tile {} is missing in {}", name, id, tiles.name(PROJECTILE_TILESET)));
+                    break;
+                }
+            }
+        }
+
+        // item activations fire these
+        for activation in [Activation::Fireball, Activation::FrostBolt, Activation::LightningStrike] {
+            if let Some(key) = activation.projectile_key() &&
+               projectile_builder.config(key).is_none() {
+                self.report(format!("{}: projectile '{}' is missing", activation.info_str(), key));
+            }
+        }
+    }
+
+
+    fn check_maps(&mut self, tiles: &KnownTiles) {
+
+        let entries = match std::fs::read_dir(MAP_PATH) {
+            Ok(entries) => entries,
+            Err(error) => {
+                self.report(format!("{}: {}", MAP_PATH, error));
+                return;
+            }
+        };
+
+        let mut filenames: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .filter(|name| name.ends_with(".map"))
+            .collect();
+
+        filenames.sort();
+
+        for filename in filenames {
+            self.check_map(&filename, tiles);
+        }
+    }
+
+
+    fn check_map(&mut self, filename: &str, tiles: &KnownTiles) {
+
+        let map_opt = ItemFactory::new().and_then(|item_factory| Map::new("Validation", "", "", item_factory));
+
+        let mut map = match map_opt {
+            Ok(map) => map,
+            Err(error) => {
+                self.report_error(error);
+                return;
+            }
+        };
+
+        // only needed to stock the shops
+        let mut rng = StdRng::seed_from_u64(0);
+
+        if let Err(error) = map.load(filename, &mut rng) {
+            self.report_error(error);
+            return;
+        }
+
+        for layer in &map.layers {
+            let mut objects: Vec<_> = layer.values().filter(|mob| mob.uid != map.player_id).collect();
+            objects.sort_by_key(|mob| mob.uid);
+
+            for mob in objects {
+                let tileset = mob.visual.tileset_id;
+                let id = mob.visual.base_image_id;
+
+                if !tiles.has(tileset, id) {
+                    self.report(format!("{}/{}: object at {:?} uses tile {} which is missing in {}",
+                                        MAP_PATH, filename, mob.position, id, tiles.name(tileset)));
+                }
+            }
+        }
+    }
+}
+
+
+/**
+ * All image offsets a stack of this item can show on the map.
+ */
+fn stack_image_offsets(max_stack_size: u32) -> Vec<usize> {
+
+    let mut offsets = Vec::new();
+
+    // one stack size from each range of Item::calc_image_offset_for_stack_size
+    for size in [1, 2, 3, 4, 50, 200, 500] {
+        if size == 1 || size <= max_stack_size {
+            let offset = Item::calc_image_offset_for_stack_size(size);
+            if !offsets.contains(&offset) {
+                offsets.push(offset);
+            }
+        }
+    }
+
+    offsets
+}
+
+
+/**
+ * Entry point for the --validate command line switch.
+ *
+ * @return The process exit code, 0 if all data is fine.
+ */
+pub fn run_validation() -> i32 {
+
+    let mut validator = Validator::new();
+    validator.validate_all();
+
+    if validator.problems.is_empty() {
+        println!("Validation finished, no problems found.");
+        0
+    }
+    else {
+        println!("Validation finished, {} problems found.", validator.problems.len());
+        1
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn test_stack_image_offsets() {
+        assert_eq!(stack_image_offsets(1), vec![0]);
+        assert_eq!(stack_image_offsets(10), vec![0, 2, 4, 6]);
+        assert_eq!(stack_image_offsets(100000).len(), 7);
+    }
+}
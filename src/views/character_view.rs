@@ -0,0 +1,155 @@
+use glium::Frame;
+
+use crate::ui::*;
+use crate::GameWorld;
+use crate::item::Attribute;
+use crate::character::Character;
+use crate::character::experience_for_level;
+use crate::character::AGILITY_DAMAGE;
+use crate::character::SPEED_BONUS;
+use crate::map::MAP_OBJECT_LAYER;
+use crate::sound::Sound;
+
+const SHEET_WIDTH: i32 = 380;
+const SHEET_HEIGHT: i32 = 320;
+const ROW_HEIGHT: i32 = 32;
+
+// the rows with the attributes start this far below the top of the sheet
+const ATTRIBUTES_TOP: i32 = 190;
+
+
+/**
+ * Shows level, experience and the attributes of the player. Unspent
+ * points are spent by clicking the [+] next to an attribute.
+ */
+pub struct CharacterView
+{
+    area: UiArea,
+
+    // the [+] gadgets of the attributes
+    plus_areas: Vec<(UiArea, Attribute)>,
+}
+
+
+impl CharacterView
+{
+    /**
+     * @param x The right edge of the sheet, it is placed left of this
+     */
+    pub fn left_of(x: i32, y: i32) -> CharacterView
+    {
+        let x = x - SHEET_WIDTH;
+
+        let plus_areas = [Attribute::Agility, Attribute::Armor, Attribute::Speed].into_iter()
+            .enumerate()
+            .map(|(row, attribute)| {
+                let area = UiArea::new(x + SHEET_WIDTH - 50, y + ATTRIBUTES_TOP + row as i32 * ROW_HEIGHT - 4, 40, ROW_HEIGHT, row);
+                (area, attribute)
+            })
+            .collect();
+
+        CharacterView {
+            area: UiArea::new(x, y, SHEET_WIDTH, SHEET_HEIGHT, 0),
+            plus_areas,
+        }
+    }
+
+
+    pub fn draw(&self, ui: &UI, target: &mut Frame, world: &GameWorld)
+    {
+        let area = &self.area;
+        let player = world.map.layers[MAP_OBJECT_LAYER].get(&world.map.player_id).unwrap();
+        let creature = player.creature.as_ref().unwrap();
+        let character = creature.character.clone().unwrap_or_default();
+
+        ui.draw_box(target, area.x, area.y, area.w, area.h, &LIGHT_GREY);
+        ui.fill_box(target, area.x + 1, area.y + 1, area.w - 2, area.h - 2, &[0.08, 0.06, 0.03, 0.95]);
+
+        let large = &ui.context.font_large;
+        let font = &ui.context.font_normal;
+        let left = area.x + 16;
+
+        large.draw(&ui.display, target, &ui.program, left, area.y + 20, &format!("Level {}", character.level), &WHITE);
+
+        let lines = [
+            format!("Experience: {} / {}", character.experience, experience_for_level(character.level + 1)),
+            format!("Hit Points: {} / {}", creature.hit_points.max(0), creature.max_hit_points),
+            format!("Points to spend: {}", character.unspent_points),
+        ];
+
+        let mut line_y = area.y + 70;
+
+        for line in lines {
+            font.draw(&ui.display, target, &ui.program, left, line_y, &line, &OFF_WHITE);
+            line_y += ROW_HEIGHT;
+        }
+
+        for (plus_area, attribute) in &self.plus_areas {
+            let row_y = plus_area.y + 4;
+            let text = format!("{}: {}", attribute, character.points(attribute.clone()));
+
+            font.draw(&ui.display, target, &ui.program, left, row_y, &text, &WHITE);
+            ui.context.font_small.draw(&ui.display, target, &ui.program, left + 120, row_y + 2,
+                                       &effect_text(&character, attribute), &LIGHT_GREY);
+
+            if character.unspent_points > 0 {
+                font.draw(&ui.display, target, &ui.program, plus_area.x, row_y, "[+]", &[0.6, 1.0, 0.5, 1.0]);
+            }
+        }
+
+        // close gadget
+        large.draw(&ui.display, target, &ui.program, area.x + area.w - 40, area.y + 20, "[X]", &OFF_WHITE);
+    }
+
+
+    /**
+     * @return (consumed, closed) like the other views
+     */
+    pub fn handle_button_event(&mut self, event: &ButtonEvent, world: &mut GameWorld) -> (bool, bool)
+    {
+        let area = &self.area;
+
+        if event.args.state != ButtonState::Release ||
+           event.args.button != Button::Mouse(MouseButton::Left) {
+            return (false, false);
+        }
+
+        let mx = event.mx as i32;
+        let my = event.my as i32;
+
+        if !area.contains(mx, my) {
+            return (false, false);
+        }
+
+        // did the player click the close button?
+        if mx > area.x + area.w - 40 && my < area.y + 70 {
+            world.speaker.play(Sound::Click, 0.5);
+            return (true, true);
+        }
+
+        if let Some((_, attribute)) = self.plus_areas.iter().find(|(plus_area, _)| plus_area.contains(mx, my)) {
+            let player = world.map.layers[MAP_OBJECT_LAYER].get_mut(&world.map.player_id).unwrap();
+
+            if let Some(character) = &mut player.creature.as_mut().unwrap().character &&
+               character.spend_point(attribute.clone()) {
+                world.speaker.play(Sound::Click, 0.5);
+            }
+        }
+
+        // clicks on the sheet never reach the game world
+        (true, false)
+    }
+}
+
+
+fn effect_text(character: &Character, attribute: &Attribute) -> String
+{
+    let points = character.points(attribute.clone()) as i32;
+
+    match attribute {
+        Attribute::Agility => format!("+{}% damage", points * AGILITY_DAMAGE),
+        Attribute::Armor => format!("-{}% physical damage taken", character.defence()),
+        Attribute::Speed => format!("+{}% walking speed", points * SPEED_BONUS),
+        _ => String::new(),
+    }
+}
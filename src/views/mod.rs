@@ -1,5 +1,6 @@
 pub mod inventory_view;
 pub mod player_items_view;
+pub mod character_view;
 pub mod shop_view;
 
 use glium::Frame;
@@ -6,10 +6,17 @@ use glutin::surface::ResizeableSurface;
 use glutin::surface::SurfaceTypeTrait;
 use glium::Display;
 
-use crate::Texture2d;
+use glium::Texture2d;
 use crate::creature::CreaturePrototype;
 use crate::creature::movement_glide;
 use crate::creature::CreatureAnimation;
+use crate::creature::DEFAULT_COLLISION_RADIUS;
+use crate::creature::CreatureAttack;
+use crate::creature::HitBox;
+use crate::creature_ai::AiConfig;
+use crate::damage::Element;
+use crate::damage::Resistances;
+use crate::WHITE;
 use crate::TileSet;
 use crate::Tile;
 use crate::gfx::Framebuffer;
@@ -56,17 +63,13 @@ pub fn generate_creature<T: SurfaceTypeTrait + ResizeableSurface>(display: &Disp
                                                                   name: &str) -> CreaturePrototype
 {
     let mut tile_id = 0;
-    let generator: fn() -> Voxelstack;
-    let animation_type: CreatureAnimation;
-
-    if name.ends_with("_2") {
-        generator = generate_goblet;
-        animation_type = CreatureAnimation::SPIN(12.0);
-    }
-    else {
-        generator = generate_scorpion;
-        animation_type = CreatureAnimation::NONE;
-    }
+    let generator: fn() -> Voxelstack = 
+        if name.ends_with("_2") {
+            generate_goblet
+        }
+        else {
+            generate_scorpion
+        };
 
     // create n directions
     let n = 16;
@@ -86,13 +89,56 @@ pub fn generate_creature<T: SurfaceTypeTrait + ResizeableSurface>(display: &Disp
         tileset.add_tile(tile);
     }
 
+    generated_creature_prototype(name, tile_id - n + 1, n)
+}
+
+
+/**
+ * The game data of a generated creature, without any images. Headless
+ * runs use this directly since they cannot render the voxel images.
+ */
+pub fn generated_creature_prototype(name: &str, base_tile_id: usize, frames: usize) -> CreaturePrototype
+{
+    let animation_type = 
+        if name.ends_with("_2") {
+            CreatureAnimation::SPIN(12.0)
+        }
+        else {
+            CreatureAnimation::NONE
+        };
+
+    // scorpions come close and spit, goblets cast spells from afar and run when hurt.
+    // The hit boxes don't depend on the images, headless runs must hit the same
+    let (level, hit_box, ai, attacks) = 
+        if name.ends_with("_2") {
+            (2,
+             HitBox { radius: 22.0, height: 0.0 },
+             AiConfig { aggro_range: 400.0, leash_distance: 600.0, attack_range: 400.0, flee_percent: 40 },
+             vec![attack("Frost bolt", 1.5, 450.0, 6, Element::Cold),
+                  attack("Lightning strike", 3.0, 450.0, 5, Element::Lightning)])
+        }
+        else {
+            (1,
+             HitBox { radius: 28.0, height: 0.0 },
+             AiConfig { aggro_range: 500.0, leash_distance: 800.0, attack_range: 250.0, flee_percent: 0 },
+             vec![attack("Iron shot", 1.0, 300.0, 4, Element::Physical),
+                  attack("Fireball", 4.0, 300.0, 8, Element::Fire)])
+        };
+
     CreaturePrototype {
-        base_tile_id: tile_id - n + 1,
-        frames: n,
+        base_tile_id,
+        frames,
         speed: 100.0,
         min_hp: 1,
         max_hp: 2,
+        level,
         projectile_spawn_distance: 25.0,
+        collision_radius: DEFAULT_COLLISION_RADIUS,
+        hit_box,
+        ai,
+        resistances: Resistances::default(),
+        attacks,
+        color: WHITE,
 
         // blend_mode: BlendMode::Add,
         blend_mode: BlendMode::Blend,
@@ -102,6 +148,18 @@ pub fn generate_creature<T: SurfaceTypeTrait + ResizeableSurface>(display: &Disp
 }
 
 
+fn attack(projectile: &str, cooldown: f32, range: f32, damage: i32, element: Element) -> CreatureAttack
+{
+    CreatureAttack {
+        projectile: projectile.to_string(),
+        cooldown,
+        range,
+        damage,
+        element,
+    }
+}
+
+
 pub fn generate_image(pen: &Framebuffer, rot: f32, generator: fn() -> Voxelstack) -> Framebuffer
 {
     let fb_size = 128;
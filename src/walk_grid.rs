@@ -0,0 +1,279 @@
+use vecmath::Vector2;
+use geo::Polygon;
+use geo::Intersects;
+use geo::BoundingRect;
+use geo::coord;
+
+
+/**
+ * Edge length of the walk grid cells in world units. Floor tiles are
+ * 216 units wide, so their edges are off by at most half a cell.
+ */
+pub const WALK_CELL_SIZE: f32 = 16.0;
+
+
+/**
+ * The walkable area of a map, rasterized. A cell is walkable if its center
+ * is inside a walkable polygon and not inside a blocked one. Must be built
+ * again whenever the walkable or blocked polygons of the map change.
+ */
+pub struct WalkGrid {
+    // world position of the top left corner of cell 0, 0
+    pub origin: Vector2<f32>,
+    pub width: usize,
+    pub height: usize,
+    cells: Vec<bool>,
+}
+
+
+impl WalkGrid {
+
+    /**
+     * A grid without any walkable cell.
+     */
+    pub fn new() -> WalkGrid {
+        WalkGrid {
+            origin: [0.0, 0.0],
+            width: 0,
+            height: 0,
+            cells: Vec::new(),
+        }
+    }
+
+
+    pub fn build(walkable: &[Polygon<f32>], blocked: &[Polygon<f32>]) -> WalkGrid {
+
+        let bounds = walkable.iter().filter_map(|polygon| polygon.bounding_rect()).reduce(|a, b| {
+            geo::Rect::new(coord! { x: a.min().x.min(b.min().x), y: a.min().y.min(b.min().y) },
+                           coord! { x: a.max().x.max(b.max().x), y: a.max().y.max(b.max().y) })
+        });
+
+        let bounds = match bounds {
+            Some(bounds) => bounds,
+            None => return WalkGrid::new(),
+        };
+
+        let origin = [(bounds.min().x / WALK_CELL_SIZE).floor() * WALK_CELL_SIZE,
+                      (bounds.min().y / WALK_CELL_SIZE).floor() * WALK_CELL_SIZE];
+        let width = ((bounds.max().x - origin[0]) / WALK_CELL_SIZE).floor() as usize + 1;
+        let height = ((bounds.max().y - origin[1]) / WALK_CELL_SIZE).floor() as usize + 1;
+
+        let mut grid = WalkGrid {
+            origin,
+            width,
+            height,
+            cells: vec![false; width * height],
+        };
+
+        for polygon in walkable {
+            grid.fill(polygon, true);
+        }
+
+        for polygon in blocked {
+            grid.fill(polygon, false);
+        }
+
+        grid
+    }
+
+
+    // sets all cells with their center inside the polygon
+    fn fill(&mut self, polygon: &Polygon<f32>, walkable: bool) {
+
+        let bounds = match polygon.bounding_rect() {
+            Some(bounds) => bounds,
+            None => return,
+        };
+
+        let from = self.clamped_cell([bounds.min().x, bounds.min().y]);
+        let to = self.clamped_cell([bounds.max().x, bounds.max().y]);
+
+        for y in from[1] ..= to[1] {
+            for x in from[0] ..= to[0] {
+                let center = self.cell_center([x, y]);
+
+                // edges count as inside, so adjacent polygons leave no gap
+                if polygon.intersects(&coord! { x: center[0], y: center[1] }) {
+                    self.cells[y * self.width + x] = walkable;
+                }
+            }
+        }
+    }
+
+
+    /**
+     * @return The cell which contains the position, None if the position is outside the grid.
+     */
+    pub fn cell_at(&self, position: Vector2<f32>) -> Option<[usize; 2]> {
+        let x = ((position[0] - self.origin[0]) / WALK_CELL_SIZE).floor();
+        let y = ((position[1] - self.origin[1]) / WALK_CELL_SIZE).floor();
+
+        if x < 0.0 || y < 0.0 || x >= self.width as f32 || y >= self.height as f32 {
+            None
+        }
+        else {
+            Some([x as usize, y as usize])
+        }
+    }
+
+
+    fn clamped_cell(&self, position: Vector2<f32>) -> [usize; 2] {
+        let x = ((position[0] - self.origin[0]) / WALK_CELL_SIZE).floor().max(0.0) as usize;
+        let y = ((position[1] - self.origin[1]) / WALK_CELL_SIZE).floor().max(0.0) as usize;
+
+        [x.min(self.width - 1), y.min(self.height - 1)]
+    }
+
+
+    pub fn cell_center(&self, cell: [usize; 2]) -> Vector2<f32> {
+        [self.origin[0] + (cell[0] as f32 + 0.5) * WALK_CELL_SIZE,
+         self.origin[1] + (cell[1] as f32 + 0.5) * WALK_CELL_SIZE]
+    }
+
+
+    pub fn is_cell_walkable(&self, cell: [usize; 2]) -> bool {
+        cell[0] < self.width && cell[1] < self.height && self.cells[cell[1] * self.width + cell[0]]
+    }
+
+
+    pub fn is_walkable(&self, position: Vector2<f32>) -> bool {
+        match self.cell_at(position) {
+            Some(cell) => self.is_cell_walkable(cell),
+            None => false,
+        }
+    }
+
+
+    /**
+     * @return true if the center and the rim of the circle are walkable.
+     *         Walls thinner than a cell can slip between the rim points.
+     */
+    pub fn is_circle_walkable(&self, center: Vector2<f32>, radius: f32) -> bool {
+
+        if !self.is_walkable(center) {
+            return false;
+        }
+
+        let diagonal = radius * std::f32::consts::FRAC_1_SQRT_2;
+        let rim = [[radius, 0.0], [-radius, 0.0], [0.0, radius], [0.0, -radius],
+                   [diagonal, diagonal], [-diagonal, diagonal], [diagonal, -diagonal], [-diagonal, -diagonal]];
+
+        rim.iter().all(|offset| self.is_walkable([center[0] + offset[0], center[1] + offset[1]]))
+    }
+
+
+    /**
+     * @return true if every point on the straight line between the positions is walkable.
+     */
+    pub fn is_segment_walkable(&self, from: Vector2<f32>, to: Vector2<f32>) -> bool {
+        self.is_sweep_walkable(from, to, 0.0)
+    }
+
+
+    /**
+     * @return true if a circle of this radius can move along the straight
+     *         line between the positions without touching a wall.
+     */
+    pub fn is_sweep_walkable(&self, from: Vector2<f32>, to: Vector2<f32>, radius: f32) -> bool {
+
+        let dx = to[0] - from[0];
+        let dy = to[1] - from[1];
+        let length = (dx * dx + dy * dy).sqrt();
+
+        // a quarter cell step can't jump over a cell
+        let steps = (length / (WALK_CELL_SIZE * 0.25)).ceil() as usize;
+
+        for i in 0 ..= steps {
+            let f = if steps == 0 {0.0} else {i as f32 / steps as f32};
+            let p = [from[0] + dx * f, from[1] + dy * f];
+
+            let ok = if radius > 0.0 {self.is_circle_walkable(p, radius)} else {self.is_walkable(p)};
+
+            if !ok {
+                return false;
+            }
+        }
+
+        true
+    }
+
+
+    /**
+     * Follows the straight line from the start until it leaves the walkable area.
+     *
+     * @return The last walkable point on the line, None if the whole line is walkable.
+     */
+    pub fn find_impact(&self, from: Vector2<f32>, to: Vector2<f32>) -> Option<Vector2<f32>> {
+
+        let dx = to[0] - from[0];
+        let dy = to[1] - from[1];
+        let length = (dx * dx + dy * dy).sqrt();
+        let steps = (length / (WALK_CELL_SIZE * 0.25)).ceil() as usize;
+
+        let mut last = from;
+
+        for i in 0 ..= steps {
+            let f = if steps == 0 {0.0} else {i as f32 / steps as f32};
+            let p = [from[0] + dx * f, from[1] + dy * f];
+
+            if !self.is_walkable(p) {
+                return Some(last);
+            }
+
+            last = p;
+        }
+
+        None
+    }
+}
+
+
+impl Default for WalkGrid {
+    fn default() -> WalkGrid {
+        WalkGrid::new()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::LineString;
+
+
+    fn square(x1: f32, y1: f32, x2: f32, y2: f32) -> Polygon<f32> {
+        Polygon::new(LineString::from(vec![(x1, y1), (x2, y1), (x2, y2), (x1, y2)]), vec![])
+    }
+
+
+    #[test]
+    fn test_walk_grid() {
+        let walkable = vec![square(0.0, 0.0, 400.0, 200.0), square(400.0, 0.0, 600.0, 600.0)];
+        let blocked = vec![square(100.0, 0.0, 200.0, 150.0)];
+
+        let grid = WalkGrid::build(&walkable, &blocked);
+
+        assert!(grid.is_walkable([50.0, 50.0]));
+        assert!(grid.is_walkable([500.0, 500.0]));
+        assert!(!grid.is_walkable([150.0, 50.0]));
+        assert!(!grid.is_walkable([300.0, 300.0]));
+        assert!(!grid.is_walkable([-50.0, 50.0]));
+
+        // inside the area, but neither across the gap between the squares nor through the blocked part
+        assert!(grid.is_segment_walkable([380.0, 100.0], [500.0, 500.0]));
+        assert!(!grid.is_segment_walkable([50.0, 180.0], [500.0, 500.0]));
+        assert!(!grid.is_segment_walkable([50.0, 50.0], [300.0, 50.0]));
+        assert!(!grid.is_segment_walkable([50.0, 180.0], [500.0, 700.0]));
+
+        assert!(grid.is_circle_walkable([50.0, 180.0], 10.0));
+        assert!(!grid.is_circle_walkable([50.0, 180.0], 30.0));
+        assert!(grid.is_sweep_walkable([450.0, 100.0], [450.0, 500.0], 30.0));
+        assert!(!grid.is_sweep_walkable([420.0, 100.0], [420.0, 500.0], 30.0));
+
+        assert_eq!(grid.find_impact([50.0, 100.0], [50.0, 180.0]), None);
+        let impact = grid.find_impact([50.0, 50.0], [300.0, 50.0]).unwrap();
+        assert!(impact[0] > 90.0 && impact[0] < 100.0 && impact[1] == 50.0);
+
+        assert!(!WalkGrid::build(&[], &[]).is_walkable([0.0, 0.0]));
+    }
+}
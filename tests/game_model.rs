@@ -0,0 +1,854 @@
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use vecmath::{vec2_len, vec2_sub};
+
+use rust_and_dust_collector::walk_player_to;
+use rust_and_dust_collector::item::{ItemFactory, Mod, ModKind, Attribute, Unit, Activation};
+use rust_and_dust_collector::creature::{CreatureFactory, CreatureAttack, HitBox, MAX_HIT_RADIUS};
+use rust_and_dust_collector::damage::{Damage, Element};
+use rust_and_dust_collector::projectile::ProjectileConfig;
+use rust_and_dust_collector::status_effect::{StatusEffect, StatusKind};
+use rust_and_dust_collector::combat_text::{element_color, COMBAT_TEXT_TIME};
+use rust_and_dust_collector::character::{Character, experience_for_kill, experience_for_level};
+use rust_and_dust_collector::character::{POINTS_PER_LEVEL, HIT_POINTS_PER_LEVEL, AGILITY_DAMAGE, ARMOR_DEFENCE, SPEED_BONUS};
+use rust_and_dust_collector::inventory::{Inventory, Slot};
+use rust_and_dust_collector::map::{Map, MobType, MAP_OBJECT_LAYER};
+use rust_and_dust_collector::game::{fire_projectile, fire_player_projectile, launch_projectile};
+use rust_and_dust_collector::game::{click_world, respawn_player, WorldClick};
+use rust_and_dust_collector::dungeon::TOWN_ENTRANCE;
+use rust_and_dust_collector::headless::{HeadlessWorld, HEADLESS_DT};
+use rust_and_dust_collector::replay::{InputEvent, Recording};
+use rust_and_dust_collector::pathfinding::find_path;
+use rust_and_dust_collector::ui::{ButtonEvent, ButtonArgs, ButtonState, Button, MouseButton, MouseMoveEvent};
+
+
+/**
+ * The game model must be usable without window, GL context or sound device.
+ */
+#[test]
+fn test_model_without_window() {
+    let mut map = Map::new("Town", "", "", ItemFactory::new().unwrap()).unwrap();
+    map.load("town.map", &mut StdRng::seed_from_u64(1)).unwrap();
+
+    assert!(map.layers[MAP_OBJECT_LAYER].contains_key(&map.player_id));
+    assert!(!map.shops.is_empty());
+    assert!(map.is_walkable(map.get_player_position()));
+
+    let mut inventory = Inventory::new();
+    let mut coins = map.item_factory.create_base("copper_coin").unwrap();
+    coins.stack_size = 100;
+    inventory.put_item(coins, Slot::Bag);
+
+    assert_eq!(inventory.total_money(), 100);
+
+    // unknown keys are reported, not a crash
+    let error = map.item_factory.create_base("no_such_item").unwrap_err();
+    assert_eq!(error.to_string(), "resources/items/items.csv: unknown item, got 'no_such_item'");
+}
+
+
+#[test]
+fn test_headless_world_runs() {
+    let mut world = HeadlessWorld::new(12345678901).unwrap();
+    let creatures = world.count_creatures();
+
+    assert!(world.map.is_walkable(world.map.get_player_position()));
+
+    world.run(2.0, HEADLESS_DT);
+
+    assert!(creatures > 0);
+}
+
+
+/**
+ * Walking into another room must find the way through the corridors.
+ */
+#[test]
+fn test_walk_around_walls() {
+    let mut world = HeadlessWorld::new(12345678901).unwrap();
+    let start = world.map.get_player_position();
+    let grid = &world.map.walk_grid;
+
+    // the first spot which is far away and can't be reached in a straight line
+    let mut dest = None;
+
+    'search: for y in (0 .. grid.height).step_by(8) {
+        for x in (0 .. grid.width).step_by(8) {
+            let center = grid.cell_center([x, y]);
+
+            if grid.is_cell_walkable([x, y]) && vec2_len(vec2_sub(center, start)) > 1000.0 &&
+               !grid.is_segment_walkable(start, center) && find_path(grid, start, center, 16.0).is_some() {
+                dest = Some(center);
+                break 'search;
+            }
+        }
+    }
+
+    let dest = dest.unwrap();
+    character(&mut world).speed = 10;
+    assert!(walk_player_to(&mut world.map, dest));
+
+    let player = world.map.layers[MAP_OBJECT_LAYER].get(&world.map.player_id).unwrap();
+    let waypoints = player.path.len();
+    let first_leg_speed = vec2_len(player.velocity);
+    assert!(waypoints > 1);
+
+    // the speed points count on all legs of the way, not only the first
+    let player_id = world.map.player_id;
+    assert!(run_until(&mut world, 30.0, |world| world.map.layers[MAP_OBJECT_LAYER].get(&player_id).unwrap().path.len() < waypoints));
+
+    let player = world.map.layers[MAP_OBJECT_LAYER].get(&player_id).unwrap();
+    assert_eq!(vec2_len(player.velocity).round(), first_leg_speed.round());
+
+    for _second in 0 .. 120 {
+        world.run(1.0, HEADLESS_DT);
+
+        let player = world.map.layers[MAP_OBJECT_LAYER].get(&world.map.player_id).unwrap();
+        if player.move_time_left <= 0.0 {
+            break;
+        }
+    }
+
+    assert!(vec2_len(vec2_sub(world.map.get_player_position(), dest)) < 1.0);
+}
+
+
+/**
+ * Points in 16 directions around the center.
+ */
+fn around(center: [f32; 2], distance: f32) -> impl Iterator<Item = [f32; 2]> {
+    (0 .. 16).map(move |i| {
+        let angle = i as f32 * std::f32::consts::PI / 8.0;
+        [center[0] + angle.cos() * distance, center[1] + angle.sin() * distance]
+    })
+}
+
+
+/**
+ * A line from the player with enough room to shoot along it.
+ */
+struct Range {
+    start: [f32; 2],
+    direction: [f32; 2],
+}
+
+
+impl Range {
+    /**
+     * @param side Distance to the right of the line
+     */
+    fn at(&self, distance: f32, side: f32) -> [f32; 2] {
+        let [x, y] = self.start;
+        let [dx, dy] = self.direction;
+
+        [x + dx * distance - dy * side, y + dy * distance + dx * side]
+    }
+}
+
+
+/**
+ * A world with only a few creatures, all with plenty of hit points. They
+ * are not in groups, so they don't walk around or shoot.
+ */
+fn shooting_range(creature_count: usize) -> (HeadlessWorld, Vec<u64>, Range) {
+    let mut world = HeadlessWorld::new(12345678901).unwrap();
+    let start = world.map.get_player_position();
+
+    world.map.mob_groups.clear();
+
+    let creatures: Vec<u64> = world.map.layers[MAP_OBJECT_LAYER].values()
+        .filter(|mob| mob.mob_type == MobType::Creature)
+        .map(|mob| mob.uid)
+        .collect();
+
+    for uid in &creatures[creature_count ..] {
+        world.map.layers[MAP_OBJECT_LAYER].remove(uid);
+    }
+
+    for uid in &creatures[.. creature_count] {
+        world.map.layers[MAP_OBJECT_LAYER].get_mut(uid).unwrap().creature.as_mut().unwrap().hit_points = 1000;
+    }
+
+    let direction = around([0.0, 0.0], 1.0)
+        .find(|d| world.map.walk_grid.is_sweep_walkable(start, [start[0] + d[0] * 350.0, start[1] + d[1] * 350.0], 60.0))
+        .unwrap();
+
+    (world, creatures[.. creature_count].to_vec(), Range { start, direction })
+}
+
+
+/**
+ * Projectiles must stop at walls instead of flying through them.
+ */
+#[test]
+fn test_projectile_hits_wall() {
+    let mut world = HeadlessWorld::new(12345678901).unwrap();
+    let start = world.map.get_player_position();
+
+    // any direction with a wall nearby
+    let fire_at = around(start, 400.0)
+        .find(|target| world.map.walk_grid.find_impact(start, *target).is_some())
+        .unwrap();
+
+    let uid = fire_projectile(&mut world.map, "Fireball", fire_at, 24.0, &mut world.speaker);
+    let mut impact = false;
+
+    for _step in 0 .. 300 {
+        world.update(HEADLESS_DT);
+
+        match world.map.layers[MAP_OBJECT_LAYER].get(&uid) {
+            Some(projectile) => {
+                assert!(world.map.is_walkable(projectile.position));
+                if projectile.mob_type == MobType::MapObject {
+                    impact = true;
+                    break;
+                }
+            },
+            None => break,
+        }
+    }
+
+    assert!(impact);
+
+    // the impact animation removes it
+    world.run(1.0, HEADLESS_DT);
+    assert!(!world.map.layers[MAP_OBJECT_LAYER].contains_key(&uid));
+}
+
+
+fn enchant(attribute: Attribute, value: i32) -> Mod {
+    Mod {
+        attribute,
+        min_value: value,
+        max_value: value,
+        unit: Unit::Integer,
+        kind: ModKind::Echanted,
+        ilvl: 1,
+    }
+}
+
+
+#[test]
+fn test_projectile_damage() {
+    let (mut world, creatures, range) = shooting_range(1);
+    let start = range.start;
+    let target = range.at(150.0, 0.0);
+
+    // only the worn ring counts
+    world.player_inventory.clear();
+
+    let mut ring = world.map.item_factory.create_base("silver_ring").unwrap();
+    ring.mods.push(enchant(Attribute::SpellDamage, 7));
+    ring.mods.push(enchant(Attribute::ResFire, 50));
+    world.player_inventory.put_item(ring, Slot::LRing);
+
+    let mut wand = world.map.item_factory.create_base("wooden_wand").unwrap();
+    wand.activation = Activation::Fireball;
+    world.player_inventory.put_item(wand, Slot::RHand);
+
+    let mut wand = world.map.item_factory.create_base("wooden_wand").unwrap();
+    wand.mods.push(enchant(Attribute::SpellDamage, 100));
+    world.player_inventory.put_item(wand, Slot::Bag);
+
+    world.map.layers[MAP_OBJECT_LAYER].set_position(creatures[0], target);
+
+    // the fireball does 10 and the ring adds 7
+    let uid = fire_player_projectile(&mut world.map, &world.player_inventory, target, &mut world.speaker).unwrap();
+    assert_eq!(world.map.layers[MAP_OBJECT_LAYER].get(&uid).unwrap().damage, Some(Damage { amount: 17, element: Element::Fire, effect_chance: 20 }));
+
+    // only the hit counts here, burning is tested elsewhere
+    world.map.layers[MAP_OBJECT_LAYER].get_mut(&uid).unwrap().damage.as_mut().unwrap().effect_chance = 0;
+
+    world.run(1.5, HEADLESS_DT);
+
+    assert_eq!(hit_points(&world, creatures[0]), 983);
+
+    // creatures resist by their kind, not by any items
+    world.map.layers[MAP_OBJECT_LAYER].get_mut(&creatures[0]).unwrap().creature.as_mut().unwrap().resistances.fire = 50;
+
+    let uid = fire_player_projectile(&mut world.map, &world.player_inventory, target, &mut world.speaker).unwrap();
+    world.map.layers[MAP_OBJECT_LAYER].get_mut(&uid).unwrap().damage.as_mut().unwrap().effect_chance = 0;
+    world.run(1.5, HEADLESS_DT);
+
+    assert_eq!(hit_points(&world, creatures[0]), 975);
+
+    // creature fire is halved by the ring
+    let player_hp = world.map.layers[MAP_OBJECT_LAYER].get(&world.map.player_id).unwrap().creature.as_ref().unwrap().hit_points;
+
+    let mut shot = launch_projectile(target, start, 0.0, MobType::CreatureProjectile, &mut world.map.factory);
+    shot.velocity = [shot.velocity[0] * 200.0, shot.velocity[1] * 200.0];
+    shot.damage = Some(Damage { amount: 20, element: Element::Fire, effect_chance: 0 });
+    world.map.layers[MAP_OBJECT_LAYER].insert(shot.uid, shot);
+
+    world.run(1.5, HEADLESS_DT);
+
+    let player = world.map.layers[MAP_OBJECT_LAYER].get(&world.map.player_id).unwrap();
+    assert_eq!(player.creature.as_ref().unwrap().hit_points, player_hp - 10);
+}
+
+
+#[test]
+fn test_status_effects() {
+    let (mut world, creatures, range) = shooting_range(1);
+    let target = range.at(150.0, 0.0);
+
+    world.player_inventory.clear();
+
+    // together with the base chance of the projectiles every hit causes an effect
+    let mut ring = world.map.item_factory.create_base("silver_ring").unwrap();
+    ring.mods.push(enchant(Attribute::ChillChance, 50));
+    ring.mods.push(enchant(Attribute::IgniteChance, 80));
+    world.player_inventory.put_item(ring, Slot::LRing);
+
+    let mut wand = world.map.item_factory.create_base("wooden_wand").unwrap();
+    let wand_id = wand.id;
+    wand.activation = Activation::FrostBolt;
+    world.player_inventory.put_item(wand, Slot::RHand);
+
+    world.map.layers[MAP_OBJECT_LAYER].set_position(creatures[0], target);
+
+    fire_player_projectile(&mut world.map, &world.player_inventory, target, &mut world.speaker).unwrap();
+    world.run(1.5, HEADLESS_DT);
+
+    let creature = world.map.layers[MAP_OBJECT_LAYER].get(&creatures[0]).unwrap().creature.as_ref().unwrap();
+    assert!(creature.effects.has(StatusKind::Chilled));
+    assert!(creature.effects.speed_factor() < 1.0);
+    assert_eq!(creature.hit_points, 992);
+
+    // the fireball hits for 10 and burns for another 10 over four seconds
+    world.player_inventory.bag.get_mut(&wand_id).unwrap().activation = Activation::Fireball;
+
+    fire_player_projectile(&mut world.map, &world.player_inventory, target, &mut world.speaker).unwrap();
+    world.run(1.5, HEADLESS_DT);
+
+    let creature = world.map.layers[MAP_OBJECT_LAYER].get(&creatures[0]).unwrap().creature.as_ref().unwrap();
+    assert!(creature.effects.has(StatusKind::Burning));
+    assert!(creature.hit_points < 982);
+
+    world.run(5.0, HEADLESS_DT);
+
+    let creature = world.map.layers[MAP_OBJECT_LAYER].get(&creatures[0]).unwrap().creature.as_ref().unwrap();
+    assert!(creature.effects.is_empty());
+    assert_eq!(creature.hit_points, 972);
+}
+
+
+#[test]
+fn test_creatures_attack_and_return() {
+    let mut world = HeadlessWorld::new(12345678901).unwrap();
+
+    let creature = world.map.layers[MAP_OBJECT_LAYER].values()
+        .find(|mob| mob.mob_type == MobType::Creature)
+        .unwrap();
+    let creature_uid = creature.uid;
+    let home = creature.position;
+
+    // stand where the creature can see the player
+    let spot = around(home, 200.0)
+        .find(|spot| world.map.walk_grid.is_segment_walkable(home, *spot))
+        .unwrap();
+
+    let player_id = world.map.player_id;
+    world.map.layers[MAP_OBJECT_LAYER].set_position(player_id, spot);
+    world.map.layers[MAP_OBJECT_LAYER].get_mut(&player_id).unwrap().creature.as_mut().unwrap().hit_points = 100000;
+
+    // the attacks decide about projectile and damage
+    let attacks: Vec<CreatureAttack> = ["generated_creature_1", "generated_creature_2"].iter()
+        .flat_map(|name| world.map.creature_factory.create(name).attacks)
+        .collect();
+
+    let mut shots = 0;
+
+    for _i in 0 .. 500 {
+        world.update(HEADLESS_DT);
+
+        for mob in world.map.layers[MAP_OBJECT_LAYER].values() {
+            if mob.mob_type == MobType::CreatureProjectile {
+                let damage = mob.damage.unwrap();
+                assert!(attacks.iter().any(|attack| Some(&attack.projectile) == mob.projectile_key.as_ref() &&
+                                                    attack.damage == damage.amount && attack.element == damage.element));
+                shots += 1;
+            }
+        }
+    }
+
+    assert!(shots > 0);
+
+    let player = world.map.layers[MAP_OBJECT_LAYER].get(&player_id).unwrap();
+    assert!(player.creature.as_ref().unwrap().hit_points < 100000);
+
+    // far away the creatures lose interest and go home
+    world.map.layers[MAP_OBJECT_LAYER].set_position(player_id, [home[0] + 5000.0, home[1]]);
+    world.run(10.0, HEADLESS_DT);
+
+    let creature = world.map.layers[MAP_OBJECT_LAYER].get(&creature_uid).unwrap();
+    assert!(vec2_len(vec2_sub(creature.position, home)) < 300.0);
+}
+
+
+#[test]
+fn test_creature_attacks() {
+    let factory = CreatureFactory::new().unwrap();
+
+    let drone = factory.create("Targetting Drone");
+    assert_eq!(drone.attacks.len(), 2);
+    assert_eq!(drone.attacks[0], CreatureAttack {
+        projectile: "Iron shot".to_string(),
+        cooldown: 1.5,
+        range: 450.0,
+        damage: 5,
+        element: Element::Physical,
+    });
+    assert_eq!(drone.attacks[1].element, Element::Lightning);
+
+    assert!(factory.create("Player").attacks.is_empty());
+}
+
+
+fn test_projectile(world: &HeadlessWorld, key: &str, change: fn(&mut ProjectileConfig)) -> ProjectileConfig {
+    let mut config = world.map.projectile_builder.config("Iron shot").unwrap().clone();
+    config.speed = 200.0;
+    config.damage = 10;
+    config.element = Element::Physical;
+    config.effect_chance = 0;
+    config.lifetime = 3.0;
+    change(&mut config);
+
+    assert!(world.map.projectile_builder.config(key).is_none());
+    config
+}
+
+
+fn hit_points(world: &HeadlessWorld, uid: u64) -> i32 {
+    world.map.layers[MAP_OBJECT_LAYER].get(&uid).unwrap().creature.as_ref().unwrap().hit_points
+}
+
+
+/**
+ * Runs the world until the condition is met, but no longer than the given time.
+ *
+ * @return false if the condition was never met
+ */
+fn run_until(world: &mut HeadlessWorld, seconds: f32, condition: impl Fn(&HeadlessWorld) -> bool) -> bool {
+    let steps = (seconds / HEADLESS_DT).ceil() as usize;
+
+    for _step in 0 .. steps {
+        if condition(world) {
+            return true;
+        }
+
+        world.update(HEADLESS_DT);
+    }
+
+    condition(world)
+}
+
+
+#[test]
+fn test_projectile_behaviours() {
+    let (mut world, creatures, range) = shooting_range(3);
+
+    // passes through the first creature and hits the second one too
+    let config = test_projectile(&world, "Test pierce", |config| config.pierce = 1);
+    world.map.projectile_builder.add("Test pierce", config);
+
+    world.map.layers[MAP_OBJECT_LAYER].set_position(creatures[0], range.at(150.0, 0.0));
+    world.map.layers[MAP_OBJECT_LAYER].set_position(creatures[1], range.at(300.0, 0.0));
+    world.map.layers[MAP_OBJECT_LAYER].set_position(creatures[2], range.at(150.0, 2000.0));
+
+    fire_projectile(&mut world.map, "Test pierce", range.at(150.0, 0.0), 24.0, &mut world.speaker);
+    world.run(3.0, HEADLESS_DT);
+
+    assert_eq!(hit_points(&world, creatures[0]), 990);
+    assert_eq!(hit_points(&world, creatures[1]), 990);
+
+    // the neighbour of the target gets less than the target, far creatures nothing
+    let config = test_projectile(&world, "Test explosion", |config| { config.explosion_radius = 100.0; config.falloff = 50.0; });
+    world.map.projectile_builder.add("Test explosion", config);
+
+    world.map.layers[MAP_OBJECT_LAYER].set_position(creatures[1], range.at(150.0, 40.0));
+
+    fire_projectile(&mut world.map, "Test explosion", range.at(150.0, 0.0), 24.0, &mut world.speaker);
+    world.run(3.0, HEADLESS_DT);
+
+    assert_eq!(hit_points(&world, creatures[0]), 980);
+    assert!((981 ..= 983).contains(&hit_points(&world, creatures[1])));
+    assert_eq!(hit_points(&world, creatures[2]), 1000);
+
+    // two parts with half the damage fly on
+    let config = test_projectile(&world, "Test split", |config| config.split = 2);
+    world.map.projectile_builder.add("Test split", config);
+
+    world.map.layers[MAP_OBJECT_LAYER].set_position(creatures[1], range.at(150.0, 2000.0));
+
+    fire_projectile(&mut world.map, "Test split", range.at(150.0, 0.0), 24.0, &mut world.speaker);
+
+    assert!(run_until(&mut world, 3.0, |world| hit_points(world, creatures[0]) != 980));
+
+    let parts: Vec<_> = world.map.layers[MAP_OBJECT_LAYER].values()
+        .filter(|mob| mob.mob_type == MobType::PlayerProjectile && mob.projectile_key.as_deref() == Some("Test split"))
+        .collect();
+
+    assert_eq!(parts.len(), 2);
+    assert!(parts.iter().all(|part| part.damage.unwrap().amount == 5 && part.projectile_state.as_ref().unwrap().splits == 0));
+}
+
+
+#[test]
+fn test_homing_and_bouncing_projectiles() {
+    let (mut world, creatures, range) = shooting_range(1);
+
+    // fired to the side, but it finds the creature
+    let config = test_projectile(&world, "Test homing", |config| config.homing = 360.0);
+    world.map.projectile_builder.add("Test homing", config);
+
+    world.map.layers[MAP_OBJECT_LAYER].set_position(creatures[0], range.at(200.0, 60.0));
+
+    fire_projectile(&mut world.map, "Test homing", range.at(250.0, -60.0), 24.0, &mut world.speaker);
+    world.run(3.0, HEADLESS_DT);
+
+    assert_eq!(hit_points(&world, creatures[0]), 990);
+
+    // the first wall sends it back, the second one stops it
+    let config = test_projectile(&world, "Test bounce", |config| config.bounce = 1);
+    world.map.projectile_builder.add("Test bounce", config);
+
+    let wall = around(range.start, 300.0)
+        .find(|target| world.map.walk_grid.find_impact(range.start, *target).is_some())
+        .unwrap();
+
+    let uid = fire_projectile(&mut world.map, "Test bounce", wall, 24.0, &mut world.speaker);
+    let velocity = world.map.layers[MAP_OBJECT_LAYER].get(&uid).unwrap().velocity;
+
+    assert!(run_until(&mut world, 5.0, |world| world.map.layers[MAP_OBJECT_LAYER].get(&uid).unwrap().velocity != velocity));
+
+    let projectile = world.map.layers[MAP_OBJECT_LAYER].get(&uid).unwrap();
+    assert!(projectile.mob_type == MobType::PlayerProjectile);
+    assert_eq!(projectile.projectile_state.as_ref().unwrap().bounces_left, 0);
+    assert_eq!(vec2_len(projectile.velocity).round(), 200.0);
+}
+
+
+#[test]
+fn test_hit_boxes() {
+    let (mut world, creatures, range) = shooting_range(1);
+
+    let config = test_projectile(&world, "Test hit box", |_| {});
+    world.map.projectile_builder.add("Test hit box", config);
+
+    let set_hit_box = |world: &mut HeadlessWorld, radius: f32, height: f32| {
+        let creature = world.map.layers[MAP_OBJECT_LAYER].get_mut(&creatures[0]).unwrap().creature.as_mut().unwrap();
+        creature.hit_box = HitBox { radius, height };
+    };
+
+    // passes a small creature by, but not a big one
+    world.map.layers[MAP_OBJECT_LAYER].set_position(creatures[0], range.at(150.0, 30.0));
+
+    set_hit_box(&mut world, 10.0, 0.0);
+    fire_projectile(&mut world.map, "Test hit box", range.at(300.0, 0.0), 24.0, &mut world.speaker);
+    world.run(3.0, HEADLESS_DT);
+    assert_eq!(hit_points(&world, creatures[0]), 1000);
+
+    set_hit_box(&mut world, 40.0, 0.0);
+    fire_projectile(&mut world.map, "Test hit box", range.at(300.0, 0.0), 24.0, &mut world.speaker);
+    world.run(3.0, HEADLESS_DT);
+    assert_eq!(hit_points(&world, creatures[0]), 990);
+
+    // flies over low creatures
+    world.map.layers[MAP_OBJECT_LAYER].set_position(creatures[0], range.at(150.0, 0.0));
+
+    set_hit_box(&mut world, 40.0, 10.0);
+    fire_projectile(&mut world.map, "Test hit box", range.at(300.0, 0.0), 24.0, &mut world.speaker);
+    world.run(3.0, HEADLESS_DT);
+    assert_eq!(hit_points(&world, creatures[0]), 990);
+
+    // loot on the way doesn't stop the projectile
+    set_hit_box(&mut world, 40.0, 0.0);
+    let coins = world.map.item_factory.create_base("copper_coin").unwrap();
+    let loot = world.map.place_item(coins, range.at(75.0, 0.0));
+
+    fire_projectile(&mut world.map, "Test hit box", range.at(300.0, 0.0), 24.0, &mut world.speaker);
+    world.run(3.0, HEADLESS_DT);
+    assert_eq!(hit_points(&world, creatures[0]), 980);
+    assert!(world.map.layers[MAP_OBJECT_LAYER].get(&loot).is_some());
+
+    // creatures without a hit radius in creatures.csv get one from their image
+    let factory = CreatureFactory::new().unwrap();
+    let drone = factory.create("Targetting Drone");
+    assert!(drone.hit_box.radius > 0.0 && drone.hit_box.radius <= MAX_HIT_RADIUS);
+    assert_eq!(factory.create("Player").hit_box, HitBox { radius: 20.0, height: 0.0 });
+}
+
+
+#[test]
+fn test_combat_text() {
+    let (mut world, creatures, range) = shooting_range(1);
+
+    let config = test_projectile(&world, "Test text", |_| {});
+    world.map.projectile_builder.add("Test text", config);
+
+    world.map.layers[MAP_OBJECT_LAYER].set_position(creatures[0], range.at(150.0, 0.0));
+
+    let texts = |world: &HeadlessWorld| -> Vec<(String, [f32; 4])> {
+        world.map.combat_texts.iter().map(|text| (text.text.clone(), text.color)).collect()
+    };
+
+    fire_projectile(&mut world.map, "Test text", range.at(150.0, 0.0), 24.0, &mut world.speaker);
+
+    assert!(run_until(&mut world, 3.0, |world| hit_points(world, creatures[0]) != 1000));
+
+    assert_eq!(texts(&world), vec![("10".to_string(), element_color(Element::Physical))]);
+
+    // shocked creatures take more damage from hits
+    let creature = world.map.layers[MAP_OBJECT_LAYER].get_mut(&creatures[0]).unwrap().creature.as_mut().unwrap();
+    creature.effects.add(StatusEffect { kind: StatusKind::Shocked, time_left: 10.0, strength: 20.0 });
+
+    fire_projectile(&mut world.map, "Test text", range.at(150.0, 0.0), 24.0, &mut world.speaker);
+
+    assert!(run_until(&mut world, 3.0, |world| hit_points(world, creatures[0]) != 990));
+
+    assert_eq!(texts(&world).last().unwrap().0, "Shocked 12");
+
+    // the texts rise, fade and are gone after a while
+    let text = world.map.combat_texts[0].clone();
+    world.run(COMBAT_TEXT_TIME * 0.75, HEADLESS_DT);
+
+    assert!(world.map.combat_texts[0].rise() > text.rise());
+    assert!(world.map.combat_texts[0].faded_color()[3] < text.faded_color()[3]);
+
+    world.run(COMBAT_TEXT_TIME, HEADLESS_DT);
+    assert!(world.map.combat_texts.is_empty());
+
+    // a small hit can be resisted completely
+    world.player_inventory.clear();
+
+    let mut ring = world.map.item_factory.create_base("silver_ring").unwrap();
+    ring.mods.push(enchant(Attribute::ResFire, 75));
+    world.player_inventory.put_item(ring, Slot::LRing);
+
+    let mut shot = launch_projectile(range.at(150.0, 0.0), range.start, 0.0, MobType::CreatureProjectile, &mut world.map.factory);
+    shot.velocity = [shot.velocity[0] * 200.0, shot.velocity[1] * 200.0];
+    shot.damage = Some(Damage { amount: 1, element: Element::Fire, effect_chance: 0 });
+    world.map.layers[MAP_OBJECT_LAYER].insert(shot.uid, shot);
+
+    assert!(run_until(&mut world, 3.0, |world| !world.map.combat_texts.is_empty()));
+
+    assert_eq!(world.map.combat_texts[0].text, "Resisted");
+}
+
+
+fn character(world: &mut HeadlessWorld) -> &mut Character {
+    let player = world.map.layers[MAP_OBJECT_LAYER].get_mut(&world.map.player_id).unwrap();
+    player.creature.as_mut().unwrap().character.as_mut().unwrap()
+}
+
+
+#[test]
+fn test_experience_and_levels() {
+    let (mut world, creatures, range) = shooting_range(2);
+
+    let config = test_projectile(&world, "Test kill", |_| {});
+    world.map.projectile_builder.add("Test kill", config);
+
+    world.map.layers[MAP_OBJECT_LAYER].set_position(creatures[0], range.at(150.0, 0.0));
+    world.map.layers[MAP_OBJECT_LAYER].set_position(creatures[1], range.at(150.0, 2000.0));
+
+    let level = world.map.layers[MAP_OBJECT_LAYER].get(&creatures[0]).unwrap().creature.as_ref().unwrap().level;
+    world.map.layers[MAP_OBJECT_LAYER].get_mut(&creatures[0]).unwrap().creature.as_mut().unwrap().hit_points = 1;
+
+    fire_projectile(&mut world.map, "Test kill", range.at(150.0, 0.0), 24.0, &mut world.speaker);
+    world.run(2.0, HEADLESS_DT);
+
+    assert!(world.map.layers[MAP_OBJECT_LAYER].get(&creatures[0]).is_none());
+    assert_eq!(character(&mut world).experience, experience_for_kill(level, 1));
+    assert_eq!(character(&mut world).level, 1);
+
+    // the next kill is enough for level 2, it heals and gives more hit points
+    character(&mut world).experience = experience_for_level(2) - 1;
+
+    let player = world.map.layers[MAP_OBJECT_LAYER].get_mut(&world.map.player_id).unwrap();
+    let max_hit_points = player.creature.as_ref().unwrap().max_hit_points;
+    player.creature.as_mut().unwrap().hit_points = 1;
+
+    world.map.layers[MAP_OBJECT_LAYER].set_position(creatures[1], range.at(150.0, 0.0));
+    world.map.layers[MAP_OBJECT_LAYER].get_mut(&creatures[1]).unwrap().creature.as_mut().unwrap().hit_points = 1;
+
+    fire_projectile(&mut world.map, "Test kill", range.at(150.0, 0.0), 24.0, &mut world.speaker);
+
+    assert!(run_until(&mut world, 3.0, |world| !world.map.layers[MAP_OBJECT_LAYER].contains_key(&creatures[1])));
+
+    assert_eq!(character(&mut world).level, 2);
+    assert_eq!(character(&mut world).unspent_points, POINTS_PER_LEVEL);
+    assert!(world.map.combat_texts.iter().any(|text| text.text == "Level 2"));
+
+    let player = world.map.layers[MAP_OBJECT_LAYER].get(&world.map.player_id).unwrap().creature.as_ref().unwrap();
+    assert_eq!(player.max_hit_points, max_hit_points + HIT_POINTS_PER_LEVEL);
+    assert_eq!(player.hit_points, player.max_hit_points);
+
+    // the points go into the attributes
+    assert!(character(&mut world).spend_point(Attribute::Speed));
+    assert_eq!(character(&mut world).speed, 1);
+}
+
+
+#[test]
+fn test_attributes() {
+    let (mut world, _creatures, range) = shooting_range(0);
+
+    world.player_inventory.clear();
+
+    let mut wand = world.map.item_factory.create_base("wooden_wand").unwrap();
+    wand.activation = Activation::Fireball;
+    world.player_inventory.put_item(wand, Slot::RHand);
+
+    // agility adds damage
+    let target = range.at(100.0, 0.0);
+    let uid = fire_player_projectile(&mut world.map, &world.player_inventory, target, &mut world.speaker).unwrap();
+    assert_eq!(world.map.layers[MAP_OBJECT_LAYER].get(&uid).unwrap().damage.unwrap().amount, 10);
+
+    character(&mut world).agility = 10;
+
+    let uid = fire_player_projectile(&mut world.map, &world.player_inventory, target, &mut world.speaker).unwrap();
+    assert_eq!(world.map.layers[MAP_OBJECT_LAYER].get(&uid).unwrap().damage.unwrap().amount, 10 * (100 + 10 * AGILITY_DAMAGE) / 100);
+
+    // speed makes the player walk faster
+    let walkable = range.at(150.0, 0.0);
+
+    let speed = |world: &mut HeadlessWorld| {
+        assert!(walk_player_to(&mut world.map, walkable));
+        vec2_len(world.map.layers[MAP_OBJECT_LAYER].get(&world.map.player_id).unwrap().velocity)
+    };
+
+    let normal = speed(&mut world);
+    character(&mut world).speed = 10;
+    assert_eq!(speed(&mut world).round(), (normal * (1.0 + 10.0 * SPEED_BONUS as f32 / 100.0)).round());
+
+    // armor blocks physical hits
+    character(&mut world).armor = 10;
+    world.run(1.0, HEADLESS_DT);
+
+    let player_hp = world.map.layers[MAP_OBJECT_LAYER].get(&world.map.player_id).unwrap().creature.as_ref().unwrap().hit_points;
+    let player_position = world.map.get_player_position();
+
+    let mut shot = launch_projectile([player_position[0] + 150.0, player_position[1]], player_position, 0.0, MobType::CreatureProjectile, &mut world.map.factory);
+    shot.velocity = [shot.velocity[0] * 200.0, shot.velocity[1] * 200.0];
+    shot.damage = Some(Damage { amount: 20, element: Element::Physical, effect_chance: 0 });
+    world.map.layers[MAP_OBJECT_LAYER].insert(shot.uid, shot);
+
+    world.run(1.5, HEADLESS_DT);
+
+    let player = world.map.layers[MAP_OBJECT_LAYER].get(&world.map.player_id).unwrap();
+    assert_eq!(player.creature.as_ref().unwrap().hit_points, player_hp - 20 * (100 - 10 * ARMOR_DEFENCE) / 100);
+}
+
+
+#[test]
+fn test_weapon_activations() {
+    let mut world = HeadlessWorld::new(12345678901).unwrap();
+    let fire_at = [0.0, 0.0];
+
+    world.player_inventory.clear();
+    assert_eq!(fire_player_projectile(&mut world.map, &world.player_inventory, fire_at, &mut world.speaker), None);
+
+    let wand = world.map.item_factory.create_base("wooden_wand").unwrap();
+    let wand_id = wand.id;
+    world.player_inventory.put_item(wand, Slot::RHand);
+
+    // a wand without an enchantment can't attack
+    assert_eq!(fire_player_projectile(&mut world.map, &world.player_inventory, fire_at, &mut world.speaker), None);
+
+    let expected = [
+        (Activation::Fireball, "Fireball", Element::Fire),
+        (Activation::FrostBolt, "Frost bolt", Element::Cold),
+        (Activation::LightningStrike, "Lightning strike", Element::Lightning),
+    ];
+
+    for (activation, key, element) in expected {
+        world.player_inventory.bag.get_mut(&wand_id).unwrap().activation = activation;
+
+        let uid = fire_player_projectile(&mut world.map, &world.player_inventory, fire_at, &mut world.speaker).unwrap();
+        let projectile = world.map.layers[MAP_OBJECT_LAYER].get(&uid).unwrap();
+
+        assert_eq!(projectile.projectile_key.as_deref(), Some(key));
+        assert_eq!(projectile.damage.unwrap().element, element);
+    }
+}
+
+
+#[test]
+fn test_player_death_and_respawn() {
+    let (mut world, _creatures, range) = shooting_range(0);
+    world.map.layers[MAP_OBJECT_LAYER].get_mut(&world.map.player_id).unwrap().creature.as_mut().unwrap().hit_points = 5;
+
+    let mut shot = launch_projectile(range.at(100.0, 0.0), range.start, 0.0, MobType::CreatureProjectile, &mut world.map.factory);
+    shot.velocity = [shot.velocity[0] * 200.0, shot.velocity[1] * 200.0];
+    shot.damage = Some(Damage { amount: 20, element: Element::Physical, effect_chance: 0 });
+    world.map.layers[MAP_OBJECT_LAYER].insert(shot.uid, shot);
+
+    world.run(1.0, HEADLESS_DT);
+    assert!(world.map.is_player_dead());
+
+    // no more walking or shooting
+    let click = click_world(&mut world.map, &world.player_inventory, &mut world.speaker,
+                            &Button::Mouse(MouseButton::Left), &[700.0, 300.0], &[1200, 770]);
+    assert_eq!(click, WorldClick::Nothing);
+
+    // the starting money is 1000 copper, a quarter is lost
+    let loss = respawn_player(&mut world.map, &mut world.player_inventory, &mut world.rng);
+
+    assert_eq!(loss, 250);
+    assert_eq!(world.player_inventory.total_money(), 750);
+    assert!(!world.map.is_player_dead());
+    assert_eq!(world.map.get_player_position(), TOWN_ENTRANCE);
+    assert!(!world.map.shops.is_empty());
+}
+
+
+/**
+ * A recorded session must give the same result every time it is played.
+ */
+#[test]
+fn test_replay_session() {
+    let mut recording = Recording::new(4711, [1200, 770]);
+
+    // walk to the right, then shoot down
+    recording.record(10, InputEvent::MouseMove(MouseMoveEvent { mx: 800.0, my: 385.0 }));
+    recording.record(10, InputEvent::Button(click(MouseButton::Left, 800.0, 385.0)));
+    recording.record(200, InputEvent::MouseMove(MouseMoveEvent { mx: 600.0, my: 600.0 }));
+    recording.record(200, InputEvent::Button(click(MouseButton::Right, 600.0, 600.0)));
+    recording.record(300, InputEvent::MouseMove(MouseMoveEvent { mx: 600.0, my: 385.0 }));
+    recording.finish(450);
+
+    let text = recording.to_ron_string().unwrap();
+
+    let play = || {
+        let mut world = HeadlessWorld::new(4711).unwrap();
+        let start = world.map.get_player_position();
+
+        world.replay(Recording::parse(&text).unwrap());
+
+        (world, start)
+    };
+
+    let (first, start) = play();
+    let (second, _start) = play();
+
+    assert_eq!(first.tick, 450);
+    assert_eq!(first.map.get_player_position(), second.map.get_player_position());
+    assert_ne!(first.map.get_player_position(), start);
+    assert_eq!(first.player_inventory.entries.len(), second.player_inventory.entries.len());
+    assert_eq!(first.map.layers[MAP_OBJECT_LAYER].len(), second.map.layers[MAP_OBJECT_LAYER].len());
+}
+
+
+fn click(button: MouseButton, mx: f32, my: f32) -> ButtonEvent {
+    ButtonEvent {
+        args: ButtonArgs {
+            state: ButtonState::Release,
+            button: Button::Mouse(button),
+            scancode: None,
+        },
+        mx,
+        my,
+    }
+}